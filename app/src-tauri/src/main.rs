@@ -3,14 +3,12 @@
 
 use std::env;
 use std::fs;
-use std::path::Path;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use tauri::Manager;
-use tokio::sync::Mutex;
 use tracing::instrument;
 
 use jomai_core::CoreController;
@@ -39,6 +37,7 @@ async fn main() -> Result<()> {
 
     let (core, watch_state_rx) = jomai_core::Core::new(&app_dir).await?;
     let core_controller = core.controller();
+    let mut saved_search_match_rx = core_controller.subscribe_saved_search_matches();
 
     tokio::spawn(async move {
         match core.start().await {
@@ -65,18 +64,46 @@ async fn main() -> Result<()> {
                 tracing::debug!("menu event: {}", id);
             }
         })
-        .manage(Arc::new(Mutex::new(core_controller)))
+        .manage(core_controller)
         .invoke_handler(tauri::generate_handler![
             get_all_documents,
             search_documents,
+            autocomplete,
+            quick_open,
+            related_documents,
+            grep_documents,
+            analyze,
+            explain,
             get_watch_state,
             get_all_watches,
             add_watch,
             delete_watch,
+            detect_duplicates,
+            get_duplicate_groups,
+            get_saved_searches,
+            create_saved_search,
+            update_saved_search,
+            delete_saved_search,
+            record_opened_result,
+            get_recent_queries,
+            get_frequent_queries,
+            is_search_history_enabled,
+            set_search_history_enabled,
+            clear_search_history,
+            pin_document,
+            unpin_document,
+            set_document_labels,
+            set_document_note,
+            get_document_annotations,
+            render_document,
+            get_document_details,
             get_containing_folder,
             shutdown,
             get_path_recommendations,
         ])
+        .register_uri_scheme_protocol(jomai_core::LOCAL_IMAGE_SCHEME, |app, request| {
+            serve_local_image(&app.state::<CoreController>(), request.uri())
+        })
         .plugin(tauri_plugin_window_state::Builder::default().build())
         .setup(|app| {
             let app_handle = app.app_handle();
@@ -95,6 +122,25 @@ async fn main() -> Result<()> {
                 });
             }
 
+            {
+                let app_handle = app_handle.clone();
+                tokio::spawn(async move {
+                    loop {
+                        match saved_search_match_rx.recv().await {
+                            Ok(saved_search_match) => {
+                                tracing::debug!("saved search match: {:?}", saved_search_match);
+                                app_handle.emit_all("saved-search-match", saved_search_match).unwrap();
+                            }
+                            Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                                tracing::warn!("{} saved search matches were skipped", n);
+                            }
+                            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                        }
+                    }
+                    tracing::info!("saved_search_match_rx: channel closed");
+                });
+            }
+
             Ok(())
         })
         .build(tauri::generate_context!())
@@ -114,60 +160,268 @@ async fn main() -> Result<()> {
 async fn get_all_documents(
     offset: usize,
     limit: usize,
-    core_controller: tauri::State<'_, Arc<Mutex<CoreController>>>,
-) -> Result<jomai_core::SearchResults, String> {
+    sort: Option<Sort>,
+    order: Option<SortOrder>,
+    cursor: Option<String>,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<jomai_core::SearchResults, SearchError> {
     tracing::debug!("get_all_documents");
+    let sort = sort.unwrap_or(Sort::Date).with_order(order);
     core_controller
-        .lock()
+        .get_all_documents(sort, offset, limit, cursor)
         .await
-        .get_all_documents(offset, limit)
-        .map_err(|e| format!("failed to get documents: {}", e))
+        .map_err(|e| {
+            if matches!(
+                e.downcast_ref(),
+                Some(jomai_core::CursorError::Expired | jomai_core::CursorError::Mismatch)
+            ) {
+                return SearchError::CursorExpired;
+            }
+            SearchError::Other {
+                message: format!("failed to get documents: {}", e),
+            }
+        })
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 enum Sort {
     Relevance,
+    /// Same as `ModifiedAt`
     Date,
+    ModifiedAt,
+    CreatedAt,
+    Title,
+    Path,
+    WordCount,
+    ReadingTime,
 }
 
-impl Into<jomai_core::Sort> for Sort {
-    fn into(self) -> jomai_core::Sort {
+impl Sort {
+    /// Dates are sorted newest first, texts alphabetically and lengths shortest first unless the order is given.
+    fn with_order(self, order: Option<SortOrder>) -> jomai_core::Sort {
+        use jomai_core::SortOrder::{Asc, Desc};
+        let order = order.map(|x| x.into());
         match self {
             Sort::Relevance => jomai_core::Sort::Relevance,
-            Sort::Date => jomai_core::Sort::Date,
+            Sort::Date | Sort::ModifiedAt => jomai_core::Sort::ModifiedAt(order.unwrap_or(Desc)),
+            Sort::CreatedAt => jomai_core::Sort::CreatedAt(order.unwrap_or(Desc)),
+            Sort::Title => jomai_core::Sort::Title(order.unwrap_or(Asc)),
+            Sort::Path => jomai_core::Sort::Path(order.unwrap_or(Asc)),
+            Sort::WordCount => jomai_core::Sort::WordCount(order.unwrap_or(Asc)),
+            Sort::ReadingTime => jomai_core::Sort::ReadingTime(order.unwrap_or(Asc)),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl Into<jomai_core::SortOrder> for SortOrder {
+    fn into(self) -> jomai_core::SortOrder {
+        match self {
+            SortOrder::Asc => jomai_core::SortOrder::Asc,
+            SortOrder::Desc => jomai_core::SortOrder::Desc,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct SearchOptions {
+    fuzzy: bool,
+    prefix: bool,
+    fragment_length: Option<usize>,
+    max_fragments: Option<usize>,
+    query_mode: Option<jomai_core::QueryMode>,
+    cursor: Option<String>,
+}
+
+impl Into<jomai_core::SearchOptions> for SearchOptions {
+    fn into(self) -> jomai_core::SearchOptions {
+        jomai_core::SearchOptions {
+            fuzzy: self.fuzzy,
+            prefix: self.prefix,
+            fragment_length: self.fragment_length,
+            max_fragments: self.max_fragments,
+            query_mode: self.query_mode,
+            cursor: self.cursor,
         }
     }
 }
 
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum SearchError {
+    #[serde(rename = "syntax")]
+    Syntax { error: jomai_core::QuerySyntaxError },
+    /// A newer search from the same window has superseded the search
+    #[serde(rename = "cancelled")]
+    Cancelled,
+    /// The snapshot of the index for the cursor has been released or the cursor belongs to another search,
+    /// so the search has to start over
+    #[serde(rename = "cursor_expired")]
+    CursorExpired,
+    #[serde(rename = "other")]
+    Other { message: String },
+}
+
 #[tauri::command]
-#[instrument(skip(core_controller))]
+#[instrument(skip(window, core_controller))]
 async fn search_documents(
     query: &str,
     tags: Vec<String>,
     sort: Sort,
+    order: Option<SortOrder>,
+    options: Option<SearchOptions>,
     offset: usize,
     limit: usize,
-    core_controller: tauri::State<'_, Arc<Mutex<CoreController>>>,
-) -> Result<jomai_core::SearchResults, String> {
+    window: tauri::Window,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<jomai_core::SearchResults, SearchError> {
     tracing::debug!("search_documents");
     let tags: Vec<&str> = tags.iter().map(|s| s.as_str()).collect();
+    let options: jomai_core::SearchOptions = options.unwrap_or_default().into();
     core_controller
-        .lock()
+        .search_documents(
+            window.label(),
+            query,
+            &tags,
+            sort.with_order(order),
+            &options,
+            offset,
+            limit,
+        )
         .await
-        .search_documents(query, &tags, sort.into(), offset, limit)
-        .map_err(|e| format!("failed to search documents: {}", e))
+        .map_err(|e| {
+            if e.is::<jomai_core::SearchCancelled>() {
+                return SearchError::Cancelled;
+            }
+            if matches!(
+                e.downcast_ref(),
+                Some(jomai_core::CursorError::Expired | jomai_core::CursorError::Mismatch)
+            ) {
+                return SearchError::CursorExpired;
+            }
+            match e.downcast::<jomai_core::QuerySyntaxError>() {
+                Ok(e) => SearchError::Syntax { error: e },
+                Err(e) => SearchError::Other {
+                    message: format!("failed to search documents: {}", e),
+                },
+            }
+        })
 }
 
 #[tauri::command]
 #[instrument(skip(core_controller))]
-async fn get_all_watches(
-    core_controller: tauri::State<'_, Arc<Mutex<CoreController>>>,
-) -> Result<Vec<jomai_core::Watch>, String> {
-    tracing::debug!("get_all_watches");
+async fn quick_open(
+    query: &str,
+    limit: usize,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Vec<jomai_core::QuickOpenResult>, String> {
+    tracing::debug!("quick_open");
+    Ok(core_controller.quick_open(query, limit))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn autocomplete(
+    text: &str,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Vec<jomai_core::Completion>, String> {
+    tracing::debug!("autocomplete");
+    core_controller
+        .autocomplete(text)
+        .await
+        .map_err(|e| format!("failed to autocomplete: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn related_documents(
+    path: &str,
+    limit: usize,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Vec<jomai_core::RelatedDocument>, String> {
+    tracing::debug!("related_documents");
+    core_controller
+        .related_documents(path, limit)
+        .await
+        .map_err(|e| format!("failed to get related documents: {}", e))
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GrepOptions {
+    #[serde(default)]
+    mode: jomai_core::GrepMode,
+    #[serde(default)]
+    ignore_case: bool,
+    context_lines: Option<usize>,
+    path: Option<String>,
+    limit: Option<usize>,
+}
+
+impl Into<jomai_core::GrepOptions> for GrepOptions {
+    fn into(self) -> jomai_core::GrepOptions {
+        let default = jomai_core::GrepOptions::default();
+        jomai_core::GrepOptions {
+            mode: self.mode,
+            ignore_case: self.ignore_case,
+            context_lines: self.context_lines.unwrap_or(default.context_lines),
+            path: self.path.map(PathBuf::from),
+            limit: self.limit.unwrap_or(default.limit),
+        }
+    }
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn grep_documents(
+    pattern: &str,
+    options: GrepOptions,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<jomai_core::GrepResults, String> {
+    tracing::debug!("grep_documents");
+    core_controller
+        .grep(pattern, options.into())
+        .await
+        .map_err(|e| format!("failed to grep documents: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn analyze(
+    text: &str,
+    language_or_field: Option<&str>,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Vec<jomai_core::AnalyzerTokens>, String> {
+    tracing::debug!("analyze");
     core_controller
-        .lock()
+        .analyze(text, language_or_field)
         .await
+        .map_err(|e| format!("failed to analyze text: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn explain(query: &str, path: &str, core_controller: tauri::State<'_, CoreController>) -> Result<String, String> {
+    tracing::debug!("explain");
+    core_controller
+        .explain(query, path)
+        .await
+        .map_err(|e| format!("failed to explain: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn get_all_watches(core_controller: tauri::State<'_, CoreController>) -> Result<Vec<jomai_core::Watch>, String> {
+    tracing::debug!("get_all_watches");
+    core_controller
         .get_all_watches()
         .await
         .map_err(|e| format!("failed to get watches: {}", e))
@@ -175,13 +429,9 @@ async fn get_all_watches(
 
 #[tauri::command]
 #[instrument(skip(core_controller))]
-async fn get_watch_state(
-    core_controller: tauri::State<'_, Arc<Mutex<CoreController>>>,
-) -> Result<jomai_core::WatchState, String> {
+async fn get_watch_state(core_controller: tauri::State<'_, CoreController>) -> Result<jomai_core::WatchState, String> {
     tracing::debug!("get_watch_state");
     core_controller
-        .lock()
-        .await
         .get_watch_state()
         .map_err(|e| format!("failed to get watches: {:?}", e))
 }
@@ -201,37 +451,323 @@ pub enum AddWatchError {
 #[instrument(skip(core_controller))]
 async fn add_watch(
     path: &str,
-    core_controller: tauri::State<'_, Arc<Mutex<CoreController>>>,
+    core_controller: tauri::State<'_, CoreController>,
 ) -> Result<jomai_core::Watch, AddWatchError> {
     tracing::debug!("add_watch");
-    let watch = core_controller
-        .lock()
-        .await
-        .add_watch(path)
-        .await
-        .map_err(|e| match e {
-            jomai_core::AddWatchError::ParentChildRelationship => AddWatchError::ParentChildRelationship,
-            jomai_core::AddWatchError::WatchAlreadyExists => AddWatchError::WatchAlreadyExists,
-            jomai_core::AddWatchError::Other(e) => {
-                tracing::error!("failed to add watch: {}", e);
-                AddWatchError::Other
-            }
-        })?;
+    let watch = core_controller.add_watch(path).await.map_err(|e| match e {
+        jomai_core::AddWatchError::ParentChildRelationship => AddWatchError::ParentChildRelationship,
+        jomai_core::AddWatchError::WatchAlreadyExists => AddWatchError::WatchAlreadyExists,
+        jomai_core::AddWatchError::Other(e) => {
+            tracing::error!("failed to add watch: {}", e);
+            AddWatchError::Other
+        }
+    })?;
     Ok(watch)
 }
 
 #[tauri::command]
 #[instrument(skip(core_controller))]
-async fn delete_watch(path: &str, core_controller: tauri::State<'_, Arc<Mutex<CoreController>>>) -> Result<(), String> {
+async fn delete_watch(path: &str, core_controller: tauri::State<'_, CoreController>) -> Result<(), String> {
     tracing::debug!("delete_watch");
     core_controller
-        .lock()
-        .await
         .delete_watch(path)
         .await
         .map_err(|e| format!("failed to delete watch: {}", e))
 }
 
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn detect_duplicates(path: &str, core_controller: tauri::State<'_, CoreController>) -> Result<(), String> {
+    tracing::debug!("detect_duplicates");
+    core_controller
+        .detect_duplicates(path)
+        .await
+        .map_err(|e| format!("failed to detect duplicates: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn get_duplicate_groups(
+    path: &str,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Vec<jomai_core::DuplicateGroup>, String> {
+    tracing::debug!("get_duplicate_groups");
+    core_controller
+        .get_duplicate_groups(path)
+        .await
+        .map_err(|e| format!("failed to get duplicate groups: {}", e))
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SavedSearchInput {
+    name: String,
+    #[serde(default)]
+    query: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    filters: String,
+    /// Same format as `SavedSearch::sort`, e.g. `relevance` or `modified_at:desc`
+    sort: String,
+}
+
+impl SavedSearchInput {
+    fn into_new_saved_search(self) -> Result<jomai_core::NewSavedSearch> {
+        Ok(jomai_core::NewSavedSearch {
+            name: self.name,
+            query: self.query,
+            tags: self.tags,
+            filters: self.filters,
+            sort: self.sort.parse()?,
+        })
+    }
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn get_saved_searches(
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Vec<jomai_core::SavedSearch>, String> {
+    tracing::debug!("get_saved_searches");
+    core_controller
+        .get_saved_searches()
+        .await
+        .map_err(|e| format!("failed to get saved searches: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn create_saved_search(
+    saved_search: SavedSearchInput,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<jomai_core::SavedSearch, String> {
+    tracing::debug!("create_saved_search");
+    let saved_search = saved_search
+        .into_new_saved_search()
+        .map_err(|e| format!("invalid saved search: {}", e))?;
+    core_controller
+        .create_saved_search(saved_search)
+        .await
+        .map_err(|e| format!("failed to create saved search: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn update_saved_search(
+    id: i64,
+    saved_search: SavedSearchInput,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<jomai_core::SavedSearch, String> {
+    tracing::debug!("update_saved_search");
+    let saved_search = saved_search
+        .into_new_saved_search()
+        .map_err(|e| format!("invalid saved search: {}", e))?;
+    core_controller
+        .update_saved_search(id.into(), saved_search)
+        .await
+        .map_err(|e| format!("failed to update saved search: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn delete_saved_search(id: i64, core_controller: tauri::State<'_, CoreController>) -> Result<(), String> {
+    tracing::debug!("delete_saved_search");
+    core_controller
+        .delete_saved_search(id.into())
+        .await
+        .map_err(|e| format!("failed to delete saved search: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn record_opened_result(
+    query: &str,
+    path: &str,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<(), String> {
+    tracing::debug!("record_opened_result");
+    core_controller
+        .record_opened_result(query, path)
+        .await
+        .map_err(|e| format!("failed to record opened result: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn get_recent_queries(
+    limit: u32,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Vec<jomai_core::QueryHistoryEntry>, String> {
+    tracing::debug!("get_recent_queries");
+    core_controller
+        .get_recent_queries(limit)
+        .await
+        .map_err(|e| format!("failed to get recent queries: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn get_frequent_queries(
+    limit: u32,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Vec<jomai_core::QueryHistoryEntry>, String> {
+    tracing::debug!("get_frequent_queries");
+    core_controller
+        .get_frequent_queries(limit)
+        .await
+        .map_err(|e| format!("failed to get frequent queries: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn is_search_history_enabled(core_controller: tauri::State<'_, CoreController>) -> Result<bool, String> {
+    tracing::debug!("is_search_history_enabled");
+    core_controller
+        .is_search_history_enabled()
+        .await
+        .map_err(|e| format!("failed to get search history setting: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn set_search_history_enabled(
+    enabled: bool,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<(), String> {
+    tracing::debug!("set_search_history_enabled");
+    core_controller
+        .set_search_history_enabled(enabled)
+        .await
+        .map_err(|e| format!("failed to set search history setting: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn clear_search_history(core_controller: tauri::State<'_, CoreController>) -> Result<(), String> {
+    tracing::debug!("clear_search_history");
+    core_controller
+        .clear_search_history()
+        .await
+        .map_err(|e| format!("failed to clear search history: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn pin_document(
+    path: &str,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Option<jomai_core::DocumentAnnotation>, String> {
+    tracing::debug!("pin_document");
+    core_controller
+        .pin_document(path)
+        .await
+        .map_err(|e| format!("failed to pin document: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn unpin_document(
+    path: &str,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Option<jomai_core::DocumentAnnotation>, String> {
+    tracing::debug!("unpin_document");
+    core_controller
+        .unpin_document(path)
+        .await
+        .map_err(|e| format!("failed to unpin document: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn set_document_labels(
+    path: &str,
+    labels: Vec<String>,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Option<jomai_core::DocumentAnnotation>, String> {
+    tracing::debug!("set_document_labels");
+    core_controller
+        .set_document_labels(path, labels)
+        .await
+        .map_err(|e| format!("failed to set document labels: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn set_document_note(
+    path: &str,
+    note: Option<String>,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Option<jomai_core::DocumentAnnotation>, String> {
+    tracing::debug!("set_document_note");
+    core_controller
+        .set_document_note(path, note)
+        .await
+        .map_err(|e| format!("failed to set document note: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn get_document_annotations(
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Vec<jomai_core::DocumentAnnotation>, String> {
+    tracing::debug!("get_document_annotations");
+    core_controller
+        .get_document_annotations()
+        .await
+        .map_err(|e| format!("failed to get document annotations: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn render_document(
+    path: &str,
+    highlight_query: Option<String>,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<jomai_core::RenderedDocument, String> {
+    tracing::debug!("render_document");
+    core_controller
+        .render_document(path, highlight_query)
+        .await
+        .map_err(|e| format!("failed to render document: {}", e))
+}
+
+#[tauri::command]
+#[instrument(skip(core_controller))]
+async fn get_document_details(
+    path: &str,
+    core_controller: tauri::State<'_, CoreController>,
+) -> Result<Option<jomai_core::DocumentDetails>, String> {
+    tracing::debug!("get_document_details");
+    core_controller
+        .get_document_details(path)
+        .await
+        .map_err(|e| format!("failed to get document details: {}", e))
+}
+
+/// Serves the local images of the rendered documents. Files other than images in the watches are never served.
+fn serve_local_image(
+    core_controller: &CoreController,
+    url: &str,
+) -> Result<tauri::http::Response, Box<dyn std::error::Error>> {
+    let response = tauri::http::ResponseBuilder::new();
+    let (path, mime_type) = match core_controller.local_image(url) {
+        Some(x) => x,
+        None => return response.status(403).body(vec![]),
+    };
+    match fs::read(&path) {
+        // SVG のスクリプトは実行させない
+        Ok(bytes) => response
+            .mimetype(mime_type)
+            .header("Content-Security-Policy", "script-src 'none'")
+            .body(bytes),
+        Err(e) => {
+            tracing::debug!("failed to read image {}: {}", path.display(), e);
+            response.status(404).body(vec![])
+        }
+    }
+}
+
 #[tauri::command]
 #[instrument]
 fn get_containing_folder(path: &str) -> CommandResult<String> {
@@ -1,6 +1,27 @@
-import { SearchResults, Sort } from '../models/Document';
+import {
+  Completion,
+  DocumentAnnotation,
+  DocumentDetails,
+  GrepOptions,
+  GrepResults,
+  QueryHistoryEntry,
+  QuickOpenResult,
+  RelatedDocument,
+  RenderedDocument,
+  SavedSearch,
+  SavedSearchInput,
+  SearchOptions,
+  SearchResults,
+  Sort,
+  SortOrder,
+} from '../models/Document';
 import { invoke } from '@tauri-apps/api/tauri';
-import { PathRecommendation, Watch, WatchState } from '../models/Watch';
+import {
+  DuplicateGroup,
+  PathRecommendation,
+  Watch,
+  WatchState,
+} from '../models/Watch';
 
 export const getAllWatches = (): Promise<Watch[]> => {
   return invoke('get_all_watches');
@@ -22,21 +43,180 @@ export const deleteWatch = (path: string): Promise<void> => {
   return invoke('delete_watch', { path });
 };
 
+export const detectDuplicates = (path: string): Promise<void> => {
+  return invoke('detect_duplicates', { path });
+};
+
+export const getDuplicateGroups = (path: string): Promise<DuplicateGroup[]> => {
+  return invoke('get_duplicate_groups', { path });
+};
+
 export const getAllDocuments = (
   offset: number,
   limit: number,
+  sort: Sort = 'date',
+  order?: SortOrder,
+  cursor?: string,
 ): Promise<SearchResults> => {
-  return invoke('get_all_documents', { offset, limit });
+  return invoke('get_all_documents', { offset, limit, sort, order, cursor });
 };
 
+/** `start` and `end` are the character offsets in the query */
+export type QuerySyntaxError = {
+  kind:
+    | { type: 'unknown_field'; field: string }
+    | { type: 'missing_value'; field: string }
+    | { type: 'invalid_date'; value: string }
+    | { type: 'unclosed_quote' }
+    | { type: 'invalid_syntax'; message: string };
+  start: number;
+  end: number;
+};
+export type SearchError =
+  | { type: 'syntax'; error: QuerySyntaxError }
+  // 同じウィンドウからの新しい検索に置き換えられた
+  | { type: 'cancelled' }
+  // カーソルのスナップショットが破棄されたか、別の検索のカーソルだった。最初のページから検索し直す
+  | { type: 'cursor_expired' }
+  | { type: 'other'; message: string };
 export const searchDocuments = (
   query: string,
   tags: string[],
   sort: Sort,
   offset: number,
   limit: number,
+  options: SearchOptions = {},
+  order?: SortOrder,
 ): Promise<SearchResults> => {
-  return invoke('search_documents', { query, tags, sort, offset, limit });
+  return invoke('search_documents', {
+    query,
+    tags,
+    sort,
+    order,
+    options,
+    offset,
+    limit,
+  });
+};
+
+export const autocomplete = (text: string): Promise<Completion[]> => {
+  return invoke('autocomplete', { text });
+};
+
+export const quickOpen = (
+  query: string,
+  limit: number,
+): Promise<QuickOpenResult[]> => {
+  return invoke('quick_open', { query, limit });
+};
+
+export const grepDocuments = (
+  pattern: string,
+  options: GrepOptions = {},
+): Promise<GrepResults> => {
+  return invoke('grep_documents', { pattern, options });
+};
+
+export const getRelatedDocuments = (
+  path: string,
+  limit: number,
+): Promise<RelatedDocument[]> => {
+  return invoke('related_documents', { path, limit });
+};
+
+export const getSavedSearches = (): Promise<SavedSearch[]> => {
+  return invoke('get_saved_searches');
+};
+
+export const createSavedSearch = (
+  savedSearch: SavedSearchInput,
+): Promise<SavedSearch> => {
+  return invoke('create_saved_search', { savedSearch });
+};
+
+export const updateSavedSearch = (
+  id: number,
+  savedSearch: SavedSearchInput,
+): Promise<SavedSearch> => {
+  return invoke('update_saved_search', { id, savedSearch });
+};
+
+export const deleteSavedSearch = (id: number): Promise<void> => {
+  return invoke('delete_saved_search', { id });
+};
+
+export const recordOpenedResult = (
+  query: string,
+  path: string,
+): Promise<void> => {
+  return invoke('record_opened_result', { query, path });
+};
+
+export const getRecentQueries = (
+  limit: number,
+): Promise<QueryHistoryEntry[]> => {
+  return invoke('get_recent_queries', { limit });
+};
+
+export const getFrequentQueries = (
+  limit: number,
+): Promise<QueryHistoryEntry[]> => {
+  return invoke('get_frequent_queries', { limit });
+};
+
+export const isSearchHistoryEnabled = (): Promise<boolean> => {
+  return invoke('is_search_history_enabled');
+};
+
+export const setSearchHistoryEnabled = (enabled: boolean): Promise<void> => {
+  return invoke('set_search_history_enabled', { enabled });
+};
+
+export const clearSearchHistory = (): Promise<void> => {
+  return invoke('clear_search_history');
+};
+
+export const pinDocument = (
+  path: string,
+): Promise<DocumentAnnotation | null> => {
+  return invoke('pin_document', { path });
+};
+
+export const unpinDocument = (
+  path: string,
+): Promise<DocumentAnnotation | null> => {
+  return invoke('unpin_document', { path });
+};
+
+export const setDocumentLabels = (
+  path: string,
+  labels: string[],
+): Promise<DocumentAnnotation | null> => {
+  return invoke('set_document_labels', { path, labels });
+};
+
+export const setDocumentNote = (
+  path: string,
+  note: string | null,
+): Promise<DocumentAnnotation | null> => {
+  return invoke('set_document_note', { path, note });
+};
+
+export const getDocumentAnnotations = (): Promise<DocumentAnnotation[]> => {
+  return invoke('get_document_annotations');
+};
+
+export const renderDocument = (
+  path: string,
+  highlightQuery?: string,
+): Promise<RenderedDocument> => {
+  return invoke('render_document', { path, highlightQuery });
+};
+
+export const getDocumentDetails = (
+  path: string,
+): Promise<DocumentDetails | null> => {
+  return invoke('get_document_details', { path });
 };
 
 export const getContainingFolder = (path: string): Promise<string> => {
@@ -10,8 +10,13 @@ import React, {
 import { Column } from '../../components/Column';
 import { SearchForm } from './SearchForm';
 import { Divider } from '../../components/Divider';
-import { useInfiniteQuery } from 'react-query';
-import { getAllDocuments, searchDocuments } from '../../api/core';
+import { useInfiniteQuery, useQueryClient } from 'react-query';
+import {
+  getAllDocuments,
+  recordOpenedResult,
+  SearchError,
+  searchDocuments,
+} from '../../api/core';
 import { isNoModifiers, useKey, UseKeyCallback } from '../../helpers/useKey';
 import { SearchResultsPane } from './SearchResultsPane';
 import { SearchResults, Sort } from '../../models/Document';
@@ -72,11 +77,14 @@ export const DocumentPanel: React.FC = () => {
     const document = documents[selectionIndex];
     if (document != null) {
       openFile(document.path);
+      if (query.trim().length > 0) {
+        recordOpenedResult(query, document.path);
+      }
       return true;
     } else {
       return false;
     }
-  }, [selectionIndex, documents]);
+  }, [selectionIndex, documents, query]);
 
   const handleOpenContainingFolder = useCallback(() => {
     const document = documents[selectionIndex];
@@ -280,41 +288,44 @@ type QueryFn = (
   query: string,
   tags: string[],
   sort: Sort,
-  offset: number,
+  cursor?: string,
 ) => Promise<SearchResults>;
 const PAGE_SIZE = 10;
 const getQueryFn = (useGetAll: boolean): QueryFn => {
   if (useGetAll) {
-    return (query: string, tags: string[], sort: Sort, offset: number) =>
-      getAllDocuments(offset, PAGE_SIZE);
+    return (query: string, tags: string[], sort: Sort, cursor?: string) =>
+      getAllDocuments(0, PAGE_SIZE, 'date', undefined, cursor);
   } else {
-    return (query: string, tags: string[], sort: Sort, offset: number) =>
-      searchDocuments(query, tags, sort, offset, PAGE_SIZE);
+    return (query: string, tags: string[], sort: Sort, cursor?: string) =>
+      searchDocuments(query, tags, sort, 0, PAGE_SIZE, { cursor });
   }
 };
 
 const useDocumentQuery = (query: string, tags: string[], sort: Sort) => {
+  const queryClient = useQueryClient();
+  const queryKey = ['documents', query, tags, sort];
   return useInfiniteQuery(
-    ['documents', query, tags, sort],
-    async ({ pageParam: offset = 0 }) => {
+    queryKey,
+    async ({ pageParam: cursor }) => {
       const queryFn = getQueryFn(
         query.trim().length === 0 && tags.length === 0,
       );
-      return await queryFn(query, tags, sort, offset);
+      return await queryFn(query, tags, sort, cursor);
     },
     {
       keepPreviousData: true,
-      getNextPageParam: (lastPage, allPages) => {
-        const length = allPages.reduce(
-          (acc, page) => acc + page.documents.length,
-          0,
-        );
-        if (length < lastPage.count) {
-          return length;
-        } else {
-          return undefined;
+      // 中断された検索をやり直すと、新しい検索のほうを中断してしまう
+      // 期限切れのカーソルはやり直しても使えないので、最初のページから読み直す
+      retry: (failureCount, error: SearchError) =>
+        error?.type !== 'cancelled' &&
+        error?.type !== 'cursor_expired' &&
+        failureCount < 3,
+      onError: (error: SearchError) => {
+        if (error?.type === 'cursor_expired') {
+          queryClient.resetQueries(queryKey, { exact: true });
         }
       },
+      getNextPageParam: (lastPage) => lastPage.cursor ?? undefined,
     },
   );
 };
@@ -21,13 +21,109 @@ export const getTitle = (document: Document): string => {
   return title != null ? title : 'Untitled';
 };
 
+export type HighlightRange = {
+  start: number;
+  end: number;
+  charStart: number;
+  charEnd: number;
+};
+
+export type SnippetFragment = {
+  text: string;
+  line: number;
+  highlights: HighlightRange[];
+};
+
 export type SearchResultDocument = Document & {
   highlight: string | undefined;
+  snippets: SnippetFragment[];
+  highlightedTitle: string | undefined;
+  titleHighlights: HighlightRange[];
+  highlightedPath: string | undefined;
+  pathHighlights: HighlightRange[];
+  matchedFields: ('title' | 'tag' | 'path' | 'contents')[];
+  pinned: boolean;
+  labels: string[];
+  note: string | null;
+};
+
+export type RelatedDocument = SearchResultDocument & {
+  similarity: number;
+};
+
+export type GrepMode = 'phrase' | 'regex';
+
+export type GrepOptions = {
+  mode?: GrepMode;
+  ignoreCase?: boolean;
+  /** Number of lines before and after a match */
+  contextLines?: number;
+  /** Searches only the documents under this directory */
+  path?: string;
+  limit?: number;
+};
+
+export type GrepMatch = {
+  /** 1-based line number of the first line of `text` */
+  line: number;
+  text: string;
+  highlights: HighlightRange[];
+  before: string[];
+  after: string[];
+};
+
+export type GrepResultDocument = {
+  path: string;
+  title?: string;
+  watch_id: number;
+  matches: GrepMatch[];
+};
+
+export type GrepResults = {
+  documents: GrepResultDocument[];
+  truncated: boolean;
 };
 
 export type SearchResults = {
   count: number;
   documents: SearchResultDocument[];
+  suggestions: string[];
+  facets: Facets;
+  /** Pass this to get the next page. `null` on the last page */
+  cursor: string | null;
+};
+
+export type Facets = {
+  watches: { watch_id: number; count: number }[];
+  folders: { watch_id: number; folder: string; count: number }[];
+  // month は YYYY-MM
+  months: { month: string; count: number }[];
+};
+
+export type SearchOptions = {
+  fuzzy?: boolean;
+  prefix?: boolean;
+  fragmentLength?: number;
+  maxFragments?: number;
+  /** Defaults to `query_mode` in settings.toml, or 'smart' */
+  queryMode?: QueryMode;
+  /** `cursor` of the previous page. `offset` is ignored if this is given */
+  cursor?: string;
+};
+
+export type QueryMode = 'literal' | 'smart' | 'advanced';
+
+export type QuickOpenResult = {
+  path: string;
+  title: string;
+  score: number;
+  matchedField: 'title' | 'path';
+  positions: number[];
+};
+
+export type Completion = {
+  text: string;
+  kind: 'title' | 'tag' | 'term';
 };
 
 export const isSearchResultDocument = (
@@ -37,4 +133,112 @@ export const isSearchResultDocument = (
 };
 
 export const ALL_SORTS = ['relevance', 'date'];
-export type Sort = typeof ALL_SORTS[number];
+export type Sort =
+  | typeof ALL_SORTS[number]
+  | 'modified_at'
+  | 'created_at'
+  | 'title'
+  | 'path'
+  | 'word_count'
+  | 'reading_time';
+/** Defaults to 'desc' for dates and 'asc' for titles, paths and lengths */
+export type SortOrder = 'asc' | 'desc';
+
+/** e.g. 'relevance' or 'modified_at:desc' */
+export type SavedSearchSort =
+  | 'relevance'
+  | `${
+      | 'modified_at'
+      | 'created_at'
+      | 'title'
+      | 'path'
+      | 'word_count'
+      | 'reading_time'}:${SortOrder}`;
+
+export type SavedSearch = {
+  id: number;
+  name: string;
+  query: string;
+  tags: string[];
+  /** Field filters in the query syntax, e.g. 'modified:this-month' */
+  filters: string;
+  sort: SavedSearchSort;
+  createdAt: string;
+  updatedAt: string;
+};
+
+export type SavedSearchInput = {
+  name: string;
+  query?: string;
+  tags?: string[];
+  filters?: string;
+  sort: SavedSearchSort;
+};
+
+/** Payload of the 'saved-search-match' event */
+export type SavedSearchMatch = {
+  savedSearchId: number;
+  name: string;
+  path: string;
+  watchId: number;
+};
+
+export type QueryHistoryEntry = {
+  query: string;
+  count: number;
+  lastSearchedAt: string;
+};
+
+export type DocumentAnnotation = {
+  path: string;
+  pinned: boolean;
+  labels: string[];
+  note: string | null;
+  createdAt: string;
+  updatedAt: string;
+};
+
+export type OutlineHeading = {
+  level: number;
+  text: string;
+  /** `id` of the heading in `html` */
+  id: string;
+};
+
+export type RenderedDocument = {
+  path: string;
+  /** Sanitized HTML with the query terms in `<mark>` tags */
+  html: string;
+  outline: OutlineHeading[];
+};
+
+export type FrontmatterField = {
+  key: string;
+  /** Lists and tables are joined with ', ' */
+  value: string;
+};
+
+export type HeadingNode = {
+  level: number;
+  text: string;
+  /** 1-based line number in the file */
+  line: number;
+  children: HeadingNode[];
+};
+
+export type DocumentDetails = {
+  path: string;
+  title: string;
+  language: 'english' | 'japanese';
+  tags: string[];
+  frontmatter: FrontmatterField[];
+  outline: HeadingNode[];
+  wordCount: number;
+  charCount: number;
+  readingTimeMinutes: number;
+  internalLinkCount: number;
+  externalLinkCount: number;
+  fileSize: number;
+  createdAt: string;
+  modifiedAt: string;
+};
@@ -15,7 +15,7 @@ export type WatchJobReport = {
     done: number;
     total: number;
   };
-  jobType: 'scan_watch_path' | 'delete_watch' | 'sync_watch';
+  jobType: 'scan_watch_path' | 'delete_watch' | 'sync_watch' | 'detect_duplicates';
   status: 'running' | 'finished';
 };
 
@@ -24,6 +24,11 @@ export type WatchState = {
   jobReports: WatchJobReport[];
 };
 
+export type DuplicateGroup = {
+  similarity: number;
+  paths: string[];
+};
+
 export type PathRecommendation = {
   path: string;
   type: 'documents' | 'obsidian';
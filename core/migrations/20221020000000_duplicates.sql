@@ -0,0 +1,26 @@
+create table document_signatures
+(
+    document_id integer primary key not null references documents (id) on delete cascade,
+    signature   blob                not null,
+    -- signature is recomputed when the document is indexed again
+    indexed_at  datetime            not null
+);
+
+
+create table duplicate_groups
+(
+    id         integer primary key autoincrement not null,
+    watch_id   integer                           not null references watches (id) on delete cascade,
+    similarity real                              not null
+);
+
+create index duplicate_groups_watch_id_idx on duplicate_groups (watch_id);
+
+
+create table duplicate_group_documents
+(
+    group_id integer not null references duplicate_groups (id) on delete cascade,
+    path     text    not null
+);
+
+create index duplicate_group_documents_group_id_idx on duplicate_group_documents (group_id);
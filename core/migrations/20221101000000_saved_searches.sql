@@ -0,0 +1,27 @@
+create table saved_searches
+(
+    id         integer primary key autoincrement not null,
+    name       text                              not null unique,
+    query      text                              not null,
+    -- tag names separated by newlines
+    tags       text                              not null,
+    -- field filters in the query syntax, e.g. "modified:this-month"
+    filters    text                              not null,
+    -- e.g. "relevance", "modified_at:desc"
+    sort       text                              not null,
+    created_at datetime                          not null default current_timestamp,
+    updated_at datetime                          not null default current_timestamp
+);
+
+
+-- documents matching the saved searches, to notify only the documents which newly match
+create table saved_search_matches
+(
+    saved_search_id integer not null references saved_searches (id) on delete cascade,
+    watch_id        integer not null references watches (id) on delete cascade,
+    path            text    not null,
+    primary key (saved_search_id, path)
+);
+
+create index saved_search_matches_path_idx on saved_search_matches (path);
+create index saved_search_matches_watch_id_idx on saved_search_matches (watch_id);
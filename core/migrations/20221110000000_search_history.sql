@@ -0,0 +1,28 @@
+-- queries executed in the search box, normalized by `search_history::normalize_query`
+create table search_history
+(
+    query            text primary key not null,
+    count            integer          not null,
+    last_searched_at datetime         not null
+);
+
+create index search_history_last_searched_at_idx on search_history (last_searched_at);
+
+
+-- documents opened from the results of the queries, to rank them higher for the same query
+create table opened_results
+(
+    query          text     not null,
+    path           text     not null,
+    count          integer  not null,
+    last_opened_at datetime not null,
+    primary key (query, path)
+);
+
+
+-- preferences changed in the app, as opposed to settings.toml edited by the user
+create table preferences
+(
+    key   text primary key not null,
+    value text             not null
+);
@@ -0,0 +1,18 @@
+-- personal annotations of documents, kept apart from the files and the index.
+-- not referencing documents so that they survive re-indexing
+create table document_annotations
+(
+    path         text primary key not null,
+    pinned       boolean          not null default false,
+    -- labels separated by newlines
+    labels       text             not null default '',
+    note         text,
+    -- hash of the file contents, to find the annotation again when the file is renamed
+    content_hash integer,
+    -- set when the document is deleted, which may be the first half of a rename
+    deleted_at   datetime,
+    created_at   datetime         not null default current_timestamp,
+    updated_at   datetime         not null default current_timestamp
+);
+
+create index document_annotations_content_hash_idx on document_annotations (content_hash);
@@ -1,13 +1,28 @@
-use std::{fs, path::Path, str::FromStr};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::Result;
 use sqlx::{sqlite::SqlitePoolOptions, ConnectOptions, SqlitePool};
 use thiserror::Error;
-use tokio::sync::watch;
+use tokio::sync::{broadcast, watch};
 
 use crate::{
     documents,
-    documents::{document_gatekeeper::DocumentGatekeeper, IndexWriter},
+    documents::{
+        annotations,
+        annotations::{AnnotationCache, AnnotationSync},
+        document_gatekeeper::DocumentGatekeeper,
+        quick_open::{QuickOpen, QuickOpenResult, QuickOpenSync},
+        saved_searches,
+        saved_searches::{saved_search_repository, SavedSearchSync},
+        search_history, Cancellation, IndexWriter,
+    },
+    settings::Settings,
     watches,
     watches::{
         file_watcher::{FileWatcher, FileWatcherOps},
@@ -24,10 +39,17 @@ pub struct Core {
     file_watcher_ops: FileWatcherOps,
     watch_event_handler: watches::WatchEventHandler,
     watch_state_sync: watches::WatchStateSync,
+    quick_open_sync: QuickOpenSync,
+    saved_search_sync: SavedSearchSync,
+    annotation_sync: AnnotationSync,
+    annotation_cache: AnnotationCache,
     job_manager: JobManager,
     document_db_writer: documents::DocumentDbCommandProcessor,
     index_writer: IndexWriter,
     connection_pool: SqlitePool,
+    settings: Arc<Settings>,
+    running_searches: Arc<Mutex<HashMap<String, Cancellation>>>,
+    watch_lock: Arc<tokio::sync::Mutex<()>>,
 }
 
 impl Core {
@@ -40,6 +62,7 @@ impl Core {
             fs::create_dir_all(&db_dir)?;
         }
 
+        let settings = Arc::new(Settings::load(app_dir)?);
         let document_gatekeeper = DocumentGatekeeper::new(app_dir.to_path_buf());
 
         let mut connection_pool_options =
@@ -52,12 +75,40 @@ impl Core {
         sqlx::migrate!().run(&connection_pool).await?;
 
         let index_dir = app_dir.join("index");
+        if documents::Search::is_index_outdated(&index_dir)? {
+            tracing::info!("Index format has changed, all documents will be re-indexed");
+            // インデックスを削除する前に indexed_at をクリアしておき、途中で終了しても次回の同期で再登録されるようにする
+            let mut conn = connection_pool.acquire().await?;
+            documents::clear_all_indexed_at(&mut conn).await?;
+            drop(conn);
+            fs::remove_dir_all(&index_dir)?;
+        }
         fs::create_dir_all(&index_dir)?;
 
         let (document_db_writer, document_db_writer_command_tx) =
             documents::DocumentDbCommandProcessor::new(connection_pool.clone());
 
-        let (search, index_writer) = documents::Search::open_index(&index_dir)?;
+        let user_dictionary = documents::UserDictionary::load(app_dir)?;
+        let query_expansion = documents::QueryExpansion::load(app_dir)?;
+        let (search, index_writer) =
+            documents::Search::open_index(&index_dir, &user_dictionary, query_expansion, settings.ranking.clone())?;
+        if user_dictionary.has_changed(&index_dir)? {
+            tracing::info!("User dictionary has changed, Japanese documents will be re-indexed");
+            let mut conn = connection_pool.acquire().await?;
+            for path in search.find_paths_by_language(documents::Language::Japanese)? {
+                documents::clear_indexed_at(&path, &mut conn).await?;
+            }
+            drop(conn);
+            user_dictionary.save_snapshot(&index_dir)?;
+        }
+        let mut conn = connection_pool.acquire().await?;
+        for watch in watch_repository::find_all(&mut conn).await? {
+            search.set_watch_path(watch.id, &watch.path);
+            if let Some(priority) = settings.ranking.watch_priority(&watch.path) {
+                search.set_watch_priority(watch.id, priority);
+            }
+        }
+        drop(conn);
         let (index_writer, index_writer_command_tx) = IndexWriter::new(index_writer);
         let document_service = documents::document_service::DocumentService::new(
             document_db_writer_command_tx.clone(),
@@ -69,6 +120,14 @@ impl Core {
             FileWatcher::make_file_watcher(document_gatekeeper.clone())?;
 
         let watch_state_sync = watches::WatchStateSync::new(connection_pool.clone(), document_service.clone())?;
+        let quick_open_sync = QuickOpenSync::new(document_service.clone());
+        let annotation_cache = AnnotationCache::default();
+        let saved_search_sync = SavedSearchSync::new(
+            connection_pool.clone(),
+            annotation_cache.clone(),
+            document_service.clone(),
+        );
+        let annotation_sync = AnnotationSync::new(connection_pool.clone(), annotation_cache.clone(), &document_service);
 
         let scan_watch_job = jobs::scan_watch_job::ScanWatchJob::new(
             connection_pool.clone(),
@@ -87,6 +146,12 @@ impl Core {
             connection_pool.clone(),
             watch_state_sync.event_tx(),
         );
+        let detect_duplicates_job = jobs::detect_duplicates_job::DetectDuplicatesJob::new(
+            connection_pool.clone(),
+            document_service.clone(),
+            watch_state_sync.event_tx(),
+            settings.duplicates.threshold,
+        );
 
         let job_manager = JobManager::new(
             connection_pool.clone(),
@@ -94,6 +159,7 @@ impl Core {
             scan_watch_job.clone(),
             delete_watch_job.clone(),
             sync_watch_job.clone(),
+            detect_duplicates_job.clone(),
         );
         let watch_service = watches::WatchService::new(
             job_manager.controller(),
@@ -114,10 +180,17 @@ impl Core {
                 file_watcher_ops,
                 watch_event_handler,
                 watch_state_sync,
+                quick_open_sync,
+                saved_search_sync,
+                annotation_sync,
+                annotation_cache,
                 job_manager,
                 document_db_writer,
                 index_writer,
                 connection_pool,
+                settings,
+                running_searches: Arc::new(Mutex::new(HashMap::new())),
+                watch_lock: Arc::new(tokio::sync::Mutex::new(())),
             },
             state_rx,
         ))
@@ -137,6 +210,9 @@ impl Core {
         join_set.spawn(self.file_watcher.run());
         join_set.spawn(self.watch_event_handler.run());
         join_set.spawn(self.watch_state_sync.run());
+        join_set.spawn(self.quick_open_sync.run());
+        join_set.spawn(self.saved_search_sync.run());
+        join_set.spawn(self.annotation_sync.run());
         join_set.spawn(self.document_db_writer.run());
         join_set.spawn(self.index_writer.run());
 
@@ -161,6 +237,12 @@ impl Core {
             watch_service: self.watch_service.clone(),
             document_service: self.document_service.clone(),
             connection_pool: self.connection_pool.clone(),
+            settings: self.settings.clone(),
+            quick_open: self.quick_open_sync.quick_open(),
+            saved_search_match_tx: self.saved_search_sync.match_tx(),
+            annotation_cache: self.annotation_cache.clone(),
+            running_searches: self.running_searches.clone(),
+            watch_lock: self.watch_lock.clone(),
         }
     }
 }
@@ -179,6 +261,14 @@ pub struct CoreController {
     connection_pool: SqlitePool,
     watch_service: watches::WatchService,
     document_service: documents::DocumentService,
+    settings: Arc<Settings>,
+    quick_open: QuickOpen,
+    saved_search_match_tx: broadcast::Sender<documents::SavedSearchMatch>,
+    annotation_cache: AnnotationCache,
+    /// Cancellations of the searches in progress by client
+    running_searches: Arc<Mutex<HashMap<String, Cancellation>>>,
+    /// Serializes adding and deleting watches, since the overlap check and the insert are not atomic
+    watch_lock: Arc<tokio::sync::Mutex<()>>,
 }
 
 impl CoreController {
@@ -193,38 +283,358 @@ impl CoreController {
         self.watch_service.get_state()
     }
 
-    pub async fn add_watch<P: AsRef<Path>>(&mut self, path: P) -> std::result::Result<watches::Watch, AddWatchError> {
+    pub async fn add_watch<P: AsRef<Path>>(&self, path: P) -> std::result::Result<watches::Watch, AddWatchError> {
         let path = path.as_ref();
+        let _watch_lock = self.watch_lock.lock().await;
         let mut tx = self.connection_pool.begin().await.map_err(|e| anyhow::anyhow!(e))?;
-        let results = self.watch_service.add_watch(path, &mut tx).await.map_err(|e| match e {
-            watches::AddWatchError::ParentChildRelationship => AddWatchError::ParentChildRelationship,
-            watches::AddWatchError::WatchAlreadyExists => AddWatchError::WatchAlreadyExists,
-            watches::AddWatchError::Other(e) => AddWatchError::Other(e),
-        })?;
+        let results = self
+            .watch_service()
+            .add_watch(path, &mut tx)
+            .await
+            .map_err(|e| match e {
+                watches::AddWatchError::ParentChildRelationship => AddWatchError::ParentChildRelationship,
+                watches::AddWatchError::WatchAlreadyExists => AddWatchError::WatchAlreadyExists,
+                watches::AddWatchError::Other(e) => AddWatchError::Other(e),
+            })?;
         tx.commit().await.map_err(|e| anyhow::anyhow!(e))?;
+        self.document_service.set_watch_path(results.id, &results.path);
+        if let Some(priority) = self.settings.ranking.watch_priority(&results.path) {
+            self.document_service.set_watch_priority(results.id, priority);
+        }
         Ok(results)
     }
 
-    pub async fn delete_watch<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+    pub async fn delete_watch<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let path = path.as_ref();
+        let _watch_lock = self.watch_lock.lock().await;
         let mut tx = self.connection_pool.begin().await?;
-        self.watch_service.delete_watch(path, &mut tx).await?;
+        self.watch_service().delete_watch(path, &mut tx).await?;
         tx.commit().await?;
+        self.document_service.remove_watch(path);
         Ok(())
     }
 
-    pub fn get_all_documents(&self, offset: usize, limit: usize) -> Result<documents::SearchResults> {
-        self.document_service.get_all_documents(offset, limit)
+    /// Enqueues a job detecting near-duplicate documents in the watch.
+    /// The result is available via `get_duplicate_groups` after the job finishes.
+    pub async fn detect_duplicates<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut conn = self.connection_pool.acquire().await?;
+        self.watch_service().detect_duplicates(path, &mut *conn).await
+    }
+
+    /// Returns the near-duplicate documents in the watch found by the last detection.
+    pub async fn get_duplicate_groups<P: AsRef<Path>>(&self, path: P) -> Result<Vec<documents::DuplicateGroup>> {
+        let path = path.as_ref();
+        let mut conn = self.connection_pool.acquire().await?;
+        let watch = watch_repository::find_by_path(path, &mut *conn)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("watch not found: {}", path.display()))?;
+        documents::duplicates::find_duplicate_groups(watch.id, &mut *conn).await
+    }
+
+    /// Saves the search, recording the documents matching it so that only documents which newly match are notified.
+    pub async fn create_saved_search(&self, saved_search: documents::NewSavedSearch) -> Result<documents::SavedSearch> {
+        saved_search.validate()?;
+        let document_service = self.annotated_document_service().await?;
+        let matches =
+            saved_searches::find_matches(&document_service, &saved_search.full_query(), &saved_search.tags).await?;
+        let mut tx = self.connection_pool.begin().await?;
+        let created = saved_search_repository::insert(&saved_search, &mut tx).await?;
+        saved_search_repository::replace_matches(created.id, &matches, &mut tx).await?;
+        tx.commit().await?;
+        Ok(created)
+    }
+
+    pub async fn update_saved_search(
+        &self,
+        id: documents::SavedSearchId,
+        saved_search: documents::NewSavedSearch,
+    ) -> Result<documents::SavedSearch> {
+        saved_search.validate()?;
+        let document_service = self.annotated_document_service().await?;
+        let matches =
+            saved_searches::find_matches(&document_service, &saved_search.full_query(), &saved_search.tags).await?;
+        let mut tx = self.connection_pool.begin().await?;
+        let updated = saved_search_repository::update(id, &saved_search, &mut tx)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("saved search not found: {}", id.0))?;
+        saved_search_repository::replace_matches(id, &matches, &mut tx).await?;
+        tx.commit().await?;
+        Ok(updated)
+    }
+
+    pub async fn delete_saved_search(&self, id: documents::SavedSearchId) -> Result<()> {
+        let mut conn = self.connection_pool.acquire().await?;
+        saved_search_repository::delete(id, &mut *conn).await
+    }
+
+    pub async fn get_saved_searches(&self) -> Result<Vec<documents::SavedSearch>> {
+        let mut conn = self.connection_pool.acquire().await?;
+        saved_search_repository::find_all(&mut *conn).await
+    }
+
+    /// Receives documents which newly match a saved search after they are added or updated.
+    pub fn subscribe_saved_search_matches(&self) -> broadcast::Receiver<documents::SavedSearchMatch> {
+        self.saved_search_match_tx.subscribe()
+    }
+
+    /// Returns the document service with the current annotations, for `is:pinned` and `label:` filters.
+    async fn annotated_document_service(&self) -> Result<documents::DocumentService> {
+        let mut conn = self.connection_pool.acquire().await?;
+        let annotations = self.annotation_cache.get(&mut *conn).await?;
+        Ok(self.document_service.with_annotations(annotations))
+    }
+
+    /// `WatchService` needs `&mut self` to send commands, so each operation uses its own clone.
+    fn watch_service(&self) -> watches::WatchService {
+        self.watch_service.clone()
     }
 
-    pub fn search_documents(
+    /// `cursor` is `SearchResults::cursor` of the previous page, which takes precedence over the offset.
+    pub async fn get_all_documents(
         &self,
+        sort: documents::Sort,
+        offset: usize,
+        limit: usize,
+        cursor: Option<String>,
+    ) -> Result<documents::SearchResults> {
+        let document_service = self.annotated_document_service().await?;
+        tokio::task::spawn_blocking(move || document_service.get_all_documents(sort, offset, limit, cursor.as_deref()))
+            .await?
+    }
+
+    /// Searches on the blocking thread pool.
+    /// A newer search from the same client cancels the running one, which returns `SearchCancelled`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_documents(
+        &self,
+        client_id: &str,
         query: &str,
         tags: &[&str],
         sort: documents::Sort,
+        options: &documents::SearchOptions,
         offset: usize,
         limit: usize,
     ) -> Result<documents::SearchResults> {
-        self.document_service.search_documents(query, tags, sort, offset, limit)
+        let options = documents::SearchOptions {
+            query_mode: options.query_mode.or(Some(self.settings.search.query_mode)),
+            ..options.clone()
+        };
+        let cancellation = Cancellation::default();
+        let superseded = self
+            .running_searches
+            .lock()
+            .unwrap()
+            .insert(client_id.to_string(), cancellation.clone());
+        if let Some(superseded) = superseded {
+            superseded.cancel();
+        }
+
+        let normalized_query = search_history::normalize_query(query);
+        // 記録を止めていても、消去されるまではそれまでの履歴をランキングに使う
+        let opened_counts = if sort == documents::Sort::Relevance && !normalized_query.is_empty() {
+            let mut conn = self.connection_pool.acquire().await?;
+            search_history::find_opened_counts(&normalized_query, &mut *conn).await?
+        } else {
+            HashMap::new()
+        };
+        let document_service = self
+            .annotated_document_service()
+            .await?
+            .with_cancellation(cancellation.clone())
+            .with_opened_counts(opened_counts);
+        let is_first_page = offset == 0 && options.cursor.is_none();
+        let query_string = query.to_string();
+        let tags: Vec<String> = tags.iter().map(|x| x.to_string()).collect();
+        let results = tokio::task::spawn_blocking(move || {
+            let tags: Vec<&str> = tags.iter().map(|x| x.as_str()).collect();
+            document_service.search_documents(&query_string, &tags, sort, &options, offset, limit)
+        })
+        .await;
+
+        {
+            let mut running_searches = self.running_searches.lock().unwrap();
+            // 新しい検索に置き換わっていれば、そちらを残す
+            if running_searches
+                .get(client_id)
+                .map_or(false, |x| x.is_same(&cancellation))
+            {
+                running_searches.remove(client_id);
+            }
+        }
+        let results = results??;
+
+        // 続きのページの取得は同じ検索なので記録しない
+        if is_first_page {
+            let mut conn = self.connection_pool.acquire().await?;
+            if let Err(e) = search_history::record_query(query, chrono::Utc::now(), &mut *conn).await {
+                tracing::warn!("Failed to record the query: {}", e);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Records that the document was opened from the results of the query, to rank it higher for the same query.
+    pub async fn record_opened_result(&self, query: &str, path: &str) -> Result<()> {
+        let mut conn = self.connection_pool.acquire().await?;
+        search_history::record_opened_result(query, path, chrono::Utc::now(), &mut *conn).await
+    }
+
+    /// Returns the queries searched most recently first.
+    pub async fn get_recent_queries(&self, limit: u32) -> Result<Vec<documents::QueryHistoryEntry>> {
+        let mut conn = self.connection_pool.acquire().await?;
+        search_history::find_recent_queries(limit, &mut *conn).await
+    }
+
+    /// Returns the queries searched most often first.
+    pub async fn get_frequent_queries(&self, limit: u32) -> Result<Vec<documents::QueryHistoryEntry>> {
+        let mut conn = self.connection_pool.acquire().await?;
+        search_history::find_frequent_queries(limit, &mut *conn).await
+    }
+
+    pub async fn is_search_history_enabled(&self) -> Result<bool> {
+        let mut conn = self.connection_pool.acquire().await?;
+        search_history::is_enabled(&mut *conn).await
+    }
+
+    /// Turns the recording of the queries and the opened results on or off. Recorded ones are kept until cleared.
+    pub async fn set_search_history_enabled(&self, enabled: bool) -> Result<()> {
+        let mut conn = self.connection_pool.acquire().await?;
+        search_history::set_enabled(enabled, &mut *conn).await
+    }
+
+    /// Deletes all the recorded queries and opened results.
+    pub async fn clear_search_history(&self) -> Result<()> {
+        let mut conn = self.connection_pool.acquire().await?;
+        search_history::clear(&mut *conn).await
+    }
+
+    pub async fn pin_document(&self, path: &str) -> Result<Option<documents::DocumentAnnotation>> {
+        let mut conn = self.connection_pool.acquire().await?;
+        let annotation = annotations::update(path, |x| x.pinned = true, &mut *conn).await;
+        self.annotation_cache.invalidate();
+        annotation
+    }
+
+    pub async fn unpin_document(&self, path: &str) -> Result<Option<documents::DocumentAnnotation>> {
+        let mut conn = self.connection_pool.acquire().await?;
+        let annotation = annotations::update(path, |x| x.pinned = false, &mut *conn).await;
+        self.annotation_cache.invalidate();
+        annotation
+    }
+
+    /// Replaces the labels of the document. Returns `None` once the document has no annotation left.
+    pub async fn set_document_labels(
+        &self,
+        path: &str,
+        labels: Vec<String>,
+    ) -> Result<Option<documents::DocumentAnnotation>> {
+        let mut conn = self.connection_pool.acquire().await?;
+        let annotation = annotations::update(path, |x| x.labels = labels, &mut *conn).await;
+        self.annotation_cache.invalidate();
+        annotation
+    }
+
+    pub async fn set_document_note(
+        &self,
+        path: &str,
+        note: Option<String>,
+    ) -> Result<Option<documents::DocumentAnnotation>> {
+        let mut conn = self.connection_pool.acquire().await?;
+        let annotation = annotations::update(path, |x| x.note = note, &mut *conn).await;
+        self.annotation_cache.invalidate();
+        annotation
+    }
+
+    /// Returns the annotations of the existing documents, pinned ones first.
+    pub async fn get_document_annotations(&self) -> Result<Vec<documents::DocumentAnnotation>> {
+        let mut conn = self.connection_pool.acquire().await?;
+        annotations::find_all(&mut *conn).await
+    }
+
+    /// Renders the document as HTML for the preview, marking the terms of `highlight_query` if given.
+    pub async fn render_document(
+        &self,
+        path: &str,
+        highlight_query: Option<String>,
+    ) -> Result<documents::RenderedDocument> {
+        let document_service = self.document_service.clone();
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || document_service.render_document(&path, highlight_query.as_deref())).await?
+    }
+
+    /// Returns the outline, counts and metadata of the indexed document, or `None` if it's not indexed.
+    pub async fn get_document_details(&self, path: &str) -> Result<Option<documents::DocumentDetails>> {
+        let document_service = self.document_service.clone();
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || document_service.get_document_details(&path)).await?
+    }
+
+    /// Returns the path of the local image of the URL in a rendered document, with its MIME type.
+    /// Only the images in the watches are returned, so that the preview can't read the other files on the disk.
+    pub fn local_image(&self, url: &str) -> Option<(PathBuf, &'static str)> {
+        let (path, mime_type) = documents::preview::local_image(url)?;
+        self.document_service.is_in_watch(&path).then_some((path, mime_type))
+    }
+
+    /// Finds documents by characters of their titles or paths, e.g. `jmrdm` for `jomai/README.md`.
+    pub fn quick_open(&self, query: &str, limit: usize) -> Vec<QuickOpenResult> {
+        self.quick_open.find(query, limit)
+    }
+
+    /// Returns titles, tags and words completing the last term of the text being typed.
+    pub async fn autocomplete(&self, text: &str) -> Result<Vec<documents::Completion>> {
+        let document_service = self.document_service.clone();
+        let text = text.to_string();
+        tokio::task::spawn_blocking(move || document_service.autocomplete(&text)).await?
+    }
+
+    /// Returns documents similar to the document, excluding itself.
+    pub async fn related_documents<P: AsRef<Path>>(
+        &self,
+        path: P,
+        limit: usize,
+    ) -> Result<Vec<documents::RelatedDocument>> {
+        let document_service = self.annotated_document_service().await?;
+        let path = path.as_ref().to_path_buf();
+        let min_similarity = self.settings.related_documents.min_similarity;
+        tokio::task::spawn_blocking(move || document_service.related_documents(path, limit, min_similarity)).await?
+    }
+
+    /// Finds the exact phrase or the regular expression in the contents, with the matched lines.
+    pub async fn grep(&self, pattern: &str, options: documents::GrepOptions) -> Result<documents::GrepResults> {
+        let document_service = self.document_service.clone();
+        let pattern = pattern.to_string();
+        tokio::task::spawn_blocking(move || document_service.grep(&pattern, &options)).await?
+    }
+
+    /// Returns tokens produced by the analyzers for the text, to see why a search misses.
+    pub async fn analyze(&self, text: &str, language_or_field: Option<&str>) -> Result<Vec<documents::AnalyzerTokens>> {
+        let document_service = self.document_service.clone();
+        let text = text.to_string();
+        let language_or_field = language_or_field.map(|x| x.to_string());
+        tokio::task::spawn_blocking(move || document_service.analyze(&text, language_or_field.as_deref())).await?
+    }
+
+    /// Returns the score explanation of the document for the query as a JSON string.
+    pub async fn explain(&self, query: &str, path: &str) -> Result<String> {
+        // 検索と同じ設定・注釈・開いた回数でスコアを計算する
+        let options = documents::SearchOptions {
+            query_mode: Some(self.settings.search.query_mode),
+            ..documents::SearchOptions::default()
+        };
+        let normalized_query = search_history::normalize_query(query);
+        let opened_counts = if normalized_query.is_empty() {
+            HashMap::new()
+        } else {
+            let mut conn = self.connection_pool.acquire().await?;
+            search_history::find_opened_counts(&normalized_query, &mut *conn).await?
+        };
+        let document_service = self
+            .annotated_document_service()
+            .await?
+            .with_opened_counts(opened_counts);
+        let query = query.to_string();
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || document_service.explain(&query, &path, &options)).await?
     }
 }
@@ -1,21 +1,47 @@
 use std::path::PathBuf;
 
-pub use document_repository::document_db_command_processor::DocumentDbCommandProcessor;
+pub use annotations::DocumentAnnotation;
+pub use document_repository::{
+    clear_all_indexed_at, clear_indexed_at, document_db_command_processor::DocumentDbCommandProcessor,
+};
 pub use document_service::DocumentService;
+pub use duplicates::DuplicateGroup;
+pub use preview::{OutlineHeading, RenderedDocument};
+pub use saved_searches::{NewSavedSearch, SavedSearch, SavedSearchId, SavedSearchMatch};
 pub use search::{
+    analysis::AnalyzerTokens,
+    cancellation::{Cancellation, SearchCancelled},
+    details::{DocumentDetails, FrontmatterField, HeadingNode},
+    facets::{Facets, FolderFacet, MonthFacet, WatchFacet},
+    grep::{GrepMatch, GrepMode, GrepOptions, GrepResultDocument, GrepResults},
+    highlight::MatchedField,
     index_writer::{IndexWriter, IndexWriterCommand},
-    Search, SearchResults, Sort,
+    pagination::CursorError,
+    prefix::Completion,
+    query_expansion::QueryExpansion,
+    query_syntax::{QuerySyntaxError, QuerySyntaxErrorKind},
+    related::RelatedDocument,
+    snippet::{HighlightRange, SnippetFragment},
+    user_dictionary::UserDictionary,
+    Language, QueryMode, Search, SearchOptions, SearchResults, Sort, SortOrder,
 };
+pub use search_history::QueryHistoryEntry;
 use serde::Serialize;
 
 use crate::WatchId;
 
+pub mod annotations;
 pub mod document_gatekeeper;
 mod document_repository;
 pub mod document_service;
+pub mod duplicates;
 mod file;
 mod markdown;
+pub mod preview;
+pub mod quick_open;
+pub mod saved_searches;
 mod search;
+pub mod search_history;
 
 #[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq, Hash, sqlx::Type)]
 #[sqlx(transparent)]
@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::{SqliteConnection, SqlitePool};
+use tokio::sync::broadcast;
+use tracing::instrument;
+
+use crate::{
+    documents::{
+        document_service::{DocumentEvent, DocumentService},
+        duplicates::fnv1a,
+        Document,
+    },
+    path_string_normalization::PathStringNormalizationExt,
+};
+
+pub use annotation_repository::find_all;
+
+mod annotation_repository;
+
+/// Annotations of deleted documents are kept this long for the documents renamed or moved back
+const DELETED_ANNOTATION_RETENTION_DAYS: i64 = 30;
+
+/// Personal annotation of a document, stored in the database instead of the file
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentAnnotation {
+    pub path: String,
+    pub pinned: bool,
+    pub labels: Vec<String>,
+    pub note: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl DocumentAnnotation {
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels.iter().any(|x| x.to_lowercase() == label.to_lowercase())
+    }
+}
+
+/// Fields of an annotation changed by the user
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnnotationFields {
+    pub pinned: bool,
+    pub labels: Vec<String>,
+    pub note: Option<String>,
+}
+
+impl AnnotationFields {
+    fn is_empty(&self) -> bool {
+        !self.pinned && self.labels.is_empty() && self.note.is_none()
+    }
+}
+
+/// Changes the annotation of the document, deleting it once nothing is left.
+pub async fn update<P: AsRef<Path>, F: FnOnce(&mut AnnotationFields)>(
+    path: P,
+    f: F,
+    conn: &mut SqliteConnection,
+) -> Result<Option<DocumentAnnotation>> {
+    let path = path.as_ref();
+    let path_string = path.to_normalized_path_string();
+    let mut fields = annotation_repository::find_by_path(&path_string, &mut *conn)
+        .await?
+        .map(|x| AnnotationFields {
+            pinned: x.pinned,
+            labels: x.labels,
+            note: x.note,
+        })
+        .unwrap_or_default();
+    f(&mut fields);
+    fields.labels = normalize_labels(&fields.labels);
+    fields.note = fields.note.filter(|x| !x.trim().is_empty());
+
+    if fields.is_empty() {
+        annotation_repository::delete(&path_string, &mut *conn).await?;
+        return Ok(None);
+    }
+    let content_hash = content_hash(path).ok();
+    annotation_repository::upsert(&path_string, &fields, content_hash, &mut *conn).await?;
+    annotation_repository::find_by_path(&path_string, &mut *conn).await
+}
+
+/// Trims the labels and removes empty and duplicated ones, keeping the order.
+fn normalize_labels(labels: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = vec![];
+    for label in labels {
+        // 改行区切りで保存するので、ラベル中の改行は空白にする
+        let label = label.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !label.is_empty() && !normalized.iter().any(|x| x.to_lowercase() == label.to_lowercase()) {
+            normalized.push(label);
+        }
+    }
+    normalized
+}
+
+/// Keeps the annotations keyed by path in memory, so that searches do not read them from the database every time.
+///
+/// Must be invalidated whenever the annotations are changed.
+#[derive(Clone, Default)]
+pub struct AnnotationCache {
+    state: Arc<Mutex<AnnotationCacheState>>,
+}
+
+#[derive(Default)]
+struct AnnotationCacheState {
+    annotations: Option<Arc<HashMap<String, DocumentAnnotation>>>,
+    generation: u64,
+}
+
+impl AnnotationCache {
+    pub async fn get(&self, conn: &mut SqliteConnection) -> Result<Arc<HashMap<String, DocumentAnnotation>>> {
+        let generation = {
+            let state = self.state.lock().unwrap();
+            if let Some(annotations) = &state.annotations {
+                return Ok(annotations.clone());
+            }
+            state.generation
+        };
+        let annotations: Arc<HashMap<String, DocumentAnnotation>> = Arc::new(
+            find_all(&mut *conn)
+                .await?
+                .into_iter()
+                .map(|x| (x.path.clone(), x))
+                .collect(),
+        );
+        let mut state = self.state.lock().unwrap();
+        // 読み込み中に無効化されていれば、古い注釈かもしれないのでキャッシュしない
+        if state.generation == generation {
+            state.annotations = Some(annotations.clone());
+        }
+        Ok(annotations)
+    }
+
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.annotations = None;
+        state.generation += 1;
+    }
+}
+
+fn content_hash(path: &Path) -> Result<i64> {
+    Ok(fnv1a(&fs::read(path)?) as i64)
+}
+
+/// Keeps the annotations attached to the documents across deletions and renames.
+///
+/// A rename is notified as a deletion followed by an addition, so the annotation of a deleted document is kept
+/// and moved to a document added later with the same contents. The ones left unmoved are purged after
+/// [`DELETED_ANNOTATION_RETENTION_DAYS`].
+pub struct AnnotationSync {
+    connection_pool: SqlitePool,
+    annotation_cache: AnnotationCache,
+    document_event_rx: broadcast::Receiver<DocumentEvent>,
+}
+
+impl AnnotationSync {
+    pub fn new(
+        connection_pool: SqlitePool,
+        annotation_cache: AnnotationCache,
+        document_service: &DocumentService,
+    ) -> Self {
+        // 起動直後のイベントを取りこぼさないように、run より前に購読しておく
+        let document_event_rx = document_service.subscribe();
+        Self {
+            connection_pool,
+            annotation_cache,
+            document_event_rx,
+        }
+    }
+
+    #[instrument(name = "AnnotationSync::run", level = "info", skip(self))]
+    pub async fn run(mut self) -> Result<()> {
+        let mut conn = self.connection_pool.acquire().await?;
+        if let Err(e) = Self::purge_deleted(&mut *conn).await {
+            tracing::warn!("Failed to purge the annotations of deleted documents: {}", e);
+        }
+        drop(conn);
+        loop {
+            match self.document_event_rx.recv().await {
+                Ok(event) => {
+                    if let Err(e) = self.handle_event(event).await {
+                        tracing::warn!("Failed to update the annotations: {}", e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!(
+                        "{} document events were skipped, renamed documents may lose annotations",
+                        n
+                    );
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_event(&self, event: DocumentEvent) -> Result<()> {
+        use DocumentEvent::*;
+        let mut conn = self.connection_pool.acquire().await?;
+        match event {
+            DocumentAdded(document) => self.handle_document_added(document, &mut *conn).await,
+            DocumentUpdated(document) => {
+                let path_string = document.path.as_path().to_normalized_path_string();
+                if annotation_repository::exists(&path_string, &mut *conn).await? {
+                    let content_hash = content_hash(&document.path)?;
+                    annotation_repository::update_content_hash(&path_string, content_hash, &mut *conn).await?;
+                }
+                Ok(())
+            }
+            DocumentDeleted(document) => {
+                let path_string = document.path.as_path().to_normalized_path_string();
+                if annotation_repository::mark_deleted(&path_string, &mut *conn).await? {
+                    self.annotation_cache.invalidate();
+                }
+                Ok(())
+            }
+            // 監視フォルダを登録し直せば同じパスで元に戻るので、削除しない
+            WatchDocumentsDeleted(_) => Ok(()),
+            // スキャンで見つからなかった文書の注釈は、保持期間を過ぎていれば削除する
+            WatchDocumentsAdded(_) => Self::purge_deleted(&mut *conn).await,
+        }
+    }
+
+    async fn purge_deleted(conn: &mut SqliteConnection) -> Result<()> {
+        let deleted_before = chrono::Utc::now() - chrono::Duration::days(DELETED_ANNOTATION_RETENTION_DAYS);
+        let purged = annotation_repository::purge_deleted(deleted_before, &mut *conn).await?;
+        if purged > 0 {
+            tracing::info!("Purged {} annotations of documents deleted long ago", purged);
+        }
+        Ok(())
+    }
+
+    async fn handle_document_added(&self, document: Document, conn: &mut SqliteConnection) -> Result<()> {
+        let path_string = document.path.as_path().to_normalized_path_string();
+        if annotation_repository::exists(&path_string, &mut *conn).await? {
+            if annotation_repository::restore(&path_string, &mut *conn).await? {
+                self.annotation_cache.invalidate();
+            }
+            return Ok(());
+        }
+        if !annotation_repository::has_deleted(&mut *conn).await? {
+            return Ok(());
+        }
+        let content_hash = content_hash(&document.path)?;
+        if let Some(old_path) =
+            annotation_repository::move_deleted_by_content_hash(content_hash, &path_string, &mut *conn).await?
+        {
+            tracing::debug!("Moved the annotation of {} to {}", old_path, path_string);
+            self.annotation_cache.invalidate();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_labels() {
+        let labels = vec![
+            " to read ".to_string(),
+            "".to_string(),
+            "To Read".to_string(),
+            "work\nnotes".to_string(),
+        ];
+        assert_eq!(
+            normalize_labels(&labels),
+            vec!["to read".to_string(), "work notes".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_has_label() {
+        let annotation = DocumentAnnotation {
+            path: "/foo/a.md".to_string(),
+            pinned: false,
+            labels: vec!["To Read".to_string()],
+            note: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        assert!(annotation.has_label("to read"));
+        assert!(!annotation.has_label("done"));
+    }
+}
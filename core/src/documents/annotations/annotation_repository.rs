@@ -0,0 +1,338 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::SqliteConnection;
+
+use crate::documents::annotations::{AnnotationFields, DocumentAnnotation};
+
+pub(super) async fn upsert(
+    path: &str,
+    fields: &AnnotationFields,
+    content_hash: Option<i64>,
+    conn: &mut SqliteConnection,
+) -> Result<()> {
+    let labels = fields.labels.join("\n");
+    sqlx::query!(
+        r"
+insert into document_annotations (path, pinned, labels, note, content_hash)
+values ($1, $2, $3, $4, $5)
+on conflict (path) do update
+set pinned = excluded.pinned,
+labels = excluded.labels,
+note = excluded.note,
+content_hash = coalesce(excluded.content_hash, content_hash),
+deleted_at = null,
+updated_at = current_timestamp
+",
+        path,
+        fields.pinned,
+        labels,
+        fields.note,
+        content_hash,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+pub(super) async fn delete(path: &str, conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query!(
+        r"
+delete from document_annotations where path = $1
+",
+        path,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the annotation of the document unless the document has been deleted.
+pub(super) async fn find_by_path(path: &str, conn: &mut SqliteConnection) -> Result<Option<DocumentAnnotation>> {
+    let row = sqlx::query_as!(
+        AnnotationRow,
+        r"
+select path, pinned, labels, note, created_at, updated_at
+from document_annotations
+where path = $1 and deleted_at is null
+",
+        path,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(row.map(|x| x.into()))
+}
+
+/// Returns the annotations of the existing documents, pinned ones first and then recently updated ones first.
+pub async fn find_all(conn: &mut SqliteConnection) -> Result<Vec<DocumentAnnotation>> {
+    let rows = sqlx::query_as!(
+        AnnotationRow,
+        r"
+select path, pinned, labels, note, created_at, updated_at
+from document_annotations
+where deleted_at is null
+order by pinned desc, updated_at desc
+"
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|x| x.into()).collect())
+}
+
+/// Returns true if there is an annotation at the path, including the one of a deleted document.
+pub(super) async fn exists(path: &str, conn: &mut SqliteConnection) -> Result<bool> {
+    let row = sqlx::query!(
+        r"
+select path
+from document_annotations
+where path = $1
+",
+        path,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(row.is_some())
+}
+
+pub(super) async fn update_content_hash(path: &str, content_hash: i64, conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query!(
+        r"
+update document_annotations set content_hash = $1 where path = $2
+",
+        content_hash,
+        path,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+pub(super) async fn mark_deleted(path: &str, conn: &mut SqliteConnection) -> Result<bool> {
+    let result = sqlx::query!(
+        r"
+update document_annotations set deleted_at = current_timestamp where path = $1
+",
+        path,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub(super) async fn restore(path: &str, conn: &mut SqliteConnection) -> Result<bool> {
+    let result = sqlx::query!(
+        r"
+update document_annotations set deleted_at = null where path = $1 and deleted_at is not null
+",
+        path,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub(super) async fn has_deleted(conn: &mut SqliteConnection) -> Result<bool> {
+    let row = sqlx::query!(
+        r"
+select path
+from document_annotations
+where deleted_at is not null
+limit 1
+"
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(row.is_some())
+}
+
+/// Moves the annotation of the deleted document with the same contents to the path, most recently deleted one first.
+/// Returns the old path if moved.
+pub(super) async fn move_deleted_by_content_hash(
+    content_hash: i64,
+    path: &str,
+    conn: &mut SqliteConnection,
+) -> Result<Option<String>> {
+    let row = sqlx::query!(
+        r"
+select path
+from document_annotations
+where content_hash = $1 and deleted_at is not null
+order by deleted_at desc
+limit 1
+",
+        content_hash,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    let old_path = match row {
+        Some(x) => x.path,
+        None => return Ok(None),
+    };
+
+    sqlx::query!(
+        r"
+update document_annotations set path = $1, deleted_at = null where path = $2
+",
+        path,
+        old_path,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(Some(old_path))
+}
+
+/// Deletes the annotations of the documents deleted before the time. Returns the number of deleted annotations.
+pub(super) async fn purge_deleted(deleted_before: DateTime<Utc>, conn: &mut SqliteConnection) -> Result<u64> {
+    let result = sqlx::query!(
+        r"
+delete from document_annotations where deleted_at < datetime($1)
+",
+        deleted_before,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+struct AnnotationRow {
+    path: String,
+    pinned: bool,
+    labels: String,
+    note: Option<String>,
+    created_at: chrono::NaiveDateTime,
+    updated_at: chrono::NaiveDateTime,
+}
+
+impl From<AnnotationRow> for DocumentAnnotation {
+    fn from(row: AnnotationRow) -> Self {
+        Self {
+            path: row.path,
+            pinned: row.pinned,
+            labels: row.labels.lines().map(|x| x.to_string()).collect(),
+            note: row.note,
+            created_at: chrono::DateTime::from_utc(row.created_at, Utc),
+            updated_at: chrono::DateTime::from_utc(row.updated_at, Utc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+
+    use super::*;
+
+    async fn init() -> Result<(tempfile::TempDir, SqlitePool)> {
+        let db_dir = tempfile::tempdir()?;
+        let connection = SqlitePoolOptions::new()
+            .connect(format!("sqlite://{}/jomai.db?mode=rwc", db_dir.path().display()).as_str())
+            .await?;
+        sqlx::migrate!().run(&connection).await?;
+        Ok((db_dir, connection))
+    }
+
+    fn pinned() -> AnnotationFields {
+        AnnotationFields {
+            pinned: true,
+            labels: vec!["to read".to_string(), "work".to_string()],
+            note: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upsert_and_find() -> Result<()> {
+        let (_db_dir, pool) = init().await?;
+        let mut conn = pool.acquire().await?;
+
+        upsert("/foo/a.md", &pinned(), Some(42), &mut *conn).await?;
+        let annotation = find_by_path("/foo/a.md", &mut *conn).await?.unwrap();
+        assert!(annotation.pinned);
+        assert_eq!(annotation.labels, vec!["to read".to_string(), "work".to_string()]);
+
+        let fields = AnnotationFields {
+            pinned: false,
+            note: Some("memo".to_string()),
+            ..pinned()
+        };
+        upsert("/foo/a.md", &fields, None, &mut *conn).await?;
+        let annotation = find_by_path("/foo/a.md", &mut *conn).await?.unwrap();
+        assert!(!annotation.pinned);
+        assert_eq!(annotation.note, Some("memo".to_string()));
+
+        delete("/foo/a.md", &mut *conn).await?;
+        assert!(find_all(&mut *conn).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_move_deleted_by_content_hash() -> Result<()> {
+        let (_db_dir, pool) = init().await?;
+        let mut conn = pool.acquire().await?;
+
+        upsert("/foo/a.md", &pinned(), Some(42), &mut *conn).await?;
+        assert!(!has_deleted(&mut *conn).await?);
+        mark_deleted("/foo/a.md", &mut *conn).await?;
+        assert!(has_deleted(&mut *conn).await?);
+        assert!(find_all(&mut *conn).await?.is_empty());
+        assert!(exists("/foo/a.md", &mut *conn).await?);
+
+        assert_eq!(move_deleted_by_content_hash(7, "/foo/b.md", &mut *conn).await?, None);
+        assert_eq!(
+            move_deleted_by_content_hash(42, "/foo/b.md", &mut *conn).await?,
+            Some("/foo/a.md".to_string())
+        );
+        assert!(!exists("/foo/a.md", &mut *conn).await?);
+        assert!(find_by_path("/foo/b.md", &mut *conn).await?.unwrap().pinned);
+        assert!(!has_deleted(&mut *conn).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mark_deleted_and_restore() -> Result<()> {
+        let (_db_dir, pool) = init().await?;
+        let mut conn = pool.acquire().await?;
+
+        assert!(!mark_deleted("/foo/a.md", &mut *conn).await?);
+        upsert("/foo/a.md", &pinned(), Some(42), &mut *conn).await?;
+        assert!(!restore("/foo/a.md", &mut *conn).await?);
+        assert!(mark_deleted("/foo/a.md", &mut *conn).await?);
+        assert!(find_all(&mut *conn).await?.is_empty());
+        assert!(restore("/foo/a.md", &mut *conn).await?);
+        assert_eq!(find_all(&mut *conn).await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_purge_deleted() -> Result<()> {
+        let (_db_dir, pool) = init().await?;
+        let mut conn = pool.acquire().await?;
+
+        upsert("/foo/a.md", &pinned(), Some(42), &mut *conn).await?;
+        upsert("/foo/b.md", &pinned(), Some(7), &mut *conn).await?;
+        mark_deleted("/foo/a.md", &mut *conn).await?;
+
+        assert_eq!(
+            purge_deleted(Utc::now() - chrono::Duration::days(1), &mut *conn).await?,
+            0
+        );
+        assert!(exists("/foo/a.md", &mut *conn).await?);
+        assert_eq!(
+            purge_deleted(Utc::now() + chrono::Duration::days(1), &mut *conn).await?,
+            1
+        );
+        assert!(!exists("/foo/a.md", &mut *conn).await?);
+        assert!(exists("/foo/b.md", &mut *conn).await?);
+        Ok(())
+    }
+}
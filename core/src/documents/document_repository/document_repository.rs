@@ -10,6 +10,7 @@ use tracing::instrument;
 
 use crate::{
     documents::{document_repository::document_db_command_processor::Command, Document},
+    path_string_normalization::PathStringNormalizationExt,
     WatchId,
 };
 
@@ -124,6 +125,39 @@ pub async fn find_by_path<P: AsRef<Path> + Debug>(
     }
 }
 
+/// Marks the document as not indexed so that the next sync re-indexes it.
+#[instrument(skip(conn))]
+pub async fn clear_indexed_at(path: &Path, conn: &mut SqliteConnection) -> Result<()> {
+    let path_string = path.to_normalized_path_string();
+    sqlx::query!(
+        r#"
+update documents
+set indexed_at = null
+where path = $1
+"#,
+        path_string,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks all documents as not indexed so that the next sync re-indexes them.
+#[instrument(skip(conn))]
+pub async fn clear_all_indexed_at(conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query!(
+        r#"
+update documents
+set indexed_at = null
+"#
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
 #[instrument(skip(conn))]
 pub fn find_by_watch_id<'a>(
     watch_id: WatchId,
@@ -1,4 +1,10 @@
-use std::{fmt::Debug, path::Path};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::{anyhow, Result};
 use futures::{
@@ -12,10 +18,21 @@ use tokio::sync::oneshot;
 
 use crate::{
     documents::{
+        annotations::DocumentAnnotation,
         document_gatekeeper::DocumentGatekeeper,
         document_repository,
         document_repository::RepositoryError,
-        search::{Search, SearchResults, Sort},
+        preview,
+        preview::RenderedDocument,
+        search::{
+            analysis::AnalyzerTokens,
+            cancellation::Cancellation,
+            details::DocumentDetails,
+            grep::{GrepOptions, GrepResults},
+            prefix::Completion,
+            related::RelatedDocument,
+            Search, SearchOptions, SearchResults, Sort,
+        },
         Document, IndexWriterCommand,
     },
     watches::watch_repository,
@@ -26,8 +43,13 @@ use crate::{
 pub enum DocumentEvent {
     DocumentAdded(Document),
     DocumentUpdated(Document),
-    /// a document was deleted. note that there is no event for multiple documents being deleted
+    /// a document was deleted. note that there is no event for each document deleted with a watch
     DocumentDeleted(Document),
+    /// all documents of a watch were deleted
+    WatchDocumentsDeleted(WatchId),
+    /// the initial scan of a watch finished and its documents were committed.
+    /// each document of the scan is also notified with `DocumentAdded` while the watch is being added
+    WatchDocumentsAdded(WatchId),
 }
 
 #[derive(Clone)]
@@ -163,7 +185,19 @@ impl DocumentService {
         self.index_writer_command_tx
             .send(IndexWriterCommand::DeleteByWatchId { watch_id, result_tx })
             .await?;
-        result_rx.await.map_err(|e| anyhow!(e))?
+        result_rx.await.map_err(|e| anyhow!(e))??;
+        self.document_event_tx
+            .send(DocumentEvent::WatchDocumentsDeleted(watch_id))
+            .map_err(|e| anyhow!(e))?;
+        Ok(())
+    }
+
+    /// Notifies that the documents found by the initial scan of the watch were committed.
+    pub fn notify_watch_documents_added(&self, watch_id: WatchId) -> Result<()> {
+        self.document_event_tx
+            .send(DocumentEvent::WatchDocumentsAdded(watch_id))
+            .map_err(|e| anyhow!(e))?;
+        Ok(())
     }
 
     pub async fn update_document_with_watch_id<P: AsRef<Path>>(
@@ -209,8 +243,14 @@ impl DocumentService {
         Ok(())
     }
 
-    pub fn get_all_documents(&self, offset: usize, limit: usize) -> Result<SearchResults> {
-        self.search.get_all_documents(offset, limit)
+    pub fn get_all_documents(
+        &self,
+        sort: Sort,
+        offset: usize,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<SearchResults> {
+        self.search.get_all_documents(sort, offset, limit, cursor)
     }
 
     pub async fn find_document_by_path<P: AsRef<Path> + Debug>(&self, path: P) -> Result<Option<Document>> {
@@ -227,25 +267,131 @@ impl DocumentService {
         document_repository::find_by_watch_id(watch_id, conn).map_err(|e| anyhow!(e))
     }
 
+    /// Returns a service whose searches stop when the cancellation is requested.
+    pub fn with_cancellation(&self, cancellation: Cancellation) -> Self {
+        DocumentService {
+            search: self.search.with_cancellation(cancellation),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a service whose relevance ranking boosts the documents often opened for the query.
+    pub fn with_opened_counts(&self, opened_counts: HashMap<String, u32>) -> Self {
+        DocumentService {
+            search: self.search.with_opened_counts(opened_counts),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a service which filters by and returns the annotations of the documents.
+    pub fn with_annotations(&self, annotations: Arc<HashMap<String, DocumentAnnotation>>) -> Self {
+        DocumentService {
+            search: self.search.with_annotations(annotations),
+            ..self.clone()
+        }
+    }
+
     pub fn search_documents(
         &self,
         query: &str,
         tags: &[&str],
         sort: Sort,
+        options: &SearchOptions,
         offset: usize,
         limit: usize,
     ) -> Result<SearchResults> {
         if tags.is_empty() {
-            self.search.search_document(query, sort, offset, limit)
+            self.search.search_document(query, sort, options, offset, limit)
         } else {
-            self.search.search_document_with_tags(query, tags, sort, offset, limit)
+            self.search
+                .search_document_with_tags(query, tags, sort, options, offset, limit)
         }
     }
 
+    pub fn autocomplete(&self, text: &str) -> Result<Vec<Completion>> {
+        self.search.autocomplete(text)
+    }
+
+    pub fn related_documents<P: AsRef<Path>>(
+        &self,
+        path: P,
+        limit: usize,
+        min_similarity: f32,
+    ) -> Result<Vec<RelatedDocument>> {
+        self.search.related_documents(path, limit, min_similarity)
+    }
+
+    pub fn grep(&self, pattern: &str, options: &GrepOptions) -> Result<GrepResults> {
+        self.search.grep(pattern, options)
+    }
+
+    pub fn analyze(&self, text: &str, language_or_field: Option<&str>) -> Result<Vec<AnalyzerTokens>> {
+        self.search.analyze(text, language_or_field)
+    }
+
+    pub fn explain(&self, query: &str, path: &str, options: &SearchOptions) -> Result<String> {
+        self.search.explain(query, path, options)
+    }
+
+    /// Renders the indexed document for the preview, marking the terms of the query if any.
+    pub fn render_document<P: AsRef<Path>>(&self, path: P, highlight_query: Option<&str>) -> Result<RenderedDocument> {
+        let path = path.as_ref();
+        // 索引されていないファイルは読まない
+        if self.search.count_documents_by_path(path)? == 0 {
+            return Err(anyhow!("document not found: {}", path.display()));
+        }
+        let contents = fs::read_to_string(path)?;
+        match highlight_query.filter(|x| !x.trim().is_empty()) {
+            Some(query) => preview::render(path, &contents, self.search.contents_highlighter(query, path)?),
+            None => preview::render(path, &contents, |_| Ok(vec![])),
+        }
+    }
+
+    pub fn get_document_details<P: AsRef<Path>>(&self, path: P) -> Result<Option<DocumentDetails>> {
+        self.search.get_document_details(path)
+    }
+
+    pub fn get_contents<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>> {
+        self.search.get_contents(path)
+    }
+
+    pub fn set_watch_priority(&self, watch_id: WatchId, priority: f32) {
+        self.search.set_watch_priority(watch_id, priority);
+    }
+
+    pub fn set_watch_path<P: AsRef<Path>>(&self, watch_id: WatchId, path: P) {
+        self.search.set_watch_path(watch_id, path);
+    }
+
+    pub fn remove_watch<P: AsRef<Path>>(&self, path: P) {
+        self.search.remove_watch(path);
+    }
+
+    pub fn is_in_watch<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.search.is_in_watch(path)
+    }
+
+    pub fn find_all_titles(&self) -> Result<Vec<(PathBuf, String, WatchId)>> {
+        self.search.find_all_titles()
+    }
+
     pub fn count_documents_under_path<P: AsRef<Path>>(&self, path: P) -> Result<u32> {
         self.search.count_documents_under_path(path)
     }
 
+    pub fn find_matching_paths(&self, query: &str, tags: &[&str]) -> Result<Vec<(PathBuf, WatchId)>> {
+        self.search.find_matching_paths(query, tags)
+    }
+
+    pub fn match_document<P: AsRef<Path>>(
+        &self,
+        path: P,
+        watch_id: WatchId,
+        queries: &[(&str, Vec<&str>)],
+    ) -> Result<Vec<bool>> {
+        self.search.match_document(path, watch_id, queries)
+    }
+
     async fn add_document_to_search_engine<P: AsRef<Path>>(&mut self, path: P, watch_id: WatchId) -> Result<()> {
         let document = self.search.make_document(path, watch_id)?;
         let (result_tx, result_rx) = oneshot::channel();
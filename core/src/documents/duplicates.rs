@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+pub use duplicate_repository::{
+    find_duplicate_groups, find_signatures_by_watch_id, replace_duplicate_groups, upsert_signature,
+};
+
+mod duplicate_repository;
+
+/// Number of hash functions of a MinHash signature
+const SIGNATURE_SIZE: usize = 64;
+/// Number of characters of a shingle
+const SHINGLE_SIZE: usize = 5;
+/// LSH のバンド数。バンド数 × 行数 = SIGNATURE_SIZE で、類似度 0.8 の組はほぼ確実に候補になる
+const BANDS: usize = 16;
+const ROWS_PER_BAND: usize = SIGNATURE_SIZE / BANDS;
+
+/// MinHash signature of a text, estimating the Jaccard similarity of character shingles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature(Vec<u64>);
+
+impl Signature {
+    /// Computes the signature of the text. Returns `None` if the text has no characters other than whitespaces.
+    pub fn from_text(text: &str) -> Option<Signature> {
+        // 改行やインデントの違いは無視する
+        let chars: Vec<char> = text
+            .split_whitespace()
+            .flat_map(|word| word.chars().flat_map(char::to_lowercase).chain([' ']))
+            .collect();
+        if chars.is_empty() {
+            return None;
+        }
+
+        let mut mins = vec![u64::MAX; SIGNATURE_SIZE];
+        let mut shingle = String::new();
+        for window in chars.windows(SHINGLE_SIZE.min(chars.len())) {
+            shingle.clear();
+            shingle.extend(window);
+            let hash = fnv1a(shingle.as_bytes());
+            for (i, min) in mins.iter_mut().enumerate() {
+                let value = splitmix64(hash ^ seed(i));
+                if value < *min {
+                    *min = value;
+                }
+            }
+        }
+        Some(Signature(mins))
+    }
+
+    /// Returns the estimated Jaccard similarity between 0 and 1.
+    pub fn similarity(&self, other: &Signature) -> f32 {
+        let same = self.0.iter().zip(other.0.iter()).filter(|(a, b)| a == b).count();
+        same as f32 / SIGNATURE_SIZE as f32
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Signature> {
+        if bytes.len() != SIGNATURE_SIZE * 8 {
+            return Err(anyhow!("invalid signature length: {}", bytes.len()));
+        }
+        Ok(Signature(
+            bytes
+                .chunks_exact(8)
+                .map(|x| u64::from_le_bytes(x.try_into().unwrap()))
+                .collect(),
+        ))
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DuplicateGroup {
+    /// The lowest similarity between the documents directly judged as near-duplicates in the group
+    pub similarity: f32,
+    pub paths: Vec<String>,
+}
+
+/// Groups the signatures whose similarity is at least `threshold`.
+/// Returns the indices of the signatures of each group with its similarity, most similar groups first.
+pub fn group_near_duplicates(signatures: &[Signature], threshold: f32) -> Vec<(f32, Vec<usize>)> {
+    // (band, band のハッシュ) が一致する組だけを候補として比較する
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    for (i, signature) in signatures.iter().enumerate() {
+        for (band, rows) in signature.0.chunks(ROWS_PER_BAND).enumerate() {
+            let hash = rows.iter().fold(0u64, |acc, x| splitmix64(acc ^ x));
+            buckets.entry((band, hash)).or_default().push(i);
+        }
+    }
+
+    let mut compared: HashSet<(usize, usize)> = HashSet::new();
+    let mut parents: Vec<usize> = (0..signatures.len()).collect();
+    let mut min_similarities: HashMap<usize, f32> = HashMap::new();
+    let mut edges = vec![];
+    for members in buckets.values().filter(|x| x.len() >= 2) {
+        for (n, &a) in members.iter().enumerate() {
+            for &b in &members[n + 1..] {
+                if !compared.insert((a, b)) {
+                    continue;
+                }
+                let similarity = signatures[a].similarity(&signatures[b]);
+                if similarity >= threshold {
+                    union(&mut parents, a, b);
+                    edges.push((a, similarity));
+                }
+            }
+        }
+    }
+    for (a, similarity) in edges {
+        let root = find(&mut parents, a);
+        let min = min_similarities.entry(root).or_insert(similarity);
+        *min = min.min(similarity);
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..signatures.len() {
+        let root = find(&mut parents, i);
+        groups.entry(root).or_default().push(i);
+    }
+    let mut groups: Vec<(f32, Vec<usize>)> = groups
+        .into_iter()
+        .filter(|(_, members)| members.len() >= 2)
+        .map(|(root, members)| (min_similarities[&root], members))
+        .collect();
+    groups.sort_by(|(a_similarity, a_members), (b_similarity, b_members)| {
+        b_similarity
+            .total_cmp(a_similarity)
+            .then(a_members[0].cmp(&b_members[0]))
+    });
+    groups
+}
+
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut i = i;
+    while parents[i] != root {
+        let next = parents[i];
+        parents[i] = root;
+        i = next;
+    }
+    root
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let a = find(parents, a);
+    let b = find(parents, b);
+    if a != b {
+        parents[a.max(b)] = a.min(b);
+    }
+}
+
+// シグネチャは SQLite に保存するので、実行ごとに値が変わらないハッシュ関数を使う
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn seed(i: usize) -> u64 {
+    splitmix64(i as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTE: &str = "Meeting notes for the quarterly planning. We discussed the roadmap, hiring plans, \
+                        and the budget for the next quarter. Action items were assigned to each team.";
+
+    #[test]
+    fn test_signature_similarity() {
+        let a = Signature::from_text(NOTE).unwrap();
+        let b = Signature::from_text(&NOTE.replace("each team", "every team")).unwrap();
+        let c = Signature::from_text("A completely different document about gardening and tomatoes.").unwrap();
+        assert_eq!(a.similarity(&a), 1.0);
+        assert!(a.similarity(&b) >= 0.7, "{}", a.similarity(&b));
+        assert!(a.similarity(&c) < 0.2, "{}", a.similarity(&c));
+    }
+
+    #[test]
+    fn test_signature_ignores_whitespaces_and_case() {
+        let a = Signature::from_text("Hello World\nfoo  bar").unwrap();
+        let b = Signature::from_text("hello world foo\tbar\n").unwrap();
+        assert_eq!(a, b);
+        assert_eq!(Signature::from_text(" \n\t"), None);
+        assert!(Signature::from_text("短い").is_some());
+    }
+
+    #[test]
+    fn test_signature_bytes() -> Result<()> {
+        let a = Signature::from_text(NOTE).unwrap();
+        assert_eq!(Signature::from_bytes(&a.to_bytes())?, a);
+        assert!(Signature::from_bytes(&[0u8; 3]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_near_duplicates() {
+        let signatures = vec![
+            Signature::from_text(NOTE).unwrap(),
+            Signature::from_text("A completely different document about gardening and tomatoes.").unwrap(),
+            Signature::from_text(NOTE).unwrap(),
+            Signature::from_text(&format!("{} Done.", NOTE)).unwrap(),
+        ];
+        let groups = group_near_duplicates(&signatures, 0.8);
+        assert_eq!(groups.len(), 1);
+        let (similarity, members) = &groups[0];
+        assert_eq!(members, &vec![0, 2, 3]);
+        assert!(*similarity >= 0.8 && *similarity < 1.0, "{}", similarity);
+
+        assert!(group_near_duplicates(&signatures, 1.0)
+            .iter()
+            .all(|(_, members)| members == &vec![0, 2]));
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::SqliteConnection;
+
+use crate::{
+    documents::{
+        duplicates::{DuplicateGroup, Signature},
+        DocumentId,
+    },
+    WatchId,
+};
+
+pub async fn upsert_signature(
+    document_id: DocumentId,
+    signature: &Signature,
+    indexed_at: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<()> {
+    let bytes = signature.to_bytes();
+    sqlx::query!(
+        r"
+insert into document_signatures (document_id, signature, indexed_at)
+values ($1, $2, $3)
+on conflict (document_id) do update
+set signature = excluded.signature,
+indexed_at = excluded.indexed_at
+",
+        document_id,
+        bytes,
+        indexed_at,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the signatures and the `indexed_at` of the documents when they were computed.
+pub async fn find_signatures_by_watch_id(
+    watch_id: WatchId,
+    conn: &mut SqliteConnection,
+) -> Result<HashMap<DocumentId, (Signature, DateTime<Utc>)>> {
+    let rows = sqlx::query_as!(
+        SignatureRow,
+        r"
+select document_id, signature, indexed_at
+from document_signatures
+where document_id in (select id from documents where watch_id = $1)
+",
+        watch_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut signatures = HashMap::with_capacity(rows.len());
+    for row in rows {
+        let signature = match Signature::from_bytes(&row.signature) {
+            Ok(x) => x,
+            Err(e) => {
+                // 壊れたシグネチャは再計算させる
+                tracing::warn!("{}", e);
+                continue;
+            }
+        };
+        signatures.insert(
+            DocumentId(row.document_id),
+            (signature, DateTime::from_utc(row.indexed_at, Utc)),
+        );
+    }
+    Ok(signatures)
+}
+
+/// Replaces the duplicate groups of the watch with the newly detected ones.
+pub async fn replace_duplicate_groups(
+    watch_id: WatchId,
+    groups: &[DuplicateGroup],
+    conn: &mut SqliteConnection,
+) -> Result<()> {
+    sqlx::query!(r"delete from duplicate_groups where watch_id = $1", watch_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for group in groups {
+        let group_id = sqlx::query!(
+            r"
+insert into duplicate_groups (watch_id, similarity)
+values ($1, $2)
+returning id
+",
+            watch_id,
+            group.similarity,
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .id;
+
+        for path in &group.paths {
+            sqlx::query!(
+                r"
+insert into duplicate_group_documents (group_id, path)
+values ($1, $2)
+",
+                group_id,
+                path,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the duplicate groups detected last time, most similar groups first.
+pub async fn find_duplicate_groups(watch_id: WatchId, conn: &mut SqliteConnection) -> Result<Vec<DuplicateGroup>> {
+    let rows = sqlx::query_as!(
+        DuplicateGroupDocumentRow,
+        r"
+select g.id, g.similarity, d.path
+from duplicate_groups g
+join duplicate_group_documents d on d.group_id = g.id
+where g.watch_id = $1
+order by g.similarity desc, g.id, d.path
+",
+        watch_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut groups: Vec<DuplicateGroup> = vec![];
+    let mut last_group_id = None;
+    for row in rows {
+        if last_group_id != Some(row.id) {
+            groups.push(DuplicateGroup {
+                similarity: row.similarity as f32,
+                paths: vec![],
+            });
+            last_group_id = Some(row.id);
+        }
+        groups.last_mut().unwrap().paths.push(row.path);
+    }
+    Ok(groups)
+}
+
+struct SignatureRow {
+    document_id: i64,
+    signature: Vec<u8>,
+    indexed_at: chrono::NaiveDateTime,
+}
+
+struct DuplicateGroupDocumentRow {
+    id: i64,
+    similarity: f64,
+    path: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path;
+
+    use anyhow::Result;
+    use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+
+    use super::*;
+    use crate::watches::watch_repository;
+
+    async fn init() -> Result<(tempfile::TempDir, SqlitePool)> {
+        let db_dir = tempfile::tempdir()?;
+        let connection = SqlitePoolOptions::new()
+            .connect(format!("sqlite://{}/jomai.db?mode=rwc", db_dir.path().display()).as_str())
+            .await?;
+        sqlx::migrate!().run(&connection).await?;
+        Ok((db_dir, connection))
+    }
+
+    #[tokio::test]
+    async fn test_replace_duplicate_groups() -> Result<()> {
+        let (_db_dir, pool) = init().await?;
+        let mut conn = pool.acquire().await?;
+
+        let watch = watch_repository::insert(path::PathBuf::from("/foo"), &mut *conn).await?;
+        let groups = vec![
+            DuplicateGroup {
+                similarity: 0.875,
+                paths: vec!["/foo/a.md".to_string(), "/foo/a (1).md".to_string()],
+            },
+            DuplicateGroup {
+                similarity: 1.0,
+                paths: vec!["/foo/b.md".to_string(), "/foo/c.md".to_string()],
+            },
+        ];
+        replace_duplicate_groups(watch.id, &groups, &mut *conn).await?;
+        replace_duplicate_groups(watch.id, &groups, &mut *conn).await?;
+
+        let found = find_duplicate_groups(watch.id, &mut *conn).await?;
+        assert_eq!(
+            found,
+            vec![
+                DuplicateGroup {
+                    similarity: 1.0,
+                    paths: vec!["/foo/b.md".to_string(), "/foo/c.md".to_string()],
+                },
+                DuplicateGroup {
+                    similarity: 0.875,
+                    paths: vec!["/foo/a (1).md".to_string(), "/foo/a.md".to_string()],
+                },
+            ]
+        );
+        Ok(())
+    }
+}
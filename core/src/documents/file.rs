@@ -5,6 +5,8 @@ use anyhow::Result;
 pub struct FileMetadata {
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub modified_at: chrono::DateTime<chrono::Utc>,
+    /// In bytes
+    pub size: u64,
 }
 
 pub fn get_file_metadata<P: AsRef<Path>>(path: P) -> Result<FileMetadata> {
@@ -14,5 +16,6 @@ pub fn get_file_metadata<P: AsRef<Path>>(path: P) -> Result<FileMetadata> {
     Ok(FileMetadata {
         created_at,
         modified_at,
+        size: metadata.len(),
     })
 }
@@ -0,0 +1,483 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Result;
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use serde::Serialize;
+
+use crate::path_string_normalization::PathStringNormalizationExt;
+
+/// Scheme of the URLs of the local images in the rendered HTML, served by the app
+pub const LOCAL_IMAGE_SCHEME: &str = "jomai-image";
+
+#[cfg(not(windows))]
+const LOCAL_IMAGE_URL_PREFIX: &str = "jomai-image://localhost";
+// WebView2 ではカスタムスキームが https://<scheme>.localhost になる
+#[cfg(windows)]
+const LOCAL_IMAGE_URL_PREFIX: &str = "https://jomai-image.localhost";
+
+/// Local images served with their MIME types. Other files are never served.
+const IMAGE_TYPES: [(&str, &str); 7] = [
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("bmp", "image/bmp"),
+    ("svg", "image/svg+xml"),
+];
+
+const LINK_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RenderedDocument {
+    pub path: String,
+    /// Sanitized HTML, with the frontmatter as a table and the query terms in `<mark>` tags
+    pub html: String,
+    pub outline: Vec<OutlineHeading>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OutlineHeading {
+    /// 1 to 6
+    pub level: u8,
+    pub text: String,
+    /// `id` attribute of the heading in the HTML, to scroll to it
+    pub id: String,
+}
+
+/// Renders the Markdown file as HTML.
+///
+/// Raw HTML in the file is shown as text, links are limited to the web and the local files,
+/// and images are limited to the web and the local image files served via `LOCAL_IMAGE_SCHEME`.
+/// `highlight` returns the sorted and non-overlapping byte ranges of the text to be marked.
+pub(crate) fn render<H: Fn(&str) -> Result<Vec<(usize, usize)>>>(
+    path: &Path,
+    contents: &str,
+    highlight: H,
+) -> Result<RenderedDocument> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("/"));
+    // 壊れたフロントマターは本文の一部として表示する
+    let frontmatter::FieldsParseResult { fields, body } =
+        frontmatter::parse_fields(contents).unwrap_or(frontmatter::FieldsParseResult {
+            fields: vec![],
+            body: contents,
+        });
+
+    let mut html = String::new();
+    if !fields.is_empty() {
+        html.push_str("<table class=\"frontmatter\"><tbody>\n");
+        for (key, value) in &fields {
+            html.push_str("<tr><th>");
+            push_highlighted(&mut html, key, &highlight)?;
+            html.push_str("</th><td>");
+            push_highlighted(&mut html, value, &highlight)?;
+            html.push_str("</td></tr>\n");
+        }
+        html.push_str("</tbody></table>\n");
+    }
+
+    let outline = make_outline(body);
+    let events = rewrite_events(body, &outline, base_dir, &highlight)?;
+    html::push_html(&mut html, events.into_iter());
+
+    Ok(RenderedDocument {
+        path: path.to_normalized_path_string(),
+        html,
+        outline,
+    })
+}
+
+/// Returns the path of the local image of the URL in the rendered HTML, with its MIME type.
+pub fn local_image(url: &str) -> Option<(PathBuf, &'static str)> {
+    let path = percent_decode(url.strip_prefix(LOCAL_IMAGE_URL_PREFIX)?)?;
+    // Windows では /C:/foo の先頭のスラッシュを除く
+    let path = match path.strip_prefix('/') {
+        Some(x) if cfg!(windows) => x.to_string(),
+        _ => path,
+    };
+    let path = PathBuf::from(path);
+    let mime_type = image_mime_type(&path)?;
+    path.is_absolute().then_some((path, mime_type))
+}
+
+/// Markdown extensions enabled for the preview, also used to compute the details so that they agree with it.
+pub(crate) fn markdown_options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+fn make_outline(body: &str) -> Vec<OutlineHeading> {
+    let mut outline = vec![];
+    let mut current: Option<(u8, String)> = None;
+    for event in Parser::new_ext(body, markdown_options()) {
+        match event {
+            Event::Start(Tag::Heading(level, _, _)) => current = Some((level as u8, String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading)) = current.as_mut() {
+                    heading.push_str(&text);
+                }
+            }
+            Event::End(Tag::Heading(_, _, _)) => {
+                if let Some((level, text)) = current.take() {
+                    let id = unique_id(&slugify(&text), &outline);
+                    outline.push(OutlineHeading {
+                        level,
+                        text: text.trim().to_string(),
+                        id,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    outline
+}
+
+fn rewrite_events<'a, H: Fn(&str) -> Result<Vec<(usize, usize)>>>(
+    body: &'a str,
+    outline: &'a [OutlineHeading],
+    base_dir: &Path,
+    highlight: &H,
+) -> Result<Vec<Event<'a>>> {
+    let mut events = vec![];
+    let mut headings = outline.iter();
+    // 画像とリンクの開始タグを残したかどうか。終了タグも同じように扱う
+    let mut images: Vec<bool> = vec![];
+    let mut links: Vec<bool> = vec![];
+
+    for event in Parser::new_ext(body, markdown_options()) {
+        match event {
+            Event::Start(Tag::Heading(level, _, classes)) => {
+                let id = headings.next().map(|x| x.id.as_str());
+                events.push(Event::Start(Tag::Heading(level, id, classes)));
+            }
+            Event::Start(Tag::Link(link_type, dest, title)) => match safe_link_url(&dest, base_dir) {
+                Some(url) => {
+                    links.push(true);
+                    events.push(Event::Start(Tag::Link(link_type, url.into(), title)));
+                }
+                None => links.push(false),
+            },
+            Event::End(Tag::Link(link_type, dest, title)) => {
+                if links.pop().unwrap_or(false) {
+                    events.push(Event::End(Tag::Link(link_type, dest, title)));
+                }
+            }
+            Event::Start(Tag::Image(link_type, dest, title)) => match safe_image_url(&dest, base_dir) {
+                Some(url) => {
+                    images.push(true);
+                    events.push(Event::Start(Tag::Image(link_type, url.into(), title)));
+                }
+                // 表示できない画像は代替テキストだけにする
+                None => images.push(false),
+            },
+            Event::End(Tag::Image(link_type, dest, title)) => {
+                if images.pop().unwrap_or(false) {
+                    events.push(Event::End(Tag::Image(link_type, dest, title)));
+                }
+            }
+            // 代替テキストの中ではタグがエスケープされるので、マークしない
+            Event::Text(text) if images.contains(&true) => events.push(Event::Text(text)),
+            Event::Code(text) if images.contains(&true) => events.push(Event::Code(text)),
+            Event::Text(text) => push_highlighted_events(&mut events, &text, highlight)?,
+            Event::Code(text) => {
+                events.push(Event::Html("<code>".into()));
+                push_highlighted_events(&mut events, &text, highlight)?;
+                events.push(Event::Html("</code>".into()));
+            }
+            // 生の HTML は表示しない。コメント以外は書いたとおりに見えるようにテキストにする
+            Event::Html(text) if text.trim_start().starts_with("<!--") => {}
+            Event::Html(text) => push_highlighted_events(&mut events, &text, highlight)?,
+            event => events.push(event),
+        }
+    }
+    Ok(events)
+}
+
+fn push_highlighted_events<'a, H: Fn(&str) -> Result<Vec<(usize, usize)>>>(
+    events: &mut Vec<Event<'a>>,
+    text: &str,
+    highlight: &H,
+) -> Result<()> {
+    let mut position = 0;
+    for (start, end) in highlight(text)? {
+        if position < start {
+            events.push(Event::Text(CowStr::from(text[position..start].to_string())));
+        }
+        events.push(Event::Html("<mark>".into()));
+        events.push(Event::Text(CowStr::from(text[start..end].to_string())));
+        events.push(Event::Html("</mark>".into()));
+        position = end;
+    }
+    if position < text.len() {
+        events.push(Event::Text(CowStr::from(text[position..].to_string())));
+    }
+    Ok(())
+}
+
+fn push_highlighted<H: Fn(&str) -> Result<Vec<(usize, usize)>>>(
+    html: &mut String,
+    text: &str,
+    highlight: &H,
+) -> Result<()> {
+    let mut position = 0;
+    for (start, end) in highlight(text)? {
+        html.push_str(&escape_html(&text[position..start]));
+        html.push_str("<mark>");
+        html.push_str(&escape_html(&text[start..end]));
+        html.push_str("</mark>");
+        position = end;
+    }
+    html.push_str(&escape_html(&text[position..]));
+    Ok(())
+}
+
+/// Keeps the links to the web and the anchors, and turns the paths into `file://` URLs opened by the app.
+fn safe_link_url(dest: &str, base_dir: &Path) -> Option<String> {
+    if dest.starts_with('#') {
+        return Some(dest.to_string());
+    }
+    match scheme(dest) {
+        Some(scheme) if LINK_SCHEMES.contains(&scheme.to_lowercase().as_str()) => Some(dest.to_string()),
+        Some(_) => None,
+        None => resolve_path(dest, base_dir).map(|x| format!("file://{}", percent_encode(&url_path(&x)))),
+    }
+}
+
+/// Keeps the images on the web and turns the paths of the local images into URLs served by the app.
+fn safe_image_url(dest: &str, base_dir: &Path) -> Option<String> {
+    match scheme(dest) {
+        Some(scheme) if ["http", "https"].contains(&scheme.to_lowercase().as_str()) => Some(dest.to_string()),
+        Some(_) => None,
+        None => {
+            let path = resolve_path(dest, base_dir)?;
+            image_mime_type(&path)?;
+            Some(format!(
+                "{}{}",
+                LOCAL_IMAGE_URL_PREFIX,
+                percent_encode(&url_path(&path))
+            ))
+        }
+    }
+}
+
+/// Returns the scheme of the URL, or `None` for a path. A drive letter of Windows is not a scheme.
+pub(crate) fn scheme(url: &str) -> Option<&str> {
+    let (scheme, _) = url.split_once(':')?;
+    let mut chars = scheme.chars();
+    let is_scheme = scheme.len() > 1
+        && chars.next().map_or(false, |x| x.is_ascii_alphabetic())
+        && chars.all(|x| x.is_ascii_alphanumeric() || "+-.".contains(x));
+    is_scheme.then_some(scheme)
+}
+
+/// Resolves the path in the document against the directory of the document, dropping `.` and `..`.
+fn resolve_path(dest: &str, base_dir: &Path) -> Option<PathBuf> {
+    let dest = dest.split(['?', '#']).next()?;
+    if dest.is_empty() {
+        return None;
+    }
+    let dest = percent_decode(dest)?;
+    let mut resolved = PathBuf::new();
+    for component in base_dir.join(dest).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            x => resolved.push(x),
+        }
+    }
+    Some(resolved)
+}
+
+fn image_mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    IMAGE_TYPES
+        .iter()
+        .find(|(x, _)| *x == extension)
+        .map(|(_, mime_type)| *mime_type)
+}
+
+/// Path part of the URL of the file, starting with `/`.
+fn url_path(path: &Path) -> String {
+    let path = path.to_normalized_path_string().replace('\\', "/");
+    if path.starts_with('/') {
+        path
+    } else {
+        format!("/{}", path)
+    }
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Lowercases the heading and joins the words with `-`, keeping non-ASCII letters such as Japanese.
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+fn unique_id(slug: &str, outline: &[OutlineHeading]) -> String {
+    let mut id = slug.to_string();
+    let mut n = 1;
+    while outline.iter().any(|x| x.id == id) {
+        id = format!("{}-{}", slug, n);
+        n += 1;
+    }
+    id
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_highlight(_: &str) -> Result<Vec<(usize, usize)>> {
+        Ok(vec![])
+    }
+
+    fn highlight_rust(text: &str) -> Result<Vec<(usize, usize)>> {
+        Ok(text.match_indices("Rust").map(|(i, x)| (i, i + x.len())).collect())
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let contents = r#"---
+title: Notes
+tags: [rust, tantivy]
+---
+# Rust notes
+
+Learning `Rust` with **Rust** book.
+
+## Links
+"#;
+        let rendered = render(Path::new("/notes/a.md"), contents, highlight_rust)?;
+        assert_eq!(
+            rendered.html,
+            r#"<table class="frontmatter"><tbody>
+<tr><th>title</th><td>Notes</td></tr>
+<tr><th>tags</th><td>rust, tantivy</td></tr>
+</tbody></table>
+<h1 id="rust-notes"><mark>Rust</mark> notes</h1>
+<p>Learning <code><mark>Rust</mark></code> with <strong><mark>Rust</mark></strong> book.</p>
+<h2 id="links">Links</h2>
+"#
+        );
+        assert_eq!(
+            rendered.outline,
+            vec![
+                OutlineHeading {
+                    level: 1,
+                    text: "Rust notes".to_string(),
+                    id: "rust-notes".to_string(),
+                },
+                OutlineHeading {
+                    level: 2,
+                    text: "Links".to_string(),
+                    id: "links".to_string(),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_sanitizes() -> Result<()> {
+        let contents = r#"<script>alert(1)</script>
+
+<!-- comment -->
+[a](javascript:alert(1)) [b](https://example.com/) [c](../b.md#top)
+![x](./images/a%20b.png) ![y](data:image/png;base64,AAAA) ![z](notes.txt)
+"#;
+        let rendered = render(Path::new("/notes/a.md"), contents, no_highlight)?;
+        let prefix = LOCAL_IMAGE_URL_PREFIX;
+        assert_eq!(
+            rendered.html,
+            format!(
+                r#"&lt;script&gt;alert(1)&lt;/script&gt;
+<p>a <a href="https://example.com/">b</a> <a href="file:///b.md">c</a>
+<img src="{}/notes/images/a%20b.png" alt="x" /> y z</p>
+"#,
+                prefix
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_image() {
+        let url = safe_image_url("images/日本語.PNG", Path::new("/notes")).unwrap();
+        assert_eq!(
+            local_image(&url),
+            Some((PathBuf::from("/notes/images/日本語.PNG"), "image/png"))
+        );
+        assert_eq!(local_image(&format!("{}/notes/a.md", LOCAL_IMAGE_URL_PREFIX)), None);
+        assert_eq!(local_image("https://example.com/a.png"), None);
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  議事録 2022-11 "), "議事録-2022-11");
+        assert_eq!(slugify("!!!"), "section");
+        let outline = vec![OutlineHeading {
+            level: 2,
+            text: "Notes".to_string(),
+            id: "notes".to_string(),
+        }];
+        assert_eq!(unique_id("notes", &outline), "notes-1");
+    }
+}
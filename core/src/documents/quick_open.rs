@@ -0,0 +1,556 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::instrument;
+
+use crate::{
+    documents::{
+        document_service::{DocumentEvent, DocumentService},
+        search,
+    },
+    path_string_normalization::PathStringNormalizationExt,
+    WatchId,
+};
+
+const SCORE_MATCH: i32 = 16;
+const BONUS_BOUNDARY: i32 = 8;
+const BONUS_CAMEL_CASE: i32 = 7;
+const BONUS_CONSECUTIVE: i32 = 4;
+/// Matches in the file name are more important than ones in the directories
+const BONUS_FILE_NAME: i32 = 2;
+const PENALTY_GAP_START: i32 = 3;
+const PENALTY_GAP_EXTENSION: i32 = 1;
+
+#[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuickOpenField {
+    Title,
+    Path,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickOpenResult {
+    pub path: String,
+    pub title: String,
+    pub score: i32,
+    pub matched_field: QuickOpenField,
+    /// Char indices of the matched characters in the matched field
+    pub positions: Vec<usize>,
+}
+
+/// An in-memory list of document titles and paths for finding documents by typing a few characters of them.
+#[derive(Clone, Default)]
+pub struct QuickOpen {
+    entries: Arc<RwLock<Entries>>,
+}
+
+/// Entries are kept in a `Vec` since scanning it is much faster than scanning a `HashMap`.
+#[derive(Default)]
+struct Entries {
+    list: Vec<Entry>,
+    index_by_path: HashMap<PathBuf, usize>,
+}
+
+impl QuickOpen {
+    /// Returns documents whose title or path contains the characters of the query in order.
+    pub fn find(&self, query: &str, limit: usize) -> Vec<QuickOpenResult> {
+        let query = Query::new(query);
+        if query.chars.is_empty() || limit == 0 {
+            return vec![];
+        }
+
+        let entries = self.entries.read().unwrap();
+        // 上位 limit 件だけを、最も悪いものが先頭に来るヒープで保持する
+        let mut top: BinaryHeap<Ranked> = BinaryHeap::with_capacity(limit + 1);
+        for entry in &entries.list {
+            let ranked = match entry.score(&query) {
+                Some(score) => Ranked { score, entry },
+                None => continue,
+            };
+            if top.len() < limit {
+                top.push(ranked);
+            } else if let Some(mut worst) = top.peek_mut() {
+                if ranked < *worst {
+                    *worst = ranked;
+                }
+            }
+        }
+        top.into_sorted_vec()
+            .into_iter()
+            .map(|x| x.entry.to_result(&query, x.score))
+            .collect()
+    }
+
+    fn insert(&self, path: &Path, title: String, watch_id: WatchId) {
+        let entry = Entry::new(path, title, watch_id);
+        let mut entries = self.entries.write().unwrap();
+        match entries.index_by_path.get(path) {
+            Some(&index) => entries.list[index] = entry,
+            None => {
+                let index = entries.list.len();
+                entries.list.push(entry);
+                entries.index_by_path.insert(path.to_path_buf(), index);
+            }
+        }
+    }
+
+    fn remove(&self, path: &Path) {
+        let mut entries = self.entries.write().unwrap();
+        if let Some(index) = entries.index_by_path.remove(path) {
+            entries.list.swap_remove(index);
+            if index < entries.list.len() {
+                let moved_path = entries.list[index].path.clone();
+                entries.index_by_path.insert(moved_path, index);
+            }
+        }
+    }
+
+    fn remove_by_watch_id(&self, watch_id: WatchId) {
+        let mut entries = self.entries.write().unwrap();
+        entries.list.retain(|entry| entry.watch_id != watch_id);
+        entries.rebuild_index();
+    }
+
+    fn clear(&self) {
+        let mut entries = self.entries.write().unwrap();
+        entries.list.clear();
+        entries.index_by_path.clear();
+    }
+}
+
+impl Entries {
+    fn rebuild_index(&mut self) {
+        self.index_by_path = self
+            .list
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (entry.path.clone(), index))
+            .collect();
+    }
+}
+
+/// Keeps [`QuickOpen`] up to date with the documents.
+pub struct QuickOpenSync {
+    quick_open: QuickOpen,
+    document_service: DocumentService,
+    document_event_rx: broadcast::Receiver<DocumentEvent>,
+}
+
+impl QuickOpenSync {
+    pub fn new(document_service: DocumentService) -> Self {
+        // 起動直後のイベントを取りこぼさないように、run より前に購読しておく
+        let document_event_rx = document_service.subscribe();
+        Self {
+            quick_open: QuickOpen::default(),
+            document_service,
+            document_event_rx,
+        }
+    }
+
+    pub fn quick_open(&self) -> QuickOpen {
+        self.quick_open.clone()
+    }
+
+    #[instrument(name = "QuickOpenSync::run", level = "info", skip(self))]
+    pub async fn run(mut self) -> Result<()> {
+        self.load_all()?;
+
+        loop {
+            match self.document_event_rx.recv().await {
+                Ok(event) => self.handle_event(event),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!("{} document events were skipped, reloading all documents", n);
+                    self.load_all()?;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_all(&self) -> Result<()> {
+        let titles = self.document_service.find_all_titles()?;
+        self.quick_open.clear();
+        for (path, title, watch_id) in titles {
+            self.quick_open.insert(&path, title, watch_id);
+        }
+        Ok(())
+    }
+
+    fn handle_event(&self, event: DocumentEvent) {
+        use DocumentEvent::*;
+        match event {
+            DocumentAdded(document) | DocumentUpdated(document) => match search::read_title(&document.path) {
+                Ok(title) => self.quick_open.insert(&document.path, title, document.watch_id),
+                Err(e) => tracing::warn!("Failed to read the title of {}: {}", document.path.display(), e),
+            },
+            DocumentDeleted(document) => self.quick_open.remove(&document.path),
+            WatchDocumentsDeleted(watch_id) => self.quick_open.remove_by_watch_id(watch_id),
+            WatchDocumentsAdded(_) => {}
+        }
+    }
+}
+
+/// A lowercased query without whitespaces
+struct Query {
+    chars: Vec<char>,
+    /// Set if the query consists of ASCII characters, which can be matched byte by byte
+    ascii_bytes: Option<Vec<u8>>,
+    mask: u64,
+}
+
+impl Query {
+    fn new(query: &str) -> Self {
+        let chars: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).map(to_lowercase).collect();
+        let ascii_bytes = chars
+            .iter()
+            .all(|c| c.is_ascii())
+            .then(|| chars.iter().map(|c| *c as u8).collect());
+        let mask = chars.iter().fold(0, |mask, c| mask | char_mask(*c));
+        Self {
+            chars,
+            ascii_bytes,
+            mask,
+        }
+    }
+}
+
+/// Ordered from the best match: a higher score, a shorter path and then the path in alphabetical order.
+struct Ranked<'a> {
+    score: i32,
+    entry: &'a Entry,
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .score
+            .cmp(&self.score)
+            .then(self.entry.path_string.len().cmp(&other.entry.path_string.len()))
+            .then(self.entry.path_string.cmp(&other.entry.path_string))
+    }
+}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+struct Entry {
+    path: PathBuf,
+    path_string: String,
+    title_string: String,
+    watch_id: WatchId,
+    title_candidate: Candidate,
+    path_candidate: Candidate,
+}
+
+impl Entry {
+    fn new(path: &Path, title: String, watch_id: WatchId) -> Self {
+        let path_string = path.to_normalized_path_string();
+        let file_name_start = path_string.len()
+            - path
+                .file_name()
+                .map(|x| x.to_normalized_path_string().len())
+                .unwrap_or(0);
+        Self {
+            path: path.to_path_buf(),
+            title_candidate: Candidate::new(&title, 0),
+            path_candidate: Candidate::new(&path_string, file_name_start),
+            path_string,
+            title_string: title,
+            watch_id,
+        }
+    }
+
+    fn score(&self, query: &Query) -> Option<i32> {
+        let title_score = fuzzy_match(query, &self.title_candidate, None);
+        let path_score = fuzzy_match(query, &self.path_candidate, None);
+        title_score.max(path_score)
+    }
+
+    fn to_result(&self, query: &Query, score: i32) -> QuickOpenResult {
+        let mut positions = vec![];
+        let (matched_field, candidate) = if fuzzy_match(query, &self.title_candidate, None) == Some(score) {
+            (QuickOpenField::Title, &self.title_candidate)
+        } else {
+            (QuickOpenField::Path, &self.path_candidate)
+        };
+        fuzzy_match(query, candidate, Some(&mut positions));
+        // 小文字にすると UTF-8 のバイト数が変わることがあるので、文字の位置で返す
+        let positions = positions
+            .into_iter()
+            .map(|byte_index| candidate.lowercased[..byte_index].chars().count())
+            .collect();
+        QuickOpenResult {
+            path: self.path_string.clone(),
+            title: self.title_string.clone(),
+            score,
+            matched_field,
+            positions,
+        }
+    }
+}
+
+struct Candidate {
+    lowercased: String,
+    /// Bonus for a match at each byte index of `lowercased`
+    bonuses: Vec<i8>,
+    /// Characters contained in the text, see [`char_mask`]
+    mask: u64,
+    /// Byte index where the last component of the path starts
+    file_name_start: usize,
+}
+
+impl Candidate {
+    fn new(text: &str, file_name_start: usize) -> Self {
+        let mut lowercased = String::with_capacity(text.len());
+        let mut bonuses = Vec::with_capacity(text.len());
+        let mut previous = None;
+        for c in text.chars() {
+            let lower = to_lowercase(c);
+            let bonus = match previous {
+                None => BONUS_BOUNDARY,
+                Some('/' | '\\' | '_' | '-' | '.' | ' ') => BONUS_BOUNDARY,
+                Some(p) if p.is_lowercase() && c.is_uppercase() => BONUS_CAMEL_CASE,
+                _ => 0,
+            };
+            lowercased.push(lower);
+            bonuses.resize(lowercased.len(), bonus as i8);
+            previous = Some(c);
+        }
+        let mask = lowercased.chars().fold(0, |mask, c| mask | char_mask(c));
+        // 小文字にしてバイト数が変わった場合に合わせる
+        let file_name_start = file_name_start.min(lowercased.len());
+        Self {
+            lowercased,
+            bonuses,
+            mask,
+            file_name_start,
+        }
+    }
+}
+
+/// Scores the candidate if it contains all the characters of the query in order.
+///
+/// The shortest window containing the query is searched like fzf does, and matches at word boundaries,
+/// consecutive matches and matches in the file name get bonuses while gaps get penalties.
+/// `positions` receives byte indices of the matched characters.
+fn fuzzy_match(query: &Query, candidate: &Candidate, mut positions: Option<&mut Vec<usize>>) -> Option<i32> {
+    if candidate.mask & query.mask != query.mask {
+        return None;
+    }
+    let text = candidate.lowercased.as_str();
+    let (start, end) = match &query.ascii_bytes {
+        Some(bytes) => find_window_ascii(text.as_bytes(), bytes)?,
+        None => find_window(text, &query.chars)?,
+    };
+    let query = &query.chars;
+
+    let mut score = 0;
+    let mut query_index = 0;
+    let mut previous_matched = false;
+    let mut in_gap = false;
+    for (i, c) in text[start..end].char_indices() {
+        let i = start + i;
+        if query_index < query.len() && c == query[query_index] {
+            score += SCORE_MATCH + candidate.bonuses[i] as i32;
+            if previous_matched {
+                score += BONUS_CONSECUTIVE;
+            }
+            if i >= candidate.file_name_start {
+                score += BONUS_FILE_NAME;
+            }
+            if let Some(positions) = positions.as_mut() {
+                positions.push(i);
+            }
+            query_index += 1;
+            previous_matched = true;
+            in_gap = false;
+        } else {
+            score -= if in_gap {
+                PENALTY_GAP_EXTENSION
+            } else {
+                PENALTY_GAP_START
+            };
+            previous_matched = false;
+            in_gap = true;
+        }
+    }
+    Some(score)
+}
+
+/// Returns the byte range of the shortest window containing the query, searching the first match from the start.
+fn find_window(text: &str, query: &[char]) -> Option<(usize, usize)> {
+    // 前方から最初に query を含み終える位置を探す
+    let mut end = 0;
+    for c in query {
+        end += text[end..].find(*c)? + c.len_utf8();
+    }
+
+    // 後方に戻って、なるべく短い範囲に絞る
+    let mut start = end;
+    for c in query.iter().rev() {
+        start = text[..start].rfind(*c)?;
+    }
+    Some((start, end))
+}
+
+/// Same as [`find_window`] but faster for ASCII queries.
+/// ASCII bytes never appear in multibyte UTF-8 sequences, so the text can be scanned byte by byte.
+fn find_window_ascii(text: &[u8], query: &[u8]) -> Option<(usize, usize)> {
+    let mut query_index = 0;
+    let mut end = None;
+    for (i, b) in text.iter().enumerate() {
+        if *b == query[query_index] {
+            query_index += 1;
+            if query_index == query.len() {
+                end = Some(i + 1);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    let mut query_index = query.len();
+    for i in (0..end).rev() {
+        if text[i] == query[query_index - 1] {
+            query_index -= 1;
+            if query_index == 0 {
+                return Some((i, end));
+            }
+        }
+    }
+    None
+}
+
+/// A bit set of characters for rejecting candidates quickly.
+/// Alphanumeric ASCII characters have their own bits and the others share the last bit.
+fn char_mask(c: char) -> u64 {
+    match c {
+        'a'..='z' => 1 << (c as u32 - 'a' as u32),
+        '0'..='9' => 1 << (26 + c as u32 - '0' as u32),
+        _ => 1 << 63,
+    }
+}
+
+fn to_lowercase(c: char) -> char {
+    // 文字の位置がずれないように、一文字に対応する小文字だけを使う
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, candidate: &Candidate) -> Option<i32> {
+        fuzzy_match(&Query::new(query), candidate, None)
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        let candidate = Candidate::new("/Users/me/jomai/README.md", 16);
+        assert!(score("jmrdm", &candidate).is_some());
+        assert_eq!(score("jmx", &candidate), None);
+        assert_eq!(score("mdj", &candidate), None);
+    }
+
+    #[test]
+    fn test_fuzzy_match_prefers_consecutive_and_boundary_matches() {
+        let consecutive = Candidate::new("/notes/README.md", 7);
+        let scattered = Candidate::new("/notes/read/me.md", 12);
+        assert!(score("readme", &consecutive) > score("readme", &scattered));
+
+        let boundary = Candidate::new("/notes/rust-memo.md", 7);
+        let middle = Candidate::new("/notes/trust.md", 7);
+        assert!(score("rm", &boundary) > score("rm", &middle));
+    }
+
+    #[test]
+    fn test_find() {
+        let quick_open = QuickOpen::default();
+        quick_open.insert(Path::new("/notes/jomai/README.md"), "Jomai".to_string(), WatchId(1));
+        quick_open.insert(Path::new("/notes/journal.md"), "Journal".to_string(), WatchId(1));
+        quick_open.insert(Path::new("/work/roadmap.md"), "Roadmap".to_string(), WatchId(2));
+        quick_open.insert(Path::new("/work/議事録.md"), "議事録".to_string(), WatchId(2));
+
+        let results = quick_open.find("jmrdm", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, "/notes/jomai/README.md");
+        assert_eq!(results[0].matched_field, QuickOpenField::Path);
+        assert_eq!(results[0].positions, vec![7, 9, 13, 16, 17]);
+
+        let results = quick_open.find("roadmap", 10);
+        assert_eq!(results[0].title, "Roadmap");
+        assert_eq!(results[0].matched_field, QuickOpenField::Title);
+
+        let results = quick_open.find("議録", 10);
+        assert_eq!(results[0].positions, vec![0, 2]);
+
+        quick_open.remove(Path::new("/notes/jomai/README.md"));
+        assert!(quick_open.find("jmrdm", 10).is_empty());
+        assert_eq!(quick_open.find("journal", 10).len(), 1);
+
+        quick_open.remove_by_watch_id(WatchId(1));
+        assert!(quick_open.find("jo", 10).is_empty());
+        assert_eq!(quick_open.find("roadmap", 10).len(), 1);
+    }
+
+    // 実行時間を測るので、最適化したビルドで実行する: cargo test --release -- --ignored test_find_performance
+    #[ignore]
+    #[test]
+    fn test_find_performance() {
+        let quick_open = QuickOpen::default();
+        let words = [
+            "rust",
+            "memo",
+            "roadmap",
+            "journal",
+            "議事録",
+            "tokio",
+            "design",
+            "README",
+        ];
+        for i in 0..100_000 {
+            let word = words[i % words.len()];
+            let path = PathBuf::from(format!("/Users/me/notes/{}/{}-{}.md", i % 100, word, i));
+            quick_open.insert(&path, format!("{} {}", word, i), WatchId((i % 3) as i64));
+        }
+
+        for query in ["rdm", "議録", "notes/42/jrnl", "zzz"] {
+            // 初回の実行によるばらつきを避けるため、最も速かった回で比べる
+            let elapsed = (0..5)
+                .map(|_| {
+                    let started_at = std::time::Instant::now();
+                    quick_open.find(query, 50);
+                    started_at.elapsed()
+                })
+                .min()
+                .unwrap();
+            assert!(
+                elapsed < std::time::Duration::from_millis(10),
+                "quick open took {:?} for {}",
+                elapsed,
+                query
+            );
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::sync::broadcast;
+use tracing::instrument;
+
+use crate::{
+    documents::{
+        annotations::AnnotationCache,
+        document_service::{DocumentEvent, DocumentService},
+        Document, Sort,
+    },
+    path_string_normalization::PathStringNormalizationExt,
+    watches::{watch_repository, WatchStatus},
+    WatchId,
+};
+
+pub mod saved_search_repository;
+
+#[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq, Hash, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct SavedSearchId(pub(crate) i64);
+
+impl From<i64> for SavedSearchId {
+    fn from(value: i64) -> Self {
+        SavedSearchId(value)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedSearch {
+    pub id: SavedSearchId,
+    pub name: String,
+    pub query: String,
+    pub tags: Vec<String>,
+    /// Field filters in the query syntax, e.g. `modified:this-month -tag:draft`
+    pub filters: String,
+    pub sort: Sort,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl SavedSearch {
+    /// Returns the query with the filters, as typed in the search box.
+    pub fn full_query(&self) -> String {
+        full_query(&self.filters, &self.query)
+    }
+}
+
+/// Fields of a saved search given by the user
+#[derive(Debug, Clone)]
+pub struct NewSavedSearch {
+    pub name: String,
+    pub query: String,
+    pub tags: Vec<String>,
+    pub filters: String,
+    pub sort: Sort,
+}
+
+impl NewSavedSearch {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("the name of a saved search must not be empty"));
+        }
+        if self.full_query().is_empty() && self.tags.is_empty() {
+            return Err(anyhow!("a saved search needs a query, filters or tags"));
+        }
+        Ok(())
+    }
+
+    pub fn full_query(&self) -> String {
+        full_query(&self.filters, &self.query)
+    }
+}
+
+fn full_query(filters: &str, query: &str) -> String {
+    format!("{} {}", filters.trim(), query.trim()).trim().to_string()
+}
+
+/// Notified when a document added or updated newly matches a saved search
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedSearchMatch {
+    pub saved_search_id: SavedSearchId,
+    pub name: String,
+    pub path: String,
+    pub watch_id: WatchId,
+}
+
+/// Keeps the documents matching the saved searches up to date, notifying the ones which newly match.
+pub struct SavedSearchSync {
+    connection_pool: SqlitePool,
+    annotation_cache: AnnotationCache,
+    document_service: DocumentService,
+    document_event_rx: broadcast::Receiver<DocumentEvent>,
+    match_tx: broadcast::Sender<SavedSearchMatch>,
+}
+
+impl SavedSearchSync {
+    pub fn new(
+        connection_pool: SqlitePool,
+        annotation_cache: AnnotationCache,
+        document_service: DocumentService,
+    ) -> Self {
+        // 起動直後のイベントを取りこぼさないように、run より前に購読しておく
+        let document_event_rx = document_service.subscribe();
+        let (match_tx, _) = broadcast::channel(100);
+        Self {
+            connection_pool,
+            annotation_cache,
+            document_service,
+            document_event_rx,
+            match_tx,
+        }
+    }
+
+    pub fn match_tx(&self) -> broadcast::Sender<SavedSearchMatch> {
+        self.match_tx.clone()
+    }
+
+    #[instrument(name = "SavedSearchSync::run", level = "info", skip(self))]
+    pub async fn run(mut self) -> Result<()> {
+        loop {
+            match self.document_event_rx.recv().await {
+                Ok(event) => {
+                    if let Err(e) = self.handle_event(event).await {
+                        tracing::warn!("Failed to update the matches of the saved searches: {}", e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!("{} document events were skipped, re-evaluating all saved searches", n);
+                    if let Err(e) = self.refresh_all().await {
+                        tracing::warn!("Failed to re-evaluate the saved searches: {}", e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_event(&self, event: DocumentEvent) -> Result<()> {
+        use DocumentEvent::*;
+        match event {
+            DocumentAdded(document) | DocumentUpdated(document) => self.match_document(document).await,
+            DocumentDeleted(document) => {
+                let mut conn = self.connection_pool.acquire().await?;
+                saved_search_repository::delete_matches_by_path(&document.path, &mut *conn).await
+            }
+            WatchDocumentsDeleted(watch_id) => {
+                let mut conn = self.connection_pool.acquire().await?;
+                saved_search_repository::delete_matches_by_watch_id(watch_id, &mut *conn).await
+            }
+            // スキャン中の文書は通知せずに、コミット後の索引でまとめてマッチを記録する
+            WatchDocumentsAdded(_) => self.refresh_all().await,
+        }
+    }
+
+    async fn match_document(&self, document: Document) -> Result<()> {
+        let mut conn = self.connection_pool.acquire().await?;
+        let saved_searches = saved_search_repository::find_all(&mut *conn).await?;
+        if saved_searches.is_empty() {
+            return Ok(());
+        }
+        // 監視フォルダを追加した直後の大量の文書は、WatchDocumentsAdded で通知せずに記録する
+        let watch = watch_repository::find_by_id(document.watch_id, &mut *conn).await?;
+        if watch.map_or(true, |x| matches!(x.status, WatchStatus::Adding)) {
+            return Ok(());
+        }
+
+        // is:pinned や label: の条件のために注釈を渡す
+        let document_service = self
+            .document_service
+            .with_annotations(self.annotation_cache.get(&mut *conn).await?);
+        let queries: Vec<(String, Vec<String>)> = saved_searches
+            .iter()
+            .map(|x| (x.full_query(), x.tags.clone()))
+            .collect();
+        let path = document.path.clone();
+        let watch_id = document.watch_id;
+        let matches = tokio::task::spawn_blocking(move || {
+            let queries: Vec<(&str, Vec<&str>)> = queries
+                .iter()
+                .map(|(query, tags)| (query.as_str(), tags.iter().map(|x| x.as_str()).collect()))
+                .collect();
+            document_service.match_document(path, watch_id, &queries)
+        })
+        .await??;
+
+        for (saved_search, matched) in saved_searches.into_iter().zip(matches) {
+            if !matched {
+                saved_search_repository::delete_match(saved_search.id, &document.path, &mut *conn).await?;
+                continue;
+            }
+            // 既にマッチしていた文書の更新は通知しない
+            if saved_search_repository::insert_match(saved_search.id, &document.path, watch_id, &mut *conn).await? {
+                tracing::debug!(
+                    "{} newly matches the saved search {}",
+                    document.path.display(),
+                    saved_search.name
+                );
+                // 購読者がいなければ送信に失敗するが、問題ない
+                let _ = self.match_tx.send(SavedSearchMatch {
+                    saved_search_id: saved_search.id,
+                    name: saved_search.name,
+                    path: document.path.as_path().to_normalized_path_string(),
+                    watch_id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the matches of all the saved searches with the committed index, without notifying them.
+    async fn refresh_all(&self) -> Result<()> {
+        let mut conn = self.connection_pool.acquire().await?;
+        let document_service = self
+            .document_service
+            .with_annotations(self.annotation_cache.get(&mut *conn).await?);
+        for saved_search in saved_search_repository::find_all(&mut *conn).await? {
+            let matches = find_matches(&document_service, &saved_search.full_query(), &saved_search.tags).await?;
+            saved_search_repository::replace_matches(saved_search.id, &matches, &mut *conn).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the documents matching the query and the tags on the blocking thread pool.
+pub async fn find_matches(
+    document_service: &DocumentService,
+    query: &str,
+    tags: &[String],
+) -> Result<Vec<(PathBuf, WatchId)>> {
+    let document_service = document_service.clone();
+    let query = query.to_string();
+    let tags = tags.to_vec();
+    tokio::task::spawn_blocking(move || {
+        let tags: Vec<&str> = tags.iter().map(|x| x.as_str()).collect();
+        document_service.find_matching_paths(&query, &tags)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::documents::SortOrder;
+
+    fn new_saved_search(name: &str, query: &str, tags: &[&str], filters: &str) -> NewSavedSearch {
+        NewSavedSearch {
+            name: name.to_string(),
+            query: query.to_string(),
+            tags: tags.iter().map(|x| x.to_string()).collect(),
+            filters: filters.to_string(),
+            sort: Sort::ModifiedAt(SortOrder::Desc),
+        }
+    }
+
+    #[test]
+    fn test_full_query() {
+        assert_eq!(
+            new_saved_search("a", " rust ", &[], "modified:this-month ").full_query(),
+            "modified:this-month rust"
+        );
+        assert_eq!(new_saved_search("a", "rust", &[], "").full_query(), "rust");
+        assert_eq!(new_saved_search("a", "", &[], " ").full_query(), "");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(new_saved_search("a", "rust", &[], "").validate().is_ok());
+        assert!(new_saved_search("a", "", &["ops"], "").validate().is_ok());
+        assert!(new_saved_search("a", "", &[], "tag:ops").validate().is_ok());
+        assert!(new_saved_search(" ", "rust", &[], "").validate().is_err());
+        assert!(new_saved_search("a", " ", &[], "").validate().is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::SqliteConnection;
+
+use crate::{
+    documents::saved_searches::{NewSavedSearch, SavedSearch, SavedSearchId},
+    path_string_normalization::PathStringNormalizationExt,
+    WatchId,
+};
+
+pub async fn insert(saved_search: &NewSavedSearch, conn: &mut SqliteConnection) -> Result<SavedSearch> {
+    let tags = join_tags(&saved_search.tags);
+    let sort = saved_search.sort.to_string();
+    let id = sqlx::query!(
+        r"
+insert into saved_searches (name, query, tags, filters, sort)
+values ($1, $2, $3, $4, $5)
+returning id
+",
+        saved_search.name,
+        saved_search.query,
+        tags,
+        saved_search.filters,
+        sort,
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .id;
+
+    Ok(find_by_id(SavedSearchId(id), &mut *conn).await?.unwrap())
+}
+
+pub async fn update(
+    id: SavedSearchId,
+    saved_search: &NewSavedSearch,
+    conn: &mut SqliteConnection,
+) -> Result<Option<SavedSearch>> {
+    let tags = join_tags(&saved_search.tags);
+    let sort = saved_search.sort.to_string();
+    let updated_at = Utc::now();
+    sqlx::query!(
+        r"
+update saved_searches
+set name = $1, query = $2, tags = $3, filters = $4, sort = $5, updated_at = $6
+where id = $7
+",
+        saved_search.name,
+        saved_search.query,
+        tags,
+        saved_search.filters,
+        sort,
+        updated_at,
+        id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    find_by_id(id, &mut *conn).await
+}
+
+pub async fn delete(id: SavedSearchId, conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query!(
+        r"
+delete from saved_searches where id = $1
+",
+        id,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn find_all(conn: &mut SqliteConnection) -> Result<Vec<SavedSearch>> {
+    sqlx::query_as!(
+        SavedSearchRow,
+        r"
+select id, name, query, tags, filters, sort, created_at, updated_at
+from saved_searches
+order by name
+"
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|r| r.try_into())
+    .collect()
+}
+
+pub async fn find_by_id(id: SavedSearchId, conn: &mut SqliteConnection) -> Result<Option<SavedSearch>> {
+    sqlx::query_as!(
+        SavedSearchRow,
+        r"
+select id, name, query, tags, filters, sort, created_at, updated_at
+from saved_searches
+where id = $1
+",
+        id,
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(|r| r.try_into())
+    .transpose()
+}
+
+/// Replaces the documents matching the saved search, e.g. after the query is changed.
+pub async fn replace_matches<P: AsRef<Path>>(
+    id: SavedSearchId,
+    documents: &[(P, WatchId)],
+    conn: &mut SqliteConnection,
+) -> Result<()> {
+    sqlx::query!(
+        r"
+delete from saved_search_matches where saved_search_id = $1
+",
+        id,
+    )
+    .execute(&mut *conn)
+    .await?;
+    for (path, watch_id) in documents {
+        insert_match(id, path, *watch_id, &mut *conn).await?;
+    }
+    Ok(())
+}
+
+/// Records that the document matches the saved search. Returns false if it already matched.
+pub async fn insert_match<P: AsRef<Path>>(
+    id: SavedSearchId,
+    path: P,
+    watch_id: WatchId,
+    conn: &mut SqliteConnection,
+) -> Result<bool> {
+    let path_string = path.as_ref().to_normalized_path_string();
+    let result = sqlx::query!(
+        r"
+insert into saved_search_matches (saved_search_id, watch_id, path)
+values ($1, $2, $3)
+on conflict (saved_search_id, path) do nothing
+",
+        id,
+        watch_id,
+        path_string,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_match<P: AsRef<Path>>(id: SavedSearchId, path: P, conn: &mut SqliteConnection) -> Result<()> {
+    let path_string = path.as_ref().to_normalized_path_string();
+    sqlx::query!(
+        r"
+delete from saved_search_matches where saved_search_id = $1 and path = $2
+",
+        id,
+        path_string,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_matches_by_path<P: AsRef<Path>>(path: P, conn: &mut SqliteConnection) -> Result<()> {
+    let path_string = path.as_ref().to_normalized_path_string();
+    sqlx::query!(
+        r"
+delete from saved_search_matches where path = $1
+",
+        path_string,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_matches_by_watch_id(watch_id: WatchId, conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query!(
+        r"
+delete from saved_search_matches where watch_id = $1
+",
+        watch_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the paths matching the saved search in alphabetical order.
+pub async fn find_matches(id: SavedSearchId, conn: &mut SqliteConnection) -> Result<Vec<String>> {
+    let rows = sqlx::query!(
+        r"
+select path
+from saved_search_matches
+where saved_search_id = $1
+order by path
+",
+        id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|x| x.path).collect())
+}
+
+fn join_tags(tags: &[String]) -> String {
+    tags.join("\n")
+}
+
+fn split_tags(tags: &str) -> Vec<String> {
+    tags.lines().filter(|x| !x.is_empty()).map(|x| x.to_string()).collect()
+}
+
+struct SavedSearchRow {
+    id: i64,
+    name: String,
+    query: String,
+    tags: String,
+    filters: String,
+    sort: String,
+    created_at: chrono::NaiveDateTime,
+    updated_at: chrono::NaiveDateTime,
+}
+
+impl TryFrom<SavedSearchRow> for SavedSearch {
+    type Error = anyhow::Error;
+
+    fn try_from(row: SavedSearchRow) -> Result<Self> {
+        Ok(Self {
+            id: SavedSearchId(row.id),
+            name: row.name,
+            query: row.query,
+            tags: split_tags(&row.tags),
+            filters: row.filters,
+            sort: row.sort.parse()?,
+            created_at: chrono::DateTime::from_utc(row.created_at, Utc),
+            updated_at: chrono::DateTime::from_utc(row.updated_at, Utc),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path;
+
+    use anyhow::Result;
+    use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+
+    use super::*;
+    use crate::{
+        documents::{Sort, SortOrder},
+        watches::watch_repository,
+    };
+
+    async fn init() -> Result<(tempfile::TempDir, SqlitePool)> {
+        let db_dir = tempfile::tempdir()?;
+        let connection = SqlitePoolOptions::new()
+            .connect(format!("sqlite://{}/jomai.db?mode=rwc", db_dir.path().display()).as_str())
+            .await?;
+        sqlx::migrate!().run(&connection).await?;
+        Ok((db_dir, connection))
+    }
+
+    fn incidents() -> NewSavedSearch {
+        NewSavedSearch {
+            name: "Open incidents".to_string(),
+            query: "status open".to_string(),
+            tags: vec!["incident".to_string(), "ops".to_string()],
+            filters: "modified:this-month".to_string(),
+            sort: Sort::ModifiedAt(SortOrder::Desc),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insert_and_update() -> Result<()> {
+        let (_db_dir, pool) = init().await?;
+        let mut conn = pool.acquire().await?;
+
+        let saved_search = insert(&incidents(), &mut *conn).await?;
+        assert_eq!(saved_search.name, "Open incidents");
+        assert_eq!(saved_search.tags, vec!["incident".to_string(), "ops".to_string()]);
+        assert_eq!(saved_search.sort, Sort::ModifiedAt(SortOrder::Desc));
+
+        let changed = NewSavedSearch {
+            tags: vec![],
+            sort: Sort::Relevance,
+            ..incidents()
+        };
+        let updated = update(saved_search.id, &changed, &mut *conn).await?.unwrap();
+        assert_eq!(updated.tags, Vec::<String>::new());
+        assert_eq!(updated.sort, Sort::Relevance);
+        assert_eq!(find_all(&mut *conn).await?.len(), 1);
+
+        delete(saved_search.id, &mut *conn).await?;
+        assert!(find_by_id(saved_search.id, &mut *conn).await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_matches() -> Result<()> {
+        let (_db_dir, pool) = init().await?;
+        let mut conn = pool.acquire().await?;
+
+        let watch = watch_repository::insert(path::PathBuf::from("/foo"), &mut *conn).await?;
+        let saved_search = insert(&incidents(), &mut *conn).await?;
+        replace_matches(saved_search.id, &[("/foo/a.md", watch.id)], &mut *conn).await?;
+
+        assert!(!insert_match(saved_search.id, "/foo/a.md", watch.id, &mut *conn).await?);
+        assert!(insert_match(saved_search.id, "/foo/b.md", watch.id, &mut *conn).await?);
+        assert_eq!(
+            find_matches(saved_search.id, &mut *conn).await?,
+            vec!["/foo/a.md".to_string(), "/foo/b.md".to_string()]
+        );
+
+        delete_match(saved_search.id, "/foo/a.md", &mut *conn).await?;
+        delete_matches_by_path("/foo/b.md", &mut *conn).await?;
+        assert!(find_matches(saved_search.id, &mut *conn).await?.is_empty());
+
+        insert_match(saved_search.id, "/foo/c.md", watch.id, &mut *conn).await?;
+        delete_matches_by_watch_id(watch.id, &mut *conn).await?;
+        assert!(find_matches(saved_search.id, &mut *conn).await?.is_empty());
+        Ok(())
+    }
+}
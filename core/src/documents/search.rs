@@ -2,37 +2,79 @@ use std::{
     collections::HashMap,
     fmt::Formatter,
     fs,
-    path::{Component, Path},
-    sync::{Arc, Mutex},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
 };
 
 use anyhow::Result;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tantivy::{
-    collector::{Collector, Count, Fruit, TopDocs},
+    collector::{Collector, Count, DocSetCollector, FilterCollector, TopDocs},
     doc,
-    query::{AllQuery, Query, QueryParser, RegexQuery, TermQuery},
+    query::{AllQuery, BooleanQuery, Occur, Query, QueryParser, QueryParserError, RegexQuery, TermQuery},
     schema::*,
-    DocAddress, Index, IndexReader, IndexWriter, ReloadPolicy, Searcher, Snippet, SnippetGenerator,
+    DocAddress, DocId, Index, IndexReader, IndexWriter, ReloadPolicy, Score, Searcher, SegmentReader,
 };
 
 use crate::{
-    documents::{file::get_file_metadata, markdown, search::schema::AppSchema},
+    documents::{
+        annotations::DocumentAnnotation,
+        file::get_file_metadata,
+        markdown,
+        search::{
+            cancellation::{CancellableCollector, Cancellation},
+            facets::Facets,
+            highlight::{Highlights, MatchedField},
+            pagination::{After, CursorKey, Page, Snapshots},
+            query_expansion::QueryExpansion,
+            query_syntax::{ParsedQuery, QuerySyntaxError},
+            schema::AppSchema,
+            snippet::{HighlightRange, SnippetFragment, SnippetOptions},
+            user_dictionary::UserDictionary,
+        },
+    },
     path_string_normalization::PathStringNormalizationExt,
+    settings::{RankingSettings, RecencySettings},
     WatchId,
 };
 
+use sort::{date_values, number_values, TextSortField};
+pub use sort::{Sort, SortOrder};
+
+pub mod analysis;
+pub mod cancellation;
+pub mod details;
 mod en_tokenizer;
+pub mod facets;
+mod fuzzy;
+pub mod grep;
+pub mod highlight;
+mod index_version;
 pub mod index_writer;
 mod ja_tokenizer;
 mod japanese_character_normalizer;
+mod literal;
+mod matching;
+mod nfkc_normalizer;
 mod ngram_tokenizer;
+pub mod pagination;
+pub mod prefix;
+pub mod query_expansion;
+pub mod query_syntax;
+pub mod related;
 mod schema;
+pub mod snippet;
+pub mod sort;
+mod spelling_tokenizer;
 mod stopwords;
+pub mod user_dictionary;
 
 const RESULT_COUNT: usize = 10;
+/// Spelling suggestions are returned when a query hits fewer documents than this
+const SUGGESTION_THRESHOLD: usize = 3;
 
-#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+#[derive(Serialize, Debug, Copy, Clone, Hash, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum Language {
     English = 1,
     Japanese = 2,
@@ -56,16 +98,54 @@ impl From<u64> for Language {
     }
 }
 
-#[derive(Debug)]
-pub enum Sort {
-    Relevance,
-    Date,
+/// How the query syntax is interpreted
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryMode {
+    /// Searches for the words as they are typed, without the query syntax
+    Literal,
+    /// Uses the query syntax if the query is valid, otherwise searches for the words as they are typed
+    #[default]
+    Smart,
+    /// Uses the query syntax and returns an error if the query is invalid
+    Advanced,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SearchOptions {
+    /// Also matches words with typos
+    pub fuzzy: bool,
+    /// Treats the last term as a prefix of a word, for searching while typing
+    pub prefix: bool,
+    /// Approximate number of characters of a snippet fragment
+    pub fragment_length: Option<usize>,
+    /// Maximum number of snippet fragments per document
+    pub max_fragments: Option<usize>,
+    /// `QueryMode::Smart` if not specified
+    pub query_mode: Option<QueryMode>,
+    /// `SearchResults::cursor` of the previous page. The offset is ignored if this is given.
+    pub cursor: Option<String>,
+}
+
+impl SearchOptions {
+    fn snippet_options(&self) -> SnippetOptions {
+        SnippetOptions {
+            fragment_length: self.fragment_length.unwrap_or(snippet::DEFAULT_FRAGMENT_LENGTH),
+            max_fragments: self.max_fragments.unwrap_or(snippet::DEFAULT_MAX_FRAGMENTS),
+        }
+    }
 }
 
 #[derive(Serialize, Debug)]
 pub struct SearchResults {
     pub count: usize,
     pub documents: Vec<SearchResultDocument>,
+    /// Corrected queries when the query hits few documents
+    pub suggestions: Vec<String>,
+    /// Numbers of all the matching documents by watch, folder and month
+    pub facets: Facets,
+    /// Pass this to get the next page from the same snapshot of the index. `None` on the last page.
+    pub cursor: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
@@ -74,17 +154,47 @@ pub struct SearchResultDocument {
     pub title: Option<String>,
     pub tags: Vec<String>,
     pub watch_id: i64,
+    /// `snippets` rendered as HTML
     pub highlight: Option<String>,
+    pub snippets: Vec<SnippetFragment>,
+    /// `title` with the matched parts in `<b>` tags, if any
+    #[serde(rename = "highlightedTitle")]
+    pub highlighted_title: Option<String>,
+    #[serde(rename = "titleHighlights")]
+    pub title_highlights: Vec<HighlightRange>,
+    /// `path` with the matched parts in `<b>` tags, if any
+    #[serde(rename = "highlightedPath")]
+    pub highlighted_path: Option<String>,
+    #[serde(rename = "pathHighlights")]
+    pub path_highlights: Vec<HighlightRange>,
+    /// Fields where the query terms appear, to tell why the document matched
+    #[serde(rename = "matchedFields")]
+    pub matched_fields: Vec<MatchedField>,
     #[serde(rename = "createdAt")]
     pub created_at: chrono::DateTime<chrono::Utc>,
     #[serde(rename = "modifiedAt")]
     pub modified_at: chrono::DateTime<chrono::Utc>,
+    /// From the annotation of the document
+    pub pinned: bool,
+    pub labels: Vec<String>,
+    pub note: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct Search {
     index: Index,
     index_reader: IndexReader,
+    query_expansion: Arc<QueryExpansion>,
+    ranking: Arc<RankingSettings>,
+    watch_priorities: Arc<RwLock<HashMap<WatchId, f32>>>,
+    /// Directories of the watches, to find the top-level folders of documents
+    watch_paths: Arc<RwLock<HashMap<WatchId, PathBuf>>>,
+    cancellation: Cancellation,
+    /// How many times each document was opened from the results of the query being searched
+    opened_counts: Arc<HashMap<String, u32>>,
+    /// Annotations of the documents keyed by path, to filter and return them with the results
+    annotations: Arc<HashMap<String, DocumentAnnotation>>,
+    snapshots: Arc<Snapshots>,
 }
 
 impl std::fmt::Debug for Search {
@@ -94,24 +204,130 @@ impl std::fmt::Debug for Search {
 }
 
 impl Search {
-    fn new(index: Index) -> Result<(Self, IndexWriter)> {
+    fn new(index: Index, query_expansion: QueryExpansion, ranking: RankingSettings) -> Result<(Self, IndexWriter)> {
         let index_reader = index
             .reader_builder()
             .reload_policy(ReloadPolicy::OnCommit)
             .try_into()?;
         let index_writer = index.writer(100_000_000)?;
-        Ok((Self { index, index_reader }, index_writer))
+        Ok((
+            Self {
+                index,
+                index_reader,
+                query_expansion: Arc::new(query_expansion),
+                ranking: Arc::new(ranking),
+                watch_priorities: Arc::new(RwLock::new(HashMap::new())),
+                watch_paths: Arc::new(RwLock::new(HashMap::new())),
+                cancellation: Cancellation::default(),
+                opened_counts: Arc::new(HashMap::new()),
+                annotations: Arc::new(HashMap::new()),
+                snapshots: Arc::new(Snapshots::default()),
+            },
+            index_writer,
+        ))
     }
 
-    pub fn open_index<P: AsRef<Path>>(index_dir: P) -> Result<(Self, IndexWriter)> {
+    pub fn open_index<P: AsRef<Path>>(
+        index_dir: P,
+        user_dictionary: &UserDictionary,
+        query_expansion: QueryExpansion,
+        ranking: RankingSettings,
+    ) -> Result<(Self, IndexWriter)> {
         let index_dir = index_dir.as_ref();
         let index =
             Index::open_in_dir(index_dir).or_else(|_| Index::create_in_dir(index_dir, schema::define_schema()))?;
+        index_version::write(index_dir)?;
         en_tokenizer::setup_tokenizer(&index)?;
-        ja_tokenizer::setup_tokenizer(&index)?;
+        ja_tokenizer::setup_tokenizer(&index, user_dictionary)?;
         ngram_tokenizer::setup_tokenizer(&index)?;
+        spelling_tokenizer::setup_tokenizer(&index)?;
         // index.writer(50_000_000)?.commit()?;
-        Self::new(index)
+        Self::new(index, query_expansion, ranking)
+    }
+
+    pub fn set_watch_priority(&self, watch_id: WatchId, priority: f32) {
+        self.watch_priorities.write().unwrap().insert(watch_id, priority);
+    }
+
+    pub fn set_watch_path<P: AsRef<Path>>(&self, watch_id: WatchId, path: P) {
+        self.watch_paths
+            .write()
+            .unwrap()
+            .insert(watch_id, path.as_ref().to_path_buf());
+    }
+
+    /// Forgets the path and the priority of the deleted watch.
+    pub fn remove_watch<P: AsRef<Path>>(&self, path: P) {
+        let mut watch_paths = self.watch_paths.write().unwrap();
+        let mut watch_priorities = self.watch_priorities.write().unwrap();
+        watch_paths.retain(|watch_id, x| {
+            let is_removed = x == path.as_ref();
+            if is_removed {
+                watch_priorities.remove(watch_id);
+            }
+            !is_removed
+        });
+    }
+
+    /// Returns true if the file is in one of the watches.
+    /// `..` and symbolic links are resolved, so that they can't lead to the files outside of the watches.
+    pub fn is_in_watch<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = match fs::canonicalize(path) {
+            Ok(x) => x,
+            Err(_) => return false,
+        };
+        self.watch_paths
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|x| fs::canonicalize(x).ok())
+            .any(|x| path.starts_with(x))
+    }
+
+    /// Returns a search which stops when the cancellation is requested and returns `SearchCancelled`.
+    pub fn with_cancellation(&self, cancellation: Cancellation) -> Self {
+        Search {
+            cancellation,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the search boosting the documents often opened for the query, keyed by path.
+    pub fn with_opened_counts(&self, opened_counts: HashMap<String, u32>) -> Self {
+        Search {
+            opened_counts: Arc::new(opened_counts),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the search with the annotations, which are kept in the database instead of the index.
+    pub fn with_annotations(&self, annotations: Arc<HashMap<String, DocumentAnnotation>>) -> Self {
+        Search {
+            annotations,
+            ..self.clone()
+        }
+    }
+
+    /// Copies the annotation of the document into the result.
+    pub(super) fn annotate(&self, document: &mut SearchResultDocument) {
+        if let Some(annotation) = self.annotations.get(&document.path) {
+            document.pinned = annotation.pinned;
+            document.labels = annotation.labels.clone();
+            document.note = annotation.note.clone();
+        }
+    }
+
+    fn cancellable<C: Collector>(&self, collector: C) -> CancellableCollector<C> {
+        CancellableCollector::new(collector, self.cancellation.clone())
+    }
+
+    /// Returns true if the index in the directory was built by an older version and has to be rebuilt.
+    pub fn is_index_outdated<P: AsRef<Path>>(index_dir: P) -> Result<bool> {
+        let index_dir = index_dir.as_ref();
+        if !index_dir.join("meta.json").exists() {
+            return Ok(false);
+        }
+        Ok(index_version::read(index_dir)? != Some(index_version::INDEX_VERSION))
     }
 
     pub(super) fn make_document<P: AsRef<Path>>(&self, path: P, watch_id: WatchId) -> Result<tantivy::Document> {
@@ -139,11 +355,26 @@ impl Search {
             document.add_text(schema.path_components(), text);
         }
         document.add_text(schema.path_ngram(), &normalized_path);
+        if let Some(parent) = path.parent() {
+            document.add_facet(schema.directory(), Facet::from_path(path_component_strings(parent)));
+        }
+        let watch_path = self.watch_paths.read().unwrap().get(&watch_id).cloned();
+        document.add_u64(
+            schema.path_sort(),
+            sort::path_sort_key_prefix(path, watch_path.as_deref()),
+        );
         document.add_u64(schema.language(), language as u64);
         document.add_text(schema.title_ngram(), &title);
         document.add_text(schema.title_for_language(language), &title);
+        document.add_u64(schema.title_sort(), sort::sort_key_prefix(&title));
         document.add_text(schema.contents_ngram(), &body);
+        let body_line = contents[..contents.len() - body.len()].matches('\n').count() as u64 + 1;
+        document.add_u64(schema.body_line(), body_line);
         document.add_text(schema.contents_for_language(language), &body);
+        if language == Language::English {
+            document.add_text(schema.spelling(), &title);
+            document.add_text(schema.spelling(), &body);
+        }
         document.add_date(
             schema.created_at(),
             tantivy::DateTime::from_timestamp_secs(file_metadata.created_at.timestamp()),
@@ -154,11 +385,26 @@ impl Search {
         );
         document.add_i64(schema.watch_id(), watch_id.0);
 
+        // frontmatter のフィールドは表示用なので、値を解釈できなくても索引付けは続ける
+        let frontmatter_fields = frontmatter::parse_fields(&contents)
+            .map(|x| x.fields)
+            .unwrap_or_default();
+        details::add_details(
+            &schema,
+            &mut document,
+            &details::analyze_markdown(body, body_line),
+            &frontmatter_fields,
+            file_metadata.size,
+        );
+
         if let Some(frontmatter) = frontmatter {
             if let Some(tags) = frontmatter.tags {
                 for tag in tags {
                     document.add_text(schema.tag_for_language(language), &tag);
                     document.add_text(schema.tag_ngram(), &tag);
+                    if language == Language::English {
+                        document.add_text(schema.spelling(), &tag);
+                    }
                 }
             }
         }
@@ -171,18 +417,37 @@ impl Search {
         query: &str,
         tags: &[&str],
         sort: Sort,
+        options: &SearchOptions,
         offset: usize,
         limit: usize,
     ) -> Result<SearchResults> {
-        let query = query.trim();
-        let tag_query = self.make_tag_query(tags)?;
-        let mut parts = vec![];
-        if !query.is_empty() {
-            parts.push(query);
+        let (built_query, parsed_query) = self.build_query_with_tags(query, tags, options)?;
+        let mut results = self.do_search(built_query, &parsed_query.text, sort, options, offset, limit)?;
+        results.suggestions = with_filters(&parsed_query, results.suggestions);
+        Ok(results)
+    }
+
+    /// Builds the query typed by the user, narrowed down to the documents with all the tags.
+    fn build_query_with_tags(
+        &self,
+        query: &str,
+        tags: &[&str],
+        options: &SearchOptions,
+    ) -> Result<(Box<dyn Query>, ParsedQuery)> {
+        if tags.is_empty() {
+            return self.build_user_query(query, options);
         }
-        parts.push(&tag_query);
-        let q = parts.join(" AND ");
-        self.search_document(&q, sort, offset, limit)
+        let tag_query = self.make_tag_query(tags)?;
+        let mut clauses = vec![];
+        let parsed_query = if query.trim().is_empty() {
+            ParsedQuery::from_text("")
+        } else {
+            let (query, parsed_query) = self.build_user_query(query.trim(), options)?;
+            clauses.push((Occur::Must, query));
+            parsed_query
+        };
+        clauses.push((Occur::Must, self.build_query(&tag_query, &SearchOptions::default())?));
+        Ok((Box::new(BooleanQuery::new(clauses)), parsed_query))
     }
 
     fn make_tag_query(&self, tags: &[&str]) -> Result<String> {
@@ -205,105 +470,384 @@ impl Search {
         &self,
         query: &str,
         sort: Sort,
+        options: &SearchOptions,
         offset: usize,
         limit: usize,
     ) -> Result<SearchResults> {
-        let query = self.build_query(query)?;
-        match sort {
-            Sort::Relevance => self.do_query_and_build_results(query, TopDocs::with_limit(limit).and_offset(offset)),
-            Sort::Date => self.do_query_and_build_results(
-                query,
-                TopDocs::with_limit(limit)
-                    .and_offset(offset)
-                    .order_by_fast_field::<tantivy::DateTime>(AppSchema::new(self.index.schema()).modified_at()),
-            ),
+        let (query, parsed_query) = self.build_user_query(query, options)?;
+        let mut results = self.do_search(query, &parsed_query.text, sort, options, offset, limit)?;
+        results.suggestions = with_filters(&parsed_query, results.suggestions);
+        Ok(results)
+    }
+
+    /// Builds the query typed by the user in the query mode.
+    /// Only `QueryMode::Advanced` returns `QuerySyntaxError` when the query is invalid.
+    fn build_user_query(&self, query: &str, options: &SearchOptions) -> Result<(Box<dyn Query>, ParsedQuery)> {
+        let literal_options = SearchOptions {
+            query_mode: Some(QueryMode::Literal),
+            ..options.clone()
+        };
+        let now = chrono::Local::now();
+        match options.query_mode.unwrap_or_default() {
+            QueryMode::Literal => Ok((
+                self.build_query(query, &literal_options)?,
+                ParsedQuery::from_text(query),
+            )),
+            QueryMode::Smart => {
+                if let Ok(parsed_query) = query_syntax::parse(query, &now) {
+                    if let Ok(built_query) = self.build_parsed_query(&parsed_query, options) {
+                        return Ok((built_query, parsed_query));
+                    }
+                    // フィルタは活かして、残りの語だけ入力どおりに検索する
+                    if let Ok(built_query) = self.build_parsed_query(&parsed_query, &literal_options) {
+                        return Ok((built_query, parsed_query));
+                    }
+                }
+                tracing::debug!("searching for the literal query: {}", query);
+                Ok((
+                    self.build_query(query, &literal_options)?,
+                    ParsedQuery::from_text(query),
+                ))
+            }
+            QueryMode::Advanced => {
+                let parsed_query = query_syntax::parse(query, &now)?;
+                match self.build_parsed_query(&parsed_query, options) {
+                    Ok(built_query) => Ok((built_query, parsed_query)),
+                    Err(e) => match e.downcast::<QueryParserError>() {
+                        Ok(e) => Err(QuerySyntaxError::invalid_syntax(query, e).into()),
+                        Err(e) => Err(e),
+                    },
+                }
+            }
         }
     }
 
-    fn build_query(&self, query: &str) -> Result<Box<dyn Query>> {
-        let schema = AppSchema::new(self.index.schema());
+    /// Combines the free text and the field filters of the query.
+    fn build_parsed_query(&self, parsed_query: &ParsedQuery, options: &SearchOptions) -> Result<Box<dyn Query>> {
+        if parsed_query.filters.is_empty() {
+            return self.build_query(&parsed_query.text, options);
+        }
+        let mut clauses = vec![];
+        if !parsed_query.text.trim().is_empty() {
+            clauses.push((Occur::Must, self.build_query(&parsed_query.text, options)?));
+        }
+        for filter in &parsed_query.filters {
+            let occur = if filter.negated { Occur::MustNot } else { Occur::Must };
+            clauses.push((occur, self.build_filter_query(&filter.condition)?));
+        }
+        // 除外条件だけのクエリは何にもマッチしないので、全文書から除外する
+        if clauses.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+            clauses.push((Occur::Must, Box::new(AllQuery)));
+        }
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
 
-        let mut fields = vec![schema.path(), schema.path_components(), schema.path_ngram()];
-        fields.extend_from_slice(&schema.contents_all());
-        fields.extend_from_slice(&schema.title_all());
-        fields.extend_from_slice(&schema.tag_all());
-        let mut query_parser = QueryParser::for_index(&self.index, fields);
-        query_parser.set_conjunction_by_default();
+    /// `query_text` is the query typed by the user, used for spelling suggestions
+    fn do_search(
+        &self,
+        query: Box<dyn Query>,
+        query_text: &str,
+        sort: Sort,
+        options: &SearchOptions,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchResults> {
+        let snippet_options = options.snippet_options();
+        let search_hash = pagination::search_hash(&*query, sort);
+        let page = self.page(offset, limit, options.cursor.as_deref(), search_hash)?;
+        let mut results = self.do_sorted_search(query, sort, snippet_options, page)?;
+        self.cancellation.check()?;
+        if results.count < SUGGESTION_THRESHOLD && !query_text.trim().is_empty() {
+            results.suggestions = self.suggest(query_text)?;
+        }
+        Ok(results)
+    }
 
-        const BOOST_LITTLE: f32 = 0.5;
-        const BOOST_NORMAL: f32 = 1.0;
-        const BOOST_MUCH: f32 = 5.0;
-        const BOOST_MORE: f32 = 8.0;
-        const BOOST_MOST: f32 = 10.0;
+    fn do_sorted_search(
+        &self,
+        query: Box<dyn Query>,
+        sort: Sort,
+        snippet_options: SnippetOptions,
+        page: Page,
+    ) -> Result<SearchResults> {
+        let schema = AppSchema::new(self.index.schema());
+        // カーソルがあれば前のページまでの文書はスコアで除外されるので、offset は使わない
+        let top_docs = if page.has_cursor() {
+            TopDocs::with_limit(page.limit)
+        } else {
+            TopDocs::with_limit(page.limit).and_offset(page.offset)
+        };
+        match sort {
+            Sort::Relevance => {
+                let scorer = self.ranking_score_tweaker(&page.searcher, page.ranking_time, page.after_score()?)?;
+                self.do_query_and_build_results(
+                    query,
+                    snippet_options,
+                    page,
+                    top_docs.tweak_score(scorer),
+                    |x: Score| (x != Score::NEG_INFINITY).then_some(CursorKey::Score(x)),
+                )
+            }
+            Sort::ModifiedAt(order) | Sort::CreatedAt(order) => {
+                let field = match sort {
+                    Sort::ModifiedAt(_) => schema.modified_at(),
+                    _ => schema.created_at(),
+                };
+                let scorer =
+                    Self::fast_field_sort_scorer(&page.searcher, order, page.after_date()?, date_values(field));
+                self.do_query_and_build_results(
+                    query,
+                    snippet_options,
+                    page,
+                    top_docs.custom_score(scorer),
+                    |x: i64| (x != i64::MIN).then_some(CursorKey::Date(x)),
+                )
+            }
+            Sort::WordCount(order) | Sort::ReadingTime(order) => {
+                let field = match sort {
+                    Sort::WordCount(_) => schema.word_count(),
+                    _ => schema.reading_time(),
+                };
+                let scorer =
+                    Self::fast_field_sort_scorer(&page.searcher, order, page.after_number()?, number_values(field));
+                self.do_query_and_build_results(
+                    query,
+                    snippet_options,
+                    page,
+                    top_docs.custom_score(scorer),
+                    |x: i64| (x != i64::MIN).then_some(CursorKey::Number(x)),
+                )
+            }
+            Sort::Title(order) => self.search_sorted_by_text(query, TextSortField::Title, order, snippet_options, page),
+            Sort::Path(order) => self.search_sorted_by_text(query, TextSortField::Path, order, snippet_options, page),
+        }
+    }
 
-        query_parser.set_field_boost(schema.path(), BOOST_MOST);
-        query_parser.set_field_boost(schema.path_components(), BOOST_MORE);
-        query_parser.set_field_boost(schema.path_ngram(), BOOST_LITTLE);
-        query_parser.set_field_boost(schema.path_components(), BOOST_MOST);
-        for field in schema.title_all_languages() {
-            query_parser.set_field_boost(field, BOOST_MOST);
+    fn build_query(&self, query: &str, options: &SearchOptions) -> Result<Box<dyn Query>> {
+        if options.prefix {
+            if let Some((head, last_term)) = prefix::split_last_term(query) {
+                let options = SearchOptions {
+                    prefix: false,
+                    ..options.clone()
+                };
+                let mut last_term_clauses = vec![(Occur::Should, self.build_query(last_term, &options)?)];
+                if let Some(prefix_query) = self.build_prefix_query(last_term)? {
+                    last_term_clauses.push((Occur::Should, prefix_query));
+                }
+                let mut clauses = vec![];
+                if !head.trim().is_empty() {
+                    clauses.push((Occur::Must, self.build_query(head, &options)?));
+                }
+                clauses.push((
+                    Occur::Must,
+                    Box::new(BooleanQuery::new(last_term_clauses)) as Box<dyn Query>,
+                ));
+                return Ok(Box::new(BooleanQuery::new(clauses)));
+            }
         }
-        query_parser.set_field_boost(schema.title_ngram(), BOOST_MUCH);
-        for field in schema.contents_all_languages() {
-            query_parser.set_field_boost(field, BOOST_NORMAL);
+
+        let fields = self.default_fields_with_boosts();
+        let parsed_query = match options.query_mode {
+            // エスケープしてパーサーに渡すと、- や AND などの演算子が残るのでトークンから組み立てる
+            Some(QueryMode::Literal) => self.build_literal_query(query, &fields)?,
+            _ => {
+                let mut query_parser =
+                    QueryParser::for_index(&self.index, fields.iter().map(|(field, _)| *field).collect());
+                query_parser.set_conjunction_by_default();
+                for (field, boost) in &fields {
+                    query_parser.set_field_boost(*field, *boost);
+                }
+                query_parser.parse_query(&self.query_expansion.expand(query))?
+            }
+        };
+        if !options.fuzzy {
+            return Ok(parsed_query);
         }
-        query_parser.set_field_boost(schema.contents_ngram(), BOOST_LITTLE);
-        for field in schema.tag_all_languages() {
-            query_parser.set_field_boost(field, BOOST_MORE);
+        match self.build_fuzzy_query(query)? {
+            Some(fuzzy_query) => Ok(Box::new(BooleanQuery::new(vec![
+                (Occur::Should, parsed_query),
+                (Occur::Should, fuzzy_query),
+            ]))),
+            None => Ok(parsed_query),
         }
-        query_parser.set_field_boost(schema.tag_ngram(), BOOST_MUCH);
-        // Ok(query_parser.parse_query(&escape_query(query))?)
-        Ok(query_parser.parse_query(query)?)
     }
 
-    fn do_query_and_build_results<C, F, K>(&self, query: Box<dyn Query>, doc_collector: C) -> Result<SearchResults>
-    where
-        C: Collector<Fruit = F>,
-        F: Fruit + IntoIterator<Item = (K, DocAddress)>,
-    {
+    /// Returns the fields searched for the words of the query with their boosts.
+    fn default_fields_with_boosts(&self) -> Vec<(Field, f32)> {
         let schema = AppSchema::new(self.index.schema());
+        let boosts = &self.ranking.field_boosts;
+        let mut fields = vec![
+            (schema.path(), boosts.path),
+            (schema.path_components(), boosts.path_components),
+            (schema.path_ngram(), boosts.path_ngram),
+        ];
+        fields.extend(self.language_fields_with_boosts());
+        fields.push((schema.title_ngram(), boosts.title_ngram));
+        fields.push((schema.contents_ngram(), boosts.contents_ngram));
+        fields.push((schema.tag_ngram(), boosts.tag_ngram));
+        fields
+    }
 
-        let searcher = self.index_reader.searcher();
-        let (count, top_docs) = searcher.search(&query, &(Count, doc_collector))?;
+    /// Returns the title, contents and tag fields analyzed for each language with their boosts.
+    fn language_fields_with_boosts(&self) -> Vec<(Field, f32)> {
+        let schema = AppSchema::new(self.index.schema());
+        let boosts = &self.ranking.field_boosts;
+        let mut fields = vec![];
+        fields.extend(schema.title_all_languages().into_iter().map(|x| (x, boosts.title)));
+        fields.extend(
+            schema
+                .contents_all_languages()
+                .into_iter()
+                .map(|x| (x, boosts.contents)),
+        );
+        fields.extend(schema.tag_all_languages().into_iter().map(|x| (x, boosts.tag)));
+        fields
+    }
 
-        let mut snippet_generator_map: HashMap<Language, SnippetGenerator> = HashMap::new();
+    /// Multiplies the relevance score by the watch priority, the recency boost and the opened boost.
+    /// Documents in the previous pages get `Score::NEG_INFINITY`.
+    fn ranking_score_tweaker(
+        &self,
+        searcher: &Searcher,
+        ranking_time: i64,
+        after: Option<After<Score>>,
+    ) -> Result<impl Fn(&SegmentReader) -> Box<dyn FnMut(DocId, Score) -> Score> + Send + Sync + 'static> {
+        let schema = AppSchema::new(self.index.schema());
+        let watch_id_field = schema.watch_id();
+        let modified_at_field = schema.modified_at();
+        let watch_priorities: HashMap<i64, f32> = self
+            .watch_priorities
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(watch_id, priority)| (watch_id.0, *priority))
+            .collect();
+        let recency = self.ranking.recency;
+        let opened_weight = self.ranking.opened.weight;
+        let now = ranking_time;
+        let segment_ords = pagination::segment_ordinals(searcher);
+        // 開かれた文書は検索ごとに数件しかないので、先にアドレスを引いておく
+        let mut opened_boosts: HashMap<DocAddress, f32> = HashMap::new();
+        if opened_weight > 0.0 {
+            for (path, count) in self.opened_counts.iter() {
+                if let Some(doc_address) = self.find_doc_address_by_path(searcher, path)? {
+                    opened_boosts.insert(doc_address, opened_boost(opened_weight, *count));
+                }
+            }
+        }
+
+        Ok(
+            move |segment_reader: &SegmentReader| -> Box<dyn FnMut(DocId, Score) -> Score> {
+                let watch_id_reader = segment_reader.fast_fields().i64(watch_id_field).unwrap();
+                let modified_at_reader = segment_reader.fast_fields().date(modified_at_field).unwrap();
+                let watch_priorities = watch_priorities.clone();
+                let opened_boosts = opened_boosts.clone();
+                let segment_ord = segment_ords[&segment_reader.segment_id()];
+                Box::new(move |doc: DocId, score: Score| {
+                    let priority = watch_priorities
+                        .get(&watch_id_reader.get_val(doc))
+                        .copied()
+                        .unwrap_or(1.0);
+                    let recency_boost =
+                        recency_boost(recency, now, modified_at_reader.get_val(doc).into_timestamp_secs());
+                    let doc_address = DocAddress::new(segment_ord, doc);
+                    let opened_boost = opened_boosts.get(&doc_address).copied().unwrap_or(1.0);
+                    let score = score * priority * recency_boost * opened_boost;
+                    match after {
+                        Some(after) if after.covers(score, doc_address) => Score::NEG_INFINITY,
+                        _ => score,
+                    }
+                })
+            },
+        )
+    }
+
+    /// `cursor_key` returns `None` for the documents in the previous pages.
+    fn do_query_and_build_results<C, K>(
+        &self,
+        query: Box<dyn Query>,
+        snippet_options: SnippetOptions,
+        page: Page,
+        doc_collector: C,
+        cursor_key: fn(K) -> Option<CursorKey>,
+    ) -> Result<SearchResults>
+    where
+        C: Collector<Fruit = Vec<(K, DocAddress)>>,
+    {
+        let collector = self.cancellable((Count, doc_collector, self.facets_collector()));
+        let (count, top_docs, facets) = page.searcher.search(&query, &collector)?;
+        self.cancellation.check()?;
+        let top_docs: Vec<(CursorKey, DocAddress)> = top_docs
+            .into_iter()
+            .filter_map(|(key, doc_address)| cursor_key(key).map(|x| (x, doc_address)))
+            .collect();
+        let mut results = self.build_results(
+            &page.searcher,
+            &*query,
+            count,
+            top_docs.iter().map(|(_, doc_address)| *doc_address),
+            facets,
+            snippet_options,
+        )?;
+        results.cursor = page
+            .next_cursor(count, top_docs.len(), top_docs.last().copied())
+            .map(|x| self.encode_cursor(&page, x));
+        Ok(results)
+    }
 
-        let snippet_generator_ngram = SnippetGenerator::create(&searcher, &*query, schema.contents_ngram())?;
+    fn build_results<I: IntoIterator<Item = DocAddress>>(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        count: usize,
+        doc_addresses: I,
+        facets: Facets,
+        snippet_options: SnippetOptions,
+    ) -> Result<SearchResults> {
+        let schema = AppSchema::new(self.index.schema());
+        let query_terms = self.query_terms(searcher, query)?;
 
         let mut documents = Vec::new();
 
-        for (_, doc_address) in top_docs {
+        for doc_address in doc_addresses {
+            // スニペットの生成が重いので、文書ごとに中断されていないか確かめる
+            self.cancellation.check()?;
             let doc = searcher.doc(doc_address)?;
             let language: Language = doc
                 .get_first(schema.language())
                 .map(|x| x.as_u64().unwrap().into())
                 .unwrap_or(Language::English);
 
-            let snippet = get_snippet(
-                &schema,
-                &searcher,
-                &*query,
-                &mut snippet_generator_map,
-                &snippet_generator_ngram,
-                &doc,
-                language,
-            );
-            let document = populate_document(&schema, Some(snippet.to_html()), &doc)?;
+            let highlights = self.make_highlights(&schema, &query_terms, &doc, language, snippet_options)?;
+            let mut document = populate_document(&schema, highlights, &doc)?;
+            self.annotate(&mut document);
             documents.push(document);
         }
 
-        Ok(SearchResults { count, documents })
+        Ok(SearchResults {
+            count,
+            documents,
+            suggestions: vec![],
+            facets,
+            cursor: None,
+        })
     }
 
-    pub(super) fn get_all_documents(&self, offset: usize, limit: usize) -> Result<SearchResults> {
-        let schema = AppSchema::new(self.index.schema());
-        let modified_at_field = schema.modified_at();
-        self.do_query_and_build_results(
-            Box::new(AllQuery),
-            TopDocs::with_limit(limit)
-                .and_offset(offset)
-                .order_by_fast_field::<tantivy::DateTime>(modified_at_field),
-        )
+    pub(super) fn get_all_documents(
+        &self,
+        sort: Sort,
+        offset: usize,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<SearchResults> {
+        // 関連度は意味がないので、新しい順にする
+        let sort = match sort {
+            Sort::Relevance => Sort::ModifiedAt(SortOrder::Desc),
+            x => x,
+        };
+        let query: Box<dyn Query> = Box::new(AllQuery);
+        let page = self.page(offset, limit, cursor, pagination::search_hash(&*query, sort))?;
+        self.do_sorted_search(query, sort, SearchOptions::default().snippet_options(), page)
     }
 
     pub(super) fn count_documents_under_path<P: AsRef<Path>>(&self, path: P) -> Result<u32> {
@@ -317,6 +861,73 @@ impl Search {
         Ok(count)
     }
 
+    pub fn find_paths_by_language(&self, language: Language) -> Result<Vec<PathBuf>> {
+        let schema = AppSchema::new(self.index.schema());
+        let searcher = self.index_reader.searcher();
+        let collector = FilterCollector::new(
+            schema.language(),
+            move |value: u64| value == language as u64,
+            DocSetCollector,
+        );
+        let doc_addresses = searcher.search(&AllQuery, &collector)?;
+        let mut paths = Vec::with_capacity(doc_addresses.len());
+        for doc_address in doc_addresses {
+            let doc = searcher.doc(doc_address)?;
+            let path = doc.get_first(schema.path()).unwrap().as_text().unwrap();
+            paths.push(PathBuf::from(path));
+        }
+        Ok(paths)
+    }
+
+    /// Returns the paths, titles and watch ids of all the documents.
+    pub(super) fn find_all_titles(&self) -> Result<Vec<(PathBuf, String, WatchId)>> {
+        let schema = AppSchema::new(self.index.schema());
+        let searcher = self.index_reader.searcher();
+        let doc_addresses = searcher.search(&AllQuery, &DocSetCollector)?;
+        let mut titles = Vec::with_capacity(doc_addresses.len());
+        for doc_address in doc_addresses {
+            let doc = searcher.doc(doc_address)?;
+            let path = doc.get_first(schema.path()).unwrap().as_text().unwrap();
+            let title = doc
+                .get_first(schema.title_ngram())
+                .and_then(|x| x.as_text())
+                .unwrap_or_default();
+            let watch_id = doc.get_first(schema.watch_id()).unwrap().as_i64().unwrap();
+            titles.push((PathBuf::from(path), title.to_string(), WatchId(watch_id)));
+        }
+        Ok(titles)
+    }
+
+    /// Returns the body text of the document extracted when it was indexed.
+    pub(super) fn get_contents<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>> {
+        let schema = AppSchema::new(self.index.schema());
+        let searcher = self.index_reader.searcher();
+        let doc_address = match self.find_doc_address_by_path(&searcher, path)? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let doc = searcher.doc(doc_address)?;
+        Ok(doc
+            .get_first(schema.contents_ngram())
+            .and_then(|x| x.as_text())
+            .map(|x| x.to_string()))
+    }
+
+    fn find_doc_address_by_path<P: AsRef<Path>>(&self, searcher: &Searcher, path: P) -> Result<Option<DocAddress>> {
+        let path = path.as_ref();
+        let schema = AppSchema::new(self.index.schema());
+        let query = TermQuery::new(
+            Term::from_field_text(schema.path(), &path.to_normalized_path_string()),
+            IndexRecordOption::Basic,
+        );
+        let doc_address = searcher
+            .search(&query, &TopDocs::with_limit(1))?
+            .into_iter()
+            .next()
+            .map(|(_, doc_address)| doc_address);
+        Ok(doc_address)
+    }
+
     pub(super) fn count_documents_by_path<P: AsRef<Path>>(&self, path: P) -> Result<u32> {
         let path = path.as_ref();
         let schema = AppSchema::new(self.index.schema());
@@ -331,7 +942,23 @@ impl Search {
     }
 }
 
-fn populate_document(schema: &AppSchema, highlight: Option<String>, doc: &Document) -> Result<SearchResultDocument> {
+/// Multiplier of the score of the document modified at the time, higher for newer ones
+fn recency_boost(recency: RecencySettings, now: i64, modified_at: i64) -> f32 {
+    let age_days = (now - modified_at).max(0) as f32 / 86_400.0;
+    1.0 + recency.weight * 0.5_f32.powf(age_days / recency.half_life_days)
+}
+
+/// Multiplier of the score of the document opened `count` times from the results of the query
+fn opened_boost(weight: f32, count: u32) -> f32 {
+    1.0 + weight * (1.0 + count as f32).log2()
+}
+
+/// Keeps the filters in the spelling suggestions of the free text.
+fn with_filters(parsed_query: &ParsedQuery, suggestions: Vec<String>) -> Vec<String> {
+    suggestions.iter().map(|x| parsed_query.with_text(x)).collect()
+}
+
+fn populate_document(schema: &AppSchema, highlights: Highlights, doc: &Document) -> Result<SearchResultDocument> {
     let path = doc.get_first(schema.path()).unwrap().as_text().unwrap().to_string();
     let watch_id = doc.get_first(schema.watch_id()).unwrap().as_i64().unwrap();
     let title = doc
@@ -354,17 +981,48 @@ fn populate_document(schema: &AppSchema, highlight: Option<String>, doc: &Docume
         chrono::Utc,
     );
 
+    let highlight = if highlights.snippets.is_empty() {
+        None
+    } else {
+        Some(snippet::to_html(&highlights.snippets))
+    };
+    let highlighted_title = match &title {
+        Some(title) if !highlights.title.is_empty() => Some(snippet::highlight_to_html(title, &highlights.title)),
+        _ => None,
+    };
+    let highlighted_path = if highlights.path.is_empty() {
+        None
+    } else {
+        Some(snippet::highlight_to_html(&path, &highlights.path))
+    };
+
     Ok(SearchResultDocument {
         path,
         title,
         tags: tag,
         watch_id,
         highlight,
+        snippets: highlights.snippets,
+        highlighted_title,
+        title_highlights: highlights.title,
+        highlighted_path,
+        path_highlights: highlights.path,
+        matched_fields: highlights.matched_fields,
         created_at,
         modified_at,
+        pinned: false,
+        labels: vec![],
+        note: None,
     })
 }
 
+/// Reads the title of the file in the same way as indexing it.
+pub(super) fn read_title(path: &Path) -> Result<String> {
+    let contents = read_file_content(path)?;
+    let frontmatter::ParseResult { frontmatter, body } = frontmatter::parse(&contents)?;
+    Ok(get_title(&frontmatter, body, path))
+}
+
 fn read_file_content(path: &Path) -> Result<String> {
     Ok(fs::read_to_string(path)?)
 }
@@ -389,46 +1047,9 @@ fn get_title(frontmatter: &Option<frontmatter::Frontmatter>, body: &str, path: &
         .unwrap_or_else(|| get_title_from_path(path))
 }
 
-fn get_snippet(
-    schema: &AppSchema,
-    searcher: &Searcher,
-    query: &dyn Query,
-    snippet_generator_map: &mut HashMap<Language, SnippetGenerator>,
-    snippet_generator_ngram: &SnippetGenerator,
-    doc: &Document,
-    language: Language,
-) -> Snippet {
-    let snippet_generator_language = snippet_generator_map.entry(language).or_insert_with(|| {
-        SnippetGenerator::create(&searcher, &*query, schema.contents_for_language(language)).unwrap()
-    });
-
-    let snippet_language = snippet_generator_language.snippet_from_doc(&doc);
-    if snippet_language.is_empty() {
-        snippet_generator_ngram.snippet_from_doc(&doc)
-    } else {
-        snippet_language
-    }
-}
-
+/// Characters of the query syntax of `QueryParser`
 const SPECIAL_CHARACTERS: &str = r#"+^`:{}"[]()~!\*"#;
 
-/// Escape special characters
-/// https://quickwit.io/docs/reference/query-language/#escaping-special-characters
-fn escape_query(query: &str) -> String {
-    query
-        .trim()
-        .chars()
-        .map(|c| {
-            if SPECIAL_CHARACTERS.contains(c) {
-                format!("\\{}", c)
-            } else {
-                c.to_string()
-            }
-        })
-        .collect::<Vec<String>>()
-        .join("")
-}
-
 fn path_component_strings<P: AsRef<Path>>(path: P) -> Vec<String> {
     let mut results = vec![];
     for c in path.as_ref().components() {
@@ -441,22 +1062,250 @@ fn path_component_strings<P: AsRef<Path>>(path: P) -> Vec<String> {
 
 #[cfg(test)]
 mod tests {
+    use super::{cancellation::SearchCancelled, pagination::CursorError, *};
+
+    /// Markdown files written to a temporary directory and indexed
+    pub(super) struct TestIndex {
+        pub(super) dir: tempfile::TempDir,
+        pub(super) search: Search,
+    }
+
+    impl TestIndex {
+        /// Normalized path of the document, as returned in the results
+        pub(super) fn path(&self, name: &str) -> String {
+            self.dir.path().join(name).to_normalized_path_string()
+        }
+    }
+
+    /// Indexes the documents given by the file names and the contents.
+    pub(super) fn index_documents(documents: &[(&str, &str)]) -> Result<TestIndex> {
+        let dir = tempfile::tempdir()?;
+        let index_dir = dir.path().join("index");
+        fs::create_dir(&index_dir)?;
+        let (search, mut index_writer) = Search::open_index(
+            &index_dir,
+            &UserDictionary::load(dir.path())?,
+            QueryExpansion::default(),
+            RankingSettings::default(),
+        )?;
+        for (name, contents) in documents {
+            let path = dir.path().join(name);
+            fs::write(&path, contents)?;
+            index_writer.add_document(search.make_document(&path, WatchId(1))?)?;
+        }
+        index_writer.commit()?;
+        search.index_reader.reload()?;
+        Ok(TestIndex { dir, search })
+    }
+
+    #[test]
+    fn test_cancelled_search() -> Result<()> {
+        let index = index_documents(&[
+            ("a.md", "# Rust\n\ntokio runtime"),
+            ("b.md", "# Go\n\ngoroutine runtime"),
+        ])?;
+        let options = SearchOptions::default();
+        let results = index
+            .search
+            .search_document("goroutine", Sort::Relevance, &options, 0, 10)?;
+        assert_eq!(
+            results.documents.iter().map(|x| x.path.clone()).collect::<Vec<_>>(),
+            vec![index.path("b.md")]
+        );
+
+        let cancellation = Cancellation::default();
+        cancellation.cancel();
+        let error = index
+            .search
+            .with_cancellation(cancellation)
+            .search_document("runtime", Sort::Relevance, &options, 0, 10)
+            .unwrap_err();
+        assert!(error.downcast_ref::<SearchCancelled>().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_of_another_search() -> Result<()> {
+        let index = index_documents(&[("a.md", "# Rust\n\nrust tokio"), ("b.md", "# Rust\n\nrust axum")])?;
+        let first_page = index
+            .search
+            .search_document("rust", Sort::Relevance, &SearchOptions::default(), 0, 1)?;
+        let options = SearchOptions {
+            cursor: first_page.cursor,
+            ..SearchOptions::default()
+        };
+        assert!(options.cursor.is_some());
+        let second_page = index.search.search_document("rust", Sort::Relevance, &options, 0, 1)?;
+        assert_eq!(second_page.documents.len(), 1);
+        assert_ne!(second_page.documents[0].path, first_page.documents[0].path);
+
+        for (query, sort) in [
+            ("tokio", Sort::Relevance),
+            ("rust", Sort::ModifiedAt(SortOrder::Desc)),
+            ("rust title:rust", Sort::Relevance),
+        ] {
+            let error = index.search.search_document(query, sort, &options, 0, 1).unwrap_err();
+            assert_eq!(error.downcast_ref::<CursorError>(), Some(&CursorError::Mismatch));
+        }
+        Ok(())
+    }
+
     #[test]
-    fn test_escape_query() {
-        assert_eq!(super::escape_query("a+b"), "a\\+b");
-        assert_eq!(super::escape_query("a^b"), "a\\^b");
-        assert_eq!(super::escape_query("a:b"), "a\\:b");
-        assert_eq!(super::escape_query("a`b"), "a\\`b");
-        assert_eq!(super::escape_query("a{b"), "a\\{b");
-        assert_eq!(super::escape_query("a}b"), "a\\}b");
-        assert_eq!(super::escape_query("a\"b"), "a\\\"b");
-        assert_eq!(super::escape_query("a[b"), "a\\[b");
-        assert_eq!(super::escape_query("a]b"), "a\\]b");
-        assert_eq!(super::escape_query("a(b"), "a\\(b");
-        assert_eq!(super::escape_query("a)b"), "a\\)b");
-        assert_eq!(super::escape_query("a~b"), "a\\~b");
-        assert_eq!(super::escape_query("a!b"), "a\\!b");
-        assert_eq!(super::escape_query("a*b"), "a\\*b");
-        assert_eq!(super::escape_query("a\\b"), "a\\\\b");
+    fn test_sort_by_word_count_with_cursor() -> Result<()> {
+        // 見出しの Rust も 1 語に数える
+        let body = |words: usize| format!("# Rust\n\n{}", "tokio ".repeat(words - 1));
+        let (a, b, c, d, e, f) = (body(10), body(30), body(20), body(40), body(100), body(1));
+        let index = index_documents(&[
+            ("a.md", &a),
+            ("b.md", &b),
+            ("c.md", &c),
+            ("d.md", &d),
+            ("e.md", &e),
+            ("f.md", &f),
+        ])?;
+
+        let mut options = SearchOptions::default();
+        let mut paths = vec![];
+        loop {
+            let results = index
+                .search
+                .search_document("words:<50", Sort::WordCount(SortOrder::Asc), &options, 0, 2)?;
+            assert_eq!(results.count, 5);
+            paths.extend(results.documents.into_iter().map(|x| x.path));
+            match results.cursor {
+                Some(cursor) if paths.len() < 10 => options.cursor = Some(cursor),
+                _ => break,
+            }
+        }
+        let expected: Vec<String> = ["f.md", "a.md", "c.md", "b.md", "d.md"]
+            .iter()
+            .map(|x| index.path(x))
+            .collect();
+        assert_eq!(paths, expected);
+
+        let results = index.search.search_document(
+            "words:<50",
+            Sort::WordCount(SortOrder::Desc),
+            &SearchOptions::default(),
+            0,
+            10,
+        )?;
+        let paths: Vec<String> = results.documents.into_iter().map(|x| x.path).collect();
+        assert_eq!(paths, expected.into_iter().rev().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_in_watch() -> Result<()> {
+        let index = index_documents(&[])?;
+        let watch_dir = index.dir.path().join("notes");
+        fs::create_dir(&watch_dir)?;
+        fs::write(watch_dir.join("a.png"), b"")?;
+        fs::write(index.dir.path().join("b.png"), b"")?;
+        index.search.set_watch_path(WatchId(1), &watch_dir);
+
+        assert!(index.search.is_in_watch(watch_dir.join("a.png")));
+        assert!(!index.search.is_in_watch(index.dir.path().join("b.png")));
+        assert!(!index.search.is_in_watch(watch_dir.join("../b.png")));
+        assert!(!index.search.is_in_watch(watch_dir.join("missing.png")));
+
+        index.search.set_watch_priority(WatchId(1), 0.5);
+        index.search.remove_watch(&watch_dir);
+        assert!(!index.search.is_in_watch(watch_dir.join("a.png")));
+        assert!(index.search.watch_priorities.read().unwrap().is_empty());
+        Ok(())
+    }
+
+    fn search_paths(index: &TestIndex, query: &str, options: &SearchOptions) -> Result<Vec<String>> {
+        let mut paths: Vec<String> = index
+            .search
+            .search_document(query, Sort::Relevance, options, 0, 10)?
+            .documents
+            .into_iter()
+            .map(|x| x.path)
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn query_mode_options(query_mode: QueryMode) -> SearchOptions {
+        SearchOptions {
+            query_mode: Some(query_mode),
+            ..SearchOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_literal_query_mode() -> Result<()> {
+        let index = index_documents(&[
+            ("a.md", "# Rust\n\nrust tokio is not axum"),
+            ("b.md", "# Rust\n\nrust axum"),
+        ])?;
+        let options = query_mode_options(QueryMode::Literal);
+        // - や演算子も入力どおりの語として検索する
+        assert_eq!(search_paths(&index, "rust -tokio", &options)?, vec![index.path("a.md")]);
+        assert_eq!(
+            search_paths(&index, "rust NOT tokio", &options)?,
+            vec![index.path("a.md")]
+        );
+        assert_eq!(
+            search_paths(&index, "\"rust", &options)?,
+            vec![index.path("a.md"), index.path("b.md")]
+        );
+        assert!(search_paths(&index, "+ -", &options)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_smart_query_mode() -> Result<()> {
+        let index = index_documents(&[
+            ("a.md", "# Rust\n\nrust tokio"),
+            ("b.md", "# Cpp\n\nrust C++ templates"),
+        ])?;
+        let options = query_mode_options(QueryMode::Smart);
+        assert_eq!(search_paths(&index, "rust -tokio", &options)?, vec![index.path("b.md")]);
+        assert_eq!(search_paths(&index, "title:rust", &options)?, vec![index.path("a.md")]);
+        // 不正なクエリは入力どおりの語として検索する
+        assert_eq!(
+            search_paths(&index, "\"rust", &options)?,
+            vec![index.path("a.md"), index.path("b.md")]
+        );
+
+        // 既定のモードでは、どんな入力でもエラーにしない
+        for query in [
+            "C++",
+            "foo:bar",
+            "\"unbalanced",
+            "rust -",
+            "-",
+            "title:",
+            "AND",
+            "(rust",
+        ] {
+            for prefix in [false, true] {
+                let options = SearchOptions {
+                    prefix,
+                    ..SearchOptions::default()
+                };
+                assert!(search_paths(&index, query, &options).is_ok(), "query: {}", query);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_advanced_query_mode() -> Result<()> {
+        let index = index_documents(&[("a.md", "# Rust\n\nrust tokio"), ("b.md", "# Rust\n\nrust axum")])?;
+        let options = query_mode_options(QueryMode::Advanced);
+        assert_eq!(search_paths(&index, "rust -tokio", &options)?, vec![index.path("b.md")]);
+        assert_eq!(
+            search_paths(&index, "title:rust -tokio", &options)?,
+            vec![index.path("b.md")]
+        );
+        for query in ["foo:bar", "\"unbalanced"] {
+            let error = search_paths(&index, query, &options).unwrap_err();
+            assert!(error.downcast_ref::<QuerySyntaxError>().is_some(), "query: {}", query);
+        }
+        Ok(())
     }
 }
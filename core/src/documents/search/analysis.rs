@@ -0,0 +1,194 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tantivy::{query::Explanation, schema::FieldType};
+
+use crate::{
+    documents::search::{opened_boost, pagination, recency_boost, schema::AppSchema, Search, SearchOptions, Sort},
+    WatchId,
+};
+
+const ANALYZER_NAMES: [&str; 3] = ["lang_en", "lang_ja", "ngram"];
+
+#[derive(Serialize, Debug)]
+pub struct AnalyzerTokens {
+    pub analyzer: String,
+    pub tokens: Vec<AnalyzedToken>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzedToken {
+    pub text: String,
+    pub position: usize,
+    pub offset_from: usize,
+    pub offset_to: usize,
+}
+
+impl Search {
+    /// Returns tokens produced by the analyzers for the text.
+    /// `language_or_field` is a language (`en`, `ja`), an analyzer name (`lang_en`, `lang_ja`, `ngram`)
+    /// or a field name. All analyzers are used if it is `None`.
+    pub(crate) fn analyze(&self, text: &str, language_or_field: Option<&str>) -> Result<Vec<AnalyzerTokens>> {
+        let analyzer_names = match language_or_field {
+            None => ANALYZER_NAMES.iter().map(|x| x.to_string()).collect(),
+            Some(x) => vec![self.resolve_analyzer_name(x)?],
+        };
+
+        let mut results = Vec::with_capacity(analyzer_names.len());
+        for analyzer_name in analyzer_names {
+            let analyzer = self
+                .index
+                .tokenizers()
+                .get(&analyzer_name)
+                .ok_or(anyhow!("analyzer {} not found", analyzer_name))?;
+            let mut tokens = vec![];
+            analyzer.token_stream(text).process(&mut |token| {
+                tokens.push(AnalyzedToken {
+                    text: token.text.clone(),
+                    position: token.position,
+                    offset_from: token.offset_from,
+                    offset_to: token.offset_to,
+                })
+            });
+            results.push(AnalyzerTokens {
+                analyzer: analyzer_name,
+                tokens,
+            });
+        }
+        Ok(results)
+    }
+
+    fn resolve_analyzer_name(&self, language_or_field: &str) -> Result<String> {
+        if ANALYZER_NAMES.contains(&language_or_field) {
+            return Ok(language_or_field.to_string());
+        }
+        let analyzer_name = format!("lang_{}", language_or_field);
+        if ANALYZER_NAMES.contains(&analyzer_name.as_str()) {
+            return Ok(analyzer_name);
+        }
+
+        let schema = self.index.schema();
+        let field = schema
+            .get_field(language_or_field)
+            .ok_or(anyhow!("unknown language or field: {}", language_or_field))?;
+        match schema.get_field_entry(field).field_type() {
+            FieldType::Str(options) => options
+                .get_indexing_options()
+                .map(|x| x.tokenizer().to_string())
+                .ok_or(anyhow!("field {} is not indexed", language_or_field)),
+            _ => Err(anyhow!("field {} is not a text field", language_or_field)),
+        }
+    }
+
+    /// Returns how the score of the document for the query is computed as a JSON string.
+    /// The query is built in the same way as searching with the options, and the relevance is multiplied by the
+    /// watch priority, the recency boost and the opened boost as in the results sorted by relevance.
+    pub(crate) fn explain(&self, query: &str, path: &str, options: &SearchOptions) -> Result<String> {
+        let (query, _) = self.build_user_query(query, options)?;
+        // 検索結果と同じ時刻で新しさを計算する
+        let page = self.page(0, 1, None, pagination::search_hash(&*query, Sort::Relevance))?;
+        let searcher = &page.searcher;
+
+        let doc_address = self
+            .find_doc_address_by_path(searcher, path)?
+            .ok_or(anyhow!("document not found: {}", path))?;
+        let relevance = query.explain(searcher, doc_address)?;
+
+        let schema = AppSchema::new(self.index.schema());
+        let fast_fields = searcher.segment_reader(doc_address.segment_ord).fast_fields();
+        let watch_id = fast_fields.i64(schema.watch_id())?.get_val(doc_address.doc_id);
+        let modified_at = fast_fields
+            .date(schema.modified_at())?
+            .get_val(doc_address.doc_id)
+            .into_timestamp_secs();
+        let watch_priority = self
+            .watch_priorities
+            .read()
+            .unwrap()
+            .get(&WatchId(watch_id))
+            .copied()
+            .unwrap_or(1.0);
+        let recency_boost = recency_boost(self.ranking.recency, page.ranking_time, modified_at);
+        let opened_boost = self
+            .opened_counts
+            .get(path)
+            .map_or(1.0, |x| opened_boost(self.ranking.opened.weight, *x));
+
+        let mut explanation = Explanation::new(
+            "ranking score, relevance multiplied by the boosts",
+            relevance.value() * watch_priority * recency_boost * opened_boost,
+        );
+        explanation.add_detail(relevance);
+        explanation.add_const("watch priority", watch_priority);
+        explanation.add_const("recency boost", recency_boost);
+        explanation.add_const("opened boost", opened_boost);
+        Ok(explanation.to_pretty_json())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::documents::search::tests::index_documents;
+
+    /// Text, position and offsets of the tokens produced by the analyzer
+    fn tokens(search: &Search, text: &str, analyzer: &str) -> Result<Vec<(String, usize, usize, usize)>> {
+        let results = search.analyze(text, Some(analyzer))?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].analyzer, analyzer);
+        Ok(results[0]
+            .tokens
+            .iter()
+            .map(|x| (x.text.clone(), x.position, x.offset_from, x.offset_to))
+            .collect())
+    }
+
+    fn token(text: &str, position: usize, offset_from: usize, offset_to: usize) -> (String, usize, usize, usize) {
+        (text.to_string(), position, offset_from, offset_to)
+    }
+
+    #[test]
+    fn test_analyze() -> Result<()> {
+        let index = index_documents(&[])?;
+        let search = &index.search;
+
+        // 小文字化、ストップワードの除去、ステミング
+        assert_eq!(
+            tokens(search, "Rust and crates", "lang_en")?,
+            vec![token("rust", 0, 0, 4), token("crate", 2, 9, 15)]
+        );
+        // カタカナはひらがなにする
+        assert_eq!(
+            tokens(search, "ラーメンが好き", "lang_ja")?,
+            vec![token("らーめん", 0, 0, 12), token("好き", 2, 15, 21)]
+        );
+        // 2 文字ずつに分け、ストップワードの um は除く
+        let ngrams: Vec<(String, usize, usize)> = tokens(search, "Axum", "ngram")?
+            .into_iter()
+            .map(|(text, _, from, to)| (text, from, to))
+            .collect();
+        assert_eq!(ngrams, vec![("ax".to_string(), 0, 2), ("xu".to_string(), 1, 3)]);
+
+        assert_eq!(tokens(search, "Rust", "en")?, tokens(search, "Rust", "lang_en")?);
+        let analyzers: Vec<String> = search.analyze("Rust", None)?.into_iter().map(|x| x.analyzer).collect();
+        assert_eq!(analyzers, ANALYZER_NAMES.map(|x| x.to_string()));
+        assert!(search.analyze("Rust", Some("fr")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain() -> Result<()> {
+        let index = index_documents(&[("a.md", "# Rust\n\nrust tokio")])?;
+        let options = SearchOptions::default();
+        let explanation = index.search.explain("tokio", &index.path("a.md"), &options)?;
+        assert!(explanation.contains("\"value\""));
+        assert!(explanation.contains("recency boost"));
+        // フィルタも検索と同じように解釈する
+        assert!(index
+            .search
+            .explain("title:rust tokio", &index.path("a.md"), &options)
+            .is_ok());
+        assert!(index.search.explain("tokio", &index.path("b.md"), &options).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    query::Weight,
+    DocId, Score, SegmentOrdinal, SegmentReader, TERMINATED,
+};
+use thiserror::Error;
+
+/// Returned when a search is cancelled before it finishes
+#[derive(Error, Debug)]
+#[error("the search was cancelled")]
+pub struct SearchCancelled;
+
+/// Shared flag to stop a running search, e.g. when a newer query supersedes it.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if both are the flag of the same search.
+    pub fn is_same(&self, other: &Cancellation) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    pub fn check(&self) -> Result<(), SearchCancelled> {
+        if self.is_cancelled() {
+            Err(SearchCancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Stops scoring and collecting documents once the search is cancelled.
+/// The fruit is incomplete in that case, so check the cancellation after the search.
+pub(super) struct CancellableCollector<C> {
+    inner: C,
+    cancellation: Cancellation,
+}
+
+impl<C> CancellableCollector<C> {
+    pub(super) fn new(inner: C, cancellation: Cancellation) -> Self {
+        CancellableCollector { inner, cancellation }
+    }
+}
+
+pub(super) struct CancellableSegmentCollector<C> {
+    inner: C,
+    cancellation: Cancellation,
+}
+
+impl<C: Collector> Collector for CancellableCollector<C> {
+    type Fruit = C::Fruit;
+    type Child = CancellableSegmentCollector<C::Child>;
+
+    fn for_segment(&self, segment_local_id: SegmentOrdinal, segment: &SegmentReader) -> tantivy::Result<Self::Child> {
+        Ok(CancellableSegmentCollector {
+            inner: self.inner.for_segment(segment_local_id, segment)?,
+            cancellation: self.cancellation.clone(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.inner.requires_scoring()
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<<C::Child as SegmentCollector>::Fruit>) -> tantivy::Result<C::Fruit> {
+        self.inner.merge_fruits(segment_fruits)
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: SegmentOrdinal,
+        reader: &SegmentReader,
+    ) -> tantivy::Result<<Self::Child as SegmentCollector>::Fruit> {
+        let mut segment_collector = self.for_segment(segment_ord, reader)?;
+        if self.cancellation.is_cancelled() {
+            return Ok(segment_collector.harvest());
+        }
+        // 既定の実装はセグメントの全文書を走査し終えるまで止まらないので、スコアラーを直接進めて文書ごとに確かめる
+        let mut scorer = weight.scorer(reader, 1.0)?;
+        let alive_bitset = reader.alive_bitset();
+        let mut doc = scorer.doc();
+        while doc != TERMINATED && !self.cancellation.is_cancelled() {
+            if alive_bitset.map_or(true, |x| x.is_alive(doc)) {
+                segment_collector.inner.collect(doc, scorer.score());
+            }
+            doc = scorer.advance();
+        }
+        Ok(segment_collector.harvest())
+    }
+}
+
+impl<C: SegmentCollector> SegmentCollector for CancellableSegmentCollector<C> {
+    type Fruit = C::Fruit;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        if self.cancellation.is_cancelled() {
+            return;
+        }
+        self.inner.collect(doc, score);
+    }
+
+    fn harvest(self) -> C::Fruit {
+        self.inner.harvest()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::{
+        collector::Count,
+        doc,
+        query::AllQuery,
+        schema::{Schema, STRING},
+        Index,
+    };
+
+    use super::*;
+
+    /// Cancels the search after collecting the number of documents.
+    struct CancellingCollector {
+        cancellation: Cancellation,
+        limit: usize,
+    }
+
+    struct CancellingSegmentCollector {
+        cancellation: Cancellation,
+        limit: usize,
+        count: usize,
+    }
+
+    impl Collector for CancellingCollector {
+        type Fruit = usize;
+        type Child = CancellingSegmentCollector;
+
+        fn for_segment(&self, _: SegmentOrdinal, _: &SegmentReader) -> tantivy::Result<Self::Child> {
+            Ok(CancellingSegmentCollector {
+                cancellation: self.cancellation.clone(),
+                limit: self.limit,
+                count: 0,
+            })
+        }
+
+        fn requires_scoring(&self) -> bool {
+            false
+        }
+
+        fn merge_fruits(&self, segment_fruits: Vec<usize>) -> tantivy::Result<usize> {
+            Ok(segment_fruits.into_iter().sum())
+        }
+    }
+
+    impl SegmentCollector for CancellingSegmentCollector {
+        type Fruit = usize;
+
+        fn collect(&mut self, _: DocId, _: Score) {
+            self.count += 1;
+            if self.count == self.limit {
+                self.cancellation.cancel();
+            }
+        }
+
+        fn harvest(self) -> usize {
+            self.count
+        }
+    }
+
+    #[test]
+    fn test_stop_scoring_when_cancelled() -> tantivy::Result<()> {
+        let mut schema_builder = Schema::builder();
+        let field = schema_builder.add_text_field("text", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer = index.writer(15_000_000)?;
+        for i in 0..1000 {
+            index_writer.add_document(doc!(field => i.to_string()))?;
+        }
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let cancellation = Cancellation::default();
+        let collector = CancellableCollector::new(
+            (
+                Count,
+                CancellingCollector {
+                    cancellation: cancellation.clone(),
+                    limit: 10,
+                },
+            ),
+            cancellation.clone(),
+        );
+        let (count, collected) = searcher.search(&AllQuery, &collector)?;
+        assert!(cancellation.check().is_err());
+        assert_eq!(collected, 10);
+        assert_eq!(count, 10);
+
+        let (count, _) = searcher.search(&AllQuery, &collector)?;
+        assert_eq!(count, 0);
+        Ok(())
+    }
+}
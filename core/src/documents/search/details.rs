@@ -0,0 +1,365 @@
+use std::path::Path;
+
+use anyhow::Result;
+use pulldown_cmark::{Event, Parser, Tag};
+use serde::Serialize;
+use tantivy::{schema::Field, Document};
+
+use crate::documents::{
+    preview,
+    search::{schema::AppSchema, Language, Search},
+};
+
+/// Words of English read per minute, for the estimated reading time
+const WORDS_PER_MINUTE: u64 = 200;
+/// Characters of Japanese read per minute
+const CJK_CHARACTERS_PER_MINUTE: u64 = 500;
+
+/// Details of an indexed document, computed when indexing it.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDetails {
+    pub path: String,
+    pub title: String,
+    pub language: Language,
+    pub tags: Vec<String>,
+    pub frontmatter: Vec<FrontmatterField>,
+    /// Headings nested under the preceding higher-level headings
+    pub outline: Vec<HeadingNode>,
+    pub word_count: u64,
+    pub char_count: u64,
+    pub reading_time_minutes: u64,
+    /// Links to the other files and the anchors in the document
+    pub internal_link_count: u64,
+    pub external_link_count: u64,
+    pub file_size: u64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub modified_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FrontmatterField {
+    pub key: String,
+    /// Lists and tables are joined with `, `
+    pub value: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HeadingNode {
+    pub level: u8,
+    pub text: String,
+    /// 1-based line number in the file
+    pub line: u64,
+    pub children: Vec<HeadingNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Heading {
+    pub level: u8,
+    pub line: u64,
+    pub text: String,
+}
+
+/// Statistics of the Markdown body, counting the text without the syntax.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(super) struct MarkdownStats {
+    pub headings: Vec<Heading>,
+    /// Each CJK character is counted as a word
+    pub words: u64,
+    pub cjk_characters: u64,
+    /// Non-whitespace characters
+    pub characters: u64,
+    pub internal_links: u64,
+    pub external_links: u64,
+}
+
+impl MarkdownStats {
+    /// Minutes rounded up, or 0 for a document without text.
+    pub fn reading_time_minutes(&self) -> u64 {
+        let other_words = self.words - self.cjk_characters;
+        let minutes = other_words as f64 / WORDS_PER_MINUTE as f64
+            + self.cjk_characters as f64 / CJK_CHARACTERS_PER_MINUTE as f64;
+        minutes.ceil() as u64
+    }
+}
+
+/// Computes the statistics of the body, which starts at `first_line` of the file.
+pub(super) fn analyze_markdown(body: &str, first_line: u64) -> MarkdownStats {
+    let mut stats = MarkdownStats::default();
+    let mut heading: Option<Heading> = None;
+    // テキストのイベントは単語の途中で分かれることがあるので、続くテキストでは単語の状態を引き継ぐ
+    let mut in_word = false;
+
+    for (event, range) in Parser::new_ext(body, preview::markdown_options()).into_offset_iter() {
+        match event {
+            Event::Text(text) | Event::Code(text) => {
+                in_word = count_text(&text, in_word, &mut stats);
+                if let Some(heading) = heading.as_mut() {
+                    heading.text.push_str(&text);
+                }
+                continue;
+            }
+            Event::Start(Tag::Heading(level, _, _)) => {
+                heading = Some(Heading {
+                    level: level as u8,
+                    line: first_line + body[..range.start].matches('\n').count() as u64,
+                    text: String::new(),
+                });
+            }
+            Event::End(Tag::Heading(_, _, _)) => {
+                if let Some(mut heading) = heading.take() {
+                    heading.text = heading.text.trim().to_string();
+                    stats.headings.push(heading);
+                }
+            }
+            Event::Start(Tag::Link(_, dest, _)) => {
+                if dest.starts_with('#') || preview::scheme(&dest).is_none() {
+                    stats.internal_links += 1;
+                } else {
+                    stats.external_links += 1;
+                }
+            }
+            _ => {}
+        }
+        in_word = false;
+    }
+    stats
+}
+
+/// Counts the words and the characters of the text, returning whether the text ends in a word.
+fn count_text(text: &str, mut in_word: bool, stats: &mut MarkdownStats) -> bool {
+    for c in text.chars() {
+        if !c.is_whitespace() {
+            stats.characters += 1;
+        }
+        if is_cjk(c) {
+            stats.words += 1;
+            stats.cjk_characters += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() {
+            if !in_word {
+                stats.words += 1;
+            }
+            in_word = true;
+        } else if !(in_word && ['\'', '’', '-'].contains(&c)) {
+            // don't や state-of-the-art は 1 語とする
+            in_word = false;
+        }
+    }
+    in_word
+}
+
+/// Kana, kanji and hangul, which are not separated by spaces
+pub(super) fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9F}'
+        | '\u{AC00}'..='\u{D7AF}'
+    )
+}
+
+/// Nests the headings under the preceding headings of higher levels.
+fn build_heading_tree(headings: Vec<Heading>) -> Vec<HeadingNode> {
+    fn insert(nodes: &mut Vec<HeadingNode>, node: HeadingNode) {
+        match nodes.last_mut() {
+            Some(last) if last.level < node.level => insert(&mut last.children, node),
+            _ => nodes.push(node),
+        }
+    }
+
+    let mut nodes = vec![];
+    for heading in headings {
+        insert(
+            &mut nodes,
+            HeadingNode {
+                level: heading.level,
+                text: heading.text,
+                line: heading.line,
+                children: vec![],
+            },
+        );
+    }
+    nodes
+}
+
+/// Adds the details to the document being indexed.
+pub(super) fn add_details(
+    schema: &AppSchema,
+    document: &mut Document,
+    stats: &MarkdownStats,
+    frontmatter_fields: &[(String, String)],
+    file_size: u64,
+) {
+    for heading in &stats.headings {
+        document.add_u64(schema.heading_level(), heading.level as u64);
+        document.add_u64(schema.heading_line(), heading.line);
+        document.add_text(schema.heading_text(), &heading.text);
+    }
+    for (key, value) in frontmatter_fields {
+        document.add_text(schema.frontmatter_key(), key);
+        document.add_text(schema.frontmatter_value(), value);
+    }
+    document.add_u64(schema.word_count(), stats.words);
+    document.add_u64(schema.char_count(), stats.characters);
+    document.add_u64(schema.reading_time(), stats.reading_time_minutes());
+    document.add_u64(schema.internal_link_count(), stats.internal_links);
+    document.add_u64(schema.external_link_count(), stats.external_links);
+    document.add_u64(schema.file_size(), file_size);
+}
+
+impl Search {
+    /// Returns the details of the document stored in the index, or `None` if it's not indexed.
+    pub(crate) fn get_document_details<P: AsRef<Path>>(&self, path: P) -> Result<Option<DocumentDetails>> {
+        let schema = AppSchema::new(self.index.schema());
+        let searcher = self.index_reader.searcher();
+        let doc_address = match self.find_doc_address_by_path(&searcher, path)? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let doc = searcher.doc(doc_address)?;
+
+        let texts = |field: Field| -> Vec<String> {
+            doc.get_all(field)
+                .filter_map(|x| x.as_text())
+                .map(|x| x.to_string())
+                .collect()
+        };
+        let numbers = |field: Field| -> Vec<u64> { doc.get_all(field).filter_map(|x| x.as_u64()).collect() };
+        let number = |field: Field| numbers(field).first().copied().unwrap_or(0);
+        let date = |field: Field| -> Result<chrono::DateTime<chrono::Utc>> {
+            let timestamp = doc
+                .get_first(field)
+                .and_then(|x| x.as_date())
+                .map(|x| x.into_timestamp_secs())
+                .unwrap_or(0);
+            Ok(chrono::DateTime::from_utc(
+                chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0)
+                    .ok_or(anyhow::anyhow!("timestamp is out of range"))?,
+                chrono::Utc,
+            ))
+        };
+
+        let headings = numbers(schema.heading_level())
+            .into_iter()
+            .zip(numbers(schema.heading_line()))
+            .zip(texts(schema.heading_text()))
+            .map(|((level, line), text)| Heading {
+                level: level as u8,
+                line,
+                text,
+            })
+            .collect();
+        let frontmatter = texts(schema.frontmatter_key())
+            .into_iter()
+            .zip(texts(schema.frontmatter_value()))
+            .map(|(key, value)| FrontmatterField { key, value })
+            .collect();
+
+        Ok(Some(DocumentDetails {
+            path: texts(schema.path()).into_iter().next().unwrap_or_default(),
+            title: texts(schema.title_ngram()).into_iter().next().unwrap_or_default(),
+            language: doc
+                .get_first(schema.language())
+                .and_then(|x| x.as_u64())
+                .map(Language::from)
+                .unwrap_or(Language::English),
+            tags: texts(schema.tag_ngram()),
+            frontmatter,
+            outline: build_heading_tree(headings),
+            word_count: number(schema.word_count()),
+            char_count: number(schema.char_count()),
+            reading_time_minutes: number(schema.reading_time()),
+            internal_link_count: number(schema.internal_link_count()),
+            external_link_count: number(schema.external_link_count()),
+            file_size: number(schema.file_size()),
+            created_at: date(schema.created_at())?,
+            modified_at: date(schema.modified_at())?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heading(level: u8, line: u64, text: &str) -> Heading {
+        Heading {
+            level,
+            line,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_analyze_markdown() {
+        let body = r#"# Rust `tokio` notes
+
+It's a state-of-the-art runtime. See [docs](https://tokio.rs/) and [setup](./setup.md#install).
+
+## 日本語のメモ
+
+非同期ランタイム
+"#;
+        let stats = analyze_markdown(body, 5);
+        assert_eq!(
+            stats.headings,
+            vec![heading(1, 5, "Rust tokio notes"), heading(2, 9, "日本語のメモ")]
+        );
+        // Rust tokio notes / It's a state-of-the-art runtime See docs and setup / 日本語のメモ / 非同期ランタイム
+        assert_eq!(stats.words, 3 + 8 + 6 + 8);
+        assert_eq!(stats.cjk_characters, 14);
+        assert_eq!(stats.internal_links, 1);
+        assert_eq!(stats.external_links, 1);
+    }
+
+    #[test]
+    fn test_analyze_markdown_as_previewed() {
+        // プレビューで有効でない拡張 (見出しの属性、スマート引用符) は解釈しない
+        let stats = analyze_markdown("# Setup {#install}\n\n## Don't \"panic\"\n", 1);
+        assert_eq!(
+            stats.headings,
+            vec![heading(1, 1, "Setup {#install}"), heading(2, 3, "Don't \"panic\"")]
+        );
+    }
+
+    #[test]
+    fn test_reading_time_minutes() {
+        let stats = |words: u64, cjk_characters: u64| MarkdownStats {
+            words,
+            cjk_characters,
+            ..MarkdownStats::default()
+        };
+        assert_eq!(stats(0, 0).reading_time_minutes(), 0);
+        assert_eq!(stats(10, 0).reading_time_minutes(), 1);
+        assert_eq!(stats(400, 0).reading_time_minutes(), 2);
+        assert_eq!(stats(401, 0).reading_time_minutes(), 3);
+        assert_eq!(stats(1000, 1000).reading_time_minutes(), 2);
+    }
+
+    #[test]
+    fn test_build_heading_tree() {
+        let tree = build_heading_tree(vec![
+            heading(2, 1, "a"),
+            heading(3, 3, "a-1"),
+            heading(4, 5, "a-1-1"),
+            heading(3, 7, "a-2"),
+            heading(1, 9, "b"),
+            heading(3, 11, "b-1"),
+        ]);
+        let summary: Vec<(String, Vec<String>)> = tree
+            .iter()
+            .map(|x| (x.text.clone(), x.children.iter().map(|x| x.text.clone()).collect()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a".to_string(), vec!["a-1".to_string(), "a-2".to_string()]),
+                ("b".to_string(), vec!["b-1".to_string()]),
+            ]
+        );
+        assert_eq!(tree[0].children[0].children[0].line, 5);
+    }
+}
@@ -7,13 +7,16 @@ use tantivy::{
 };
 use tantivy_vibrato::VibratoTokenizer;
 
-use crate::{assets::Assets, documents::search::stopwords::load_stopwords_for_lang};
+use crate::{
+    assets::Assets,
+    documents::search::{nfkc_normalizer::NfkcNormalizer, stopwords::load_stopwords_for_lang},
+};
 
 pub fn setup_tokenizer(index: &Index) -> Result<()> {
     let stopwords = load_stopwords_for_lang("en")?;
     let analyzer = TextAnalyzer::from(SimpleTokenizer)
         .filter(RemoveLongFilter::limit(40))
-        .filter(LowerCaser)
+        .filter(NfkcNormalizer)
         .filter(StopWordFilter::remove(stopwords))
         .filter(Stemmer::new(Language::English));
 
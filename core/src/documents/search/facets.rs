@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, TimeZone};
+use serde::Serialize;
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    schema::{Facet, Field},
+    DocId, Score, SegmentOrdinal, SegmentReader,
+};
+
+use crate::documents::search::{path_component_strings, schema::AppSchema, Search};
+
+/// Numbers of the matching documents per watch, per top-level folder and per month
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Facets {
+    /// Most documents first
+    pub watches: Vec<WatchFacet>,
+    /// Most documents first in each watch. Documents right under the watch directory are not counted.
+    pub folders: Vec<FolderFacet>,
+    /// Histogram of the last modified time in the local time zone, oldest first
+    pub months: Vec<MonthFacet>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct WatchFacet {
+    pub watch_id: i64,
+    pub count: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FolderFacet {
+    pub watch_id: i64,
+    /// Name of the folder right under the watch directory
+    pub folder: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MonthFacet {
+    /// e.g. `2022-06`
+    pub month: String,
+    pub count: usize,
+}
+
+impl Search {
+    pub(super) fn facets_collector(&self) -> FacetsCollector {
+        let schema = AppSchema::new(self.index.schema());
+        let watch_roots = self
+            .watch_paths
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(watch_id, path)| (watch_id.0, path_component_strings(path)))
+            .collect();
+        FacetsCollector {
+            watch_id_field: schema.watch_id(),
+            modified_at_field: schema.modified_at(),
+            directory_field: schema.directory(),
+            watch_roots,
+        }
+    }
+}
+
+/// Counts the matching documents by the fast fields.
+pub(super) struct FacetsCollector {
+    watch_id_field: Field,
+    modified_at_field: Field,
+    directory_field: Field,
+    /// Path components of the watch directories
+    watch_roots: HashMap<i64, Vec<String>>,
+}
+
+/// Counts in a segment. Directories are resolved to strings since term ordinals are local to the segment.
+#[derive(Default)]
+pub(super) struct SegmentFacets {
+    watches: HashMap<i64, usize>,
+    directories: HashMap<(i64, String), usize>,
+    /// Keyed by `year * 12 + month0`
+    months: HashMap<i32, usize>,
+}
+
+pub(super) struct FacetsSegmentCollector {
+    watch_id: Box<dyn Fn(DocId) -> i64>,
+    modified_at: Box<dyn Fn(DocId) -> i64>,
+    directory_reader: tantivy::fastfield::FacetReader,
+    directory_ords: Vec<u64>,
+    watches: HashMap<i64, usize>,
+    directories: HashMap<(i64, u64), usize>,
+    months: HashMap<i32, usize>,
+}
+
+impl Collector for FacetsCollector {
+    type Fruit = Facets;
+    type Child = FacetsSegmentCollector;
+
+    fn for_segment(&self, _segment_local_id: SegmentOrdinal, segment: &SegmentReader) -> tantivy::Result<Self::Child> {
+        let watch_id_reader = segment.fast_fields().i64(self.watch_id_field)?;
+        let modified_at_reader = segment.fast_fields().date(self.modified_at_field)?;
+        Ok(FacetsSegmentCollector {
+            watch_id: Box::new(move |doc| watch_id_reader.get_val(doc)),
+            modified_at: Box::new(move |doc| modified_at_reader.get_val(doc).into_timestamp_secs()),
+            directory_reader: segment.facet_reader(self.directory_field)?,
+            directory_ords: vec![],
+            watches: HashMap::new(),
+            directories: HashMap::new(),
+            months: HashMap::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<SegmentFacets>) -> tantivy::Result<Facets> {
+        let mut merged = SegmentFacets::default();
+        for fruit in segment_fruits {
+            for (key, count) in fruit.watches {
+                *merged.watches.entry(key).or_default() += count;
+            }
+            for (key, count) in fruit.directories {
+                *merged.directories.entry(key).or_default() += count;
+            }
+            for (key, count) in fruit.months {
+                *merged.months.entry(key).or_default() += count;
+            }
+        }
+        Ok(make_facets(merged, &self.watch_roots))
+    }
+}
+
+impl SegmentCollector for FacetsSegmentCollector {
+    type Fruit = SegmentFacets;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        let watch_id = (self.watch_id)(doc);
+        *self.watches.entry(watch_id).or_default() += 1;
+
+        self.directory_reader.facet_ords(doc, &mut self.directory_ords);
+        for ord in &self.directory_ords {
+            *self.directories.entry((watch_id, *ord)).or_default() += 1;
+        }
+
+        if let Some(month) = local_month((self.modified_at)(doc)) {
+            *self.months.entry(month).or_default() += 1;
+        }
+    }
+
+    fn harvest(mut self) -> SegmentFacets {
+        let mut directories = HashMap::new();
+        let mut facet = Facet::root();
+        for ((watch_id, ord), count) in self.directories {
+            if let Err(e) = self.directory_reader.facet_from_ord(ord, &mut facet) {
+                tracing::warn!("failed to read a directory facet: {}", e);
+                continue;
+            }
+            *directories.entry((watch_id, facet.to_path_string())).or_default() += count;
+        }
+        SegmentFacets {
+            watches: self.watches,
+            directories,
+            months: self.months,
+        }
+    }
+}
+
+fn local_month(timestamp: i64) -> Option<i32> {
+    let date = chrono::Local.timestamp_opt(timestamp, 0).single()?;
+    Some(date.year() * 12 + date.month0() as i32)
+}
+
+fn make_facets(counts: SegmentFacets, watch_roots: &HashMap<i64, Vec<String>>) -> Facets {
+    let mut watches: Vec<WatchFacet> = counts
+        .watches
+        .into_iter()
+        .map(|(watch_id, count)| WatchFacet { watch_id, count })
+        .collect();
+    watches.sort_by(|a, b| b.count.cmp(&a.count).then(a.watch_id.cmp(&b.watch_id)));
+
+    let mut folder_counts: HashMap<(i64, String), usize> = HashMap::new();
+    for ((watch_id, directory), count) in counts.directories {
+        let root = match watch_roots.get(&watch_id) {
+            Some(x) => x,
+            None => continue,
+        };
+        if let Some(folder) = top_level_folder(root, &directory) {
+            *folder_counts.entry((watch_id, folder.to_string())).or_default() += count;
+        }
+    }
+    let mut folders: Vec<FolderFacet> = folder_counts
+        .into_iter()
+        .map(|((watch_id, folder), count)| FolderFacet {
+            watch_id,
+            folder,
+            count,
+        })
+        .collect();
+    folders.sort_by(|a, b| {
+        a.watch_id
+            .cmp(&b.watch_id)
+            .then(b.count.cmp(&a.count))
+            .then(a.folder.cmp(&b.folder))
+    });
+
+    let mut months: Vec<(i32, usize)> = counts.months.into_iter().collect();
+    months.sort();
+    let months = months
+        .into_iter()
+        .map(|(month, count)| MonthFacet {
+            month: format!("{:04}-{:02}", month.div_euclid(12), month.rem_euclid(12) + 1),
+            count,
+        })
+        .collect();
+
+    Facets {
+        watches,
+        folders,
+        months,
+    }
+}
+
+/// Returns the first component of the directory under the root, e.g. `work` for `/notes/work/2022` under `/notes`.
+/// `directory` is a facet path like `/notes/work/2022`.
+fn top_level_folder<'a>(root: &[String], directory: &'a str) -> Option<&'a str> {
+    let components: Vec<&str> = directory.split('/').filter(|x| !x.is_empty()).collect();
+    if components.len() <= root.len() || components.iter().zip(root).any(|(a, b)| a != b) {
+        return None;
+    }
+    Some(components[root.len()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_level_folder() {
+        let root = vec!["Users".to_string(), "me".to_string(), "notes".to_string()];
+        assert_eq!(top_level_folder(&root, "/Users/me/notes/work/2022"), Some("work"));
+        assert_eq!(top_level_folder(&root, "/Users/me/notes/work"), Some("work"));
+        assert_eq!(top_level_folder(&root, "/Users/me/notes"), None);
+        assert_eq!(top_level_folder(&root, "/Users/me/others/work"), None);
+    }
+
+    #[test]
+    fn test_make_facets() {
+        let counts = SegmentFacets {
+            watches: HashMap::from([(1, 3), (2, 5)]),
+            directories: HashMap::from([
+                ((1, "/notes/work/a".to_string()), 1),
+                ((1, "/notes/work".to_string()), 1),
+                ((1, "/notes/home".to_string()), 3),
+                ((1, "/notes".to_string()), 1),
+                ((3, "/unknown/x".to_string()), 1),
+            ]),
+            months: HashMap::from([(2022 * 12 + 5, 2), (2021 * 12 + 11, 1)]),
+        };
+        let roots = HashMap::from([(1, vec!["notes".to_string()])]);
+        let facets = make_facets(counts, &roots);
+        assert_eq!(
+            facets.watches,
+            vec![
+                WatchFacet { watch_id: 2, count: 5 },
+                WatchFacet { watch_id: 1, count: 3 }
+            ]
+        );
+        assert_eq!(
+            facets.folders,
+            vec![
+                FolderFacet {
+                    watch_id: 1,
+                    folder: "home".to_string(),
+                    count: 3
+                },
+                FolderFacet {
+                    watch_id: 1,
+                    folder: "work".to_string(),
+                    count: 2
+                },
+            ]
+        );
+        assert_eq!(
+            facets.months,
+            vec![
+                MonthFacet {
+                    month: "2021-12".to_string(),
+                    count: 1
+                },
+                MonthFacet {
+                    month: "2022-06".to_string(),
+                    count: 2
+                },
+            ]
+        );
+    }
+}
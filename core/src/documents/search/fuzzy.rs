@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use tantivy::{
+    query::{BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, Query},
+    schema::{Field, Term},
+    Searcher,
+};
+
+use crate::documents::search::{
+    japanese_character_normalizer::normalize_character_type,
+    nfkc_normalizer::normalize,
+    prefix::prefix_upper_bound,
+    query_expansion::{is_plain_term, split_terms},
+    schema::AppSchema,
+    Search,
+};
+
+/// Fuzzy matches are scored lower than exact ones
+const FUZZY_BOOST: f32 = 0.5;
+const MAX_SUGGESTIONS: usize = 3;
+
+impl Search {
+    /// Builds a query matching documents containing every term of the query with typos.
+    /// Returns `None` if the query uses the query syntax since it can't be rewritten term by term.
+    pub(super) fn build_fuzzy_query(&self, query: &str) -> Result<Option<Box<dyn Query>>> {
+        let terms = split_terms(query);
+        if terms.is_empty() || !terms.iter().all(|x| is_plain_term(x)) {
+            return Ok(None);
+        }
+
+        let fields = self.language_fields_with_boosts();
+        let mut term_queries: Vec<(Occur, Box<dyn Query>)> = vec![];
+        for term in terms {
+            let mut field_queries: Vec<(Occur, Box<dyn Query>)> = vec![];
+            for (field, boost) in &fields {
+                let analyzer = self.index.tokenizer_for_field(*field)?;
+                analyzer.token_stream(term).process(&mut |token| {
+                    let query = FuzzyTermQuery::new(
+                        Term::from_field_text(*field, &token.text),
+                        max_edit_distance(&token.text),
+                        true,
+                    );
+                    field_queries.push((
+                        Occur::Should,
+                        Box::new(BoostQuery::new(Box::new(query), boost * FUZZY_BOOST)),
+                    ));
+                });
+            }
+            // ストップワードなどでトークンが残らない語は条件にしない
+            if !field_queries.is_empty() {
+                term_queries.push((Occur::Must, Box::new(BooleanQuery::new(field_queries))));
+            }
+        }
+        if term_queries.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Box::new(BooleanQuery::new(term_queries))))
+    }
+
+    /// Returns corrected queries built from the words in the index.
+    /// Each misspelled term is replaced by a similar word, more frequent words first.
+    pub(super) fn suggest(&self, query: &str) -> Result<Vec<String>> {
+        let terms = split_terms(query);
+        if terms.is_empty() || !terms.iter().all(|x| is_plain_term(x)) {
+            return Ok(vec![]);
+        }
+
+        let searcher = self.index_reader.searcher();
+        let mut corrections = Vec::with_capacity(terms.len());
+        for term in &terms {
+            let word = normalize(term);
+            if self.word_exists(&searcher, &word)? {
+                corrections.push(vec![]);
+            } else {
+                corrections.push(self.find_similar_words(&searcher, &word)?);
+            }
+        }
+        if corrections.iter().all(|x| x.is_empty()) {
+            return Ok(vec![]);
+        }
+
+        let mut suggestions: Vec<String> = vec![];
+        for i in 0..MAX_SUGGESTIONS {
+            let suggestion = terms
+                .iter()
+                .zip(&corrections)
+                .map(
+                    |(term, candidates)| match candidates.get(i).or_else(|| candidates.last()) {
+                        Some(candidate) => candidate.as_str(),
+                        None => *term,
+                    },
+                )
+                .collect::<Vec<_>>()
+                .join(" ");
+            if !suggestions.contains(&suggestion) {
+                suggestions.push(suggestion);
+            }
+        }
+        Ok(suggestions)
+    }
+
+    fn word_exists(&self, searcher: &Searcher, word: &str) -> Result<bool> {
+        let schema = AppSchema::new(self.index.schema());
+        for field in schema.spelling_all() {
+            if searcher.doc_freq(&Term::from_field_text(field, &spelling_form(&schema, field, word)))? > 0 {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn find_similar_words(&self, searcher: &Searcher, word: &str) -> Result<Vec<String>> {
+        let max_distance = max_edit_distance(word) as usize;
+        if max_distance == 0 {
+            return Ok(vec![]);
+        }
+
+        let schema = AppSchema::new(self.index.schema());
+        // word -> (distance, doc_freq)
+        let mut candidates: HashMap<String, (usize, u32)> = HashMap::new();
+        for segment_reader in searcher.segment_readers() {
+            for field in schema.spelling_all() {
+                let lookup_word = spelling_form(&schema, field, word);
+                let first_char = match lookup_word.chars().next() {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let word_len = lookup_word.chars().count();
+                let lower_bound = first_char.to_string();
+                let upper_bound = prefix_upper_bound(&lower_bound);
+                let inverted_index = segment_reader.inverted_index(field)?;
+                let term_dict = inverted_index.terms();
+                // 先頭の文字を打ち間違えることは少ないので、同じ文字で始まる語だけを調べる
+                let mut range = term_dict.range().ge(&lower_bound);
+                if let Some(upper_bound) = &upper_bound {
+                    range = range.lt(upper_bound);
+                }
+                let mut stream = range.into_stream()?;
+                while stream.advance() {
+                    let candidate = match std::str::from_utf8(stream.key()) {
+                        Ok(x) => x,
+                        Err(_) => continue,
+                    };
+                    if candidate.chars().count().abs_diff(word_len) > max_distance {
+                        continue;
+                    }
+                    let distance = levenshtein_distance(&lookup_word, candidate);
+                    if distance == 0 || distance > max_distance {
+                        continue;
+                    }
+                    // 日本語のフィールドではカタカナがひらがなになっているので、入力された語に合わせて戻す
+                    let candidate = if lookup_word == word {
+                        candidate.to_string()
+                    } else {
+                        kana::hira2kata(candidate)
+                    };
+                    let entry = candidates.entry(candidate).or_insert((distance, 0));
+                    entry.1 += stream.value().doc_freq;
+                }
+            }
+        }
+
+        let mut candidates: Vec<(String, (usize, u32))> = candidates.into_iter().collect();
+        candidates.sort_by(|(a_word, (a_distance, a_freq)), (b_word, (b_distance, b_freq))| {
+            a_distance
+                .cmp(b_distance)
+                .then(b_freq.cmp(a_freq))
+                .then(a_word.cmp(b_word))
+        });
+        Ok(candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(word, _)| word)
+            .collect())
+    }
+}
+
+/// Returns the word as indexed in the field.
+/// The Japanese fields are indexed with katakana converted to hiragana, unlike the spelling field.
+fn spelling_form(schema: &AppSchema, field: Field, word: &str) -> String {
+    if field == schema.spelling() {
+        word.to_string()
+    } else {
+        normalize_character_type(word)
+    }
+}
+
+/// Allows more typos for longer words.
+fn max_edit_distance(word: &str) -> u8 {
+    match word.chars().count() {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    }
+}
+
+fn levenshtein_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let cost = if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::documents::search::tests::index_documents;
+
+    #[test]
+    fn test_levenshtein_distance() {
+        assert_eq!(levenshtein_distance("kubernetes", "kubernetes"), 0);
+        assert_eq!(levenshtein_distance("kubernets", "kubernetes"), 1);
+        assert_eq!(levenshtein_distance("kitten", "sitting"), 3);
+        assert_eq!(levenshtein_distance("", "abc"), 3);
+        assert_eq!(levenshtein_distance("データベス", "データベース"), 1);
+    }
+
+    #[test]
+    fn test_max_edit_distance() {
+        assert_eq!(max_edit_distance("go"), 0);
+        assert_eq!(max_edit_distance("rust"), 1);
+        assert_eq!(max_edit_distance("kubernetes"), 2);
+    }
+
+    #[test]
+    fn test_suggest_katakana() -> Result<()> {
+        let index = index_documents(&[("a.md", "# 設計メモ\n\nコンピュータを設計する")])?;
+        // 日本語のフィールドではひらがなで索引されている
+        assert!(index.search.suggest("コンピュータ")?.is_empty());
+        assert_eq!(index.search.suggest("コンピュタ")?, vec!["コンピュータ".to_string()]);
+        Ok(())
+    }
+}
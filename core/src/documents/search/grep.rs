@@ -0,0 +1,414 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    collector::DocSetCollector,
+    query::{AllQuery, BooleanQuery, Occur, Query, RegexQuery, TermQuery},
+    schema::{IndexRecordOption, Term},
+    DocAddress,
+};
+
+use crate::{
+    documents::search::{
+        schema::AppSchema,
+        snippet::{to_highlight_ranges, HighlightRange},
+        Search,
+    },
+    path_string_normalization::PathStringNormalizationExt,
+};
+
+/// Matches beyond this are not returned, to keep the results small
+const MAX_MATCHES_PER_DOCUMENT: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GrepMode {
+    /// Exact substring including punctuation
+    #[default]
+    Phrase,
+    Regex,
+}
+
+#[derive(Debug, Clone)]
+pub struct GrepOptions {
+    pub mode: GrepMode,
+    pub ignore_case: bool,
+    /// Number of lines before and after a match
+    pub context_lines: usize,
+    /// Searches only the documents under this directory
+    pub path: Option<PathBuf>,
+    /// Maximum number of documents
+    pub limit: usize,
+}
+
+impl Default for GrepOptions {
+    fn default() -> Self {
+        Self {
+            mode: GrepMode::Phrase,
+            ignore_case: false,
+            context_lines: 2,
+            path: None,
+            limit: 50,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct GrepResults {
+    /// Documents with matches, most recently modified first
+    pub documents: Vec<GrepResultDocument>,
+    /// More documents may match beyond the limit
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GrepResultDocument {
+    pub path: String,
+    pub title: Option<String>,
+    pub watch_id: i64,
+    pub matches: Vec<GrepMatch>,
+}
+
+/// Lines containing one or more matches
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    /// 1-based line number in the file of the first line of `text`
+    pub line: usize,
+    pub text: String,
+    pub highlights: Vec<HighlightRange>,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+impl Search {
+    /// Finds the exact phrase or the regular expression in the contents.
+    /// Candidates are narrowed down by the n-grams of the literal parts of the pattern, then verified.
+    pub(crate) fn grep(&self, pattern: &str, options: &GrepOptions) -> Result<GrepResults> {
+        let schema = AppSchema::new(self.index.schema());
+        let (regex_pattern, literals) = match options.mode {
+            GrepMode::Phrase => (regex::escape(pattern), vec![pattern.to_string()]),
+            GrepMode::Regex => (pattern.to_string(), required_literals(pattern)),
+        };
+        let regex = RegexBuilder::new(&regex_pattern)
+            .case_insensitive(options.ignore_case)
+            .multi_line(true)
+            .build()?;
+
+        let searcher = self.index_reader.searcher();
+        let query = self.build_grep_candidate_query(&literals, options)?;
+        let mut candidates: Vec<(i64, DocAddress)> = {
+            let doc_addresses = searcher.search(&query, &DocSetCollector)?;
+            let readers = searcher
+                .segment_readers()
+                .iter()
+                .map(|x| x.fast_fields().date(schema.modified_at()))
+                .collect::<tantivy::Result<Vec<_>>>()?;
+            doc_addresses
+                .into_iter()
+                .map(|x| {
+                    let modified_at = readers[x.segment_ord as usize].get_val(x.doc_id);
+                    (modified_at.into_timestamp_secs(), x)
+                })
+                .collect()
+        };
+        candidates.sort_by(|a, b| b.cmp(a));
+
+        let mut documents = vec![];
+        let mut truncated = false;
+        for (_, doc_address) in candidates {
+            let doc = searcher.doc(doc_address)?;
+            let contents = doc
+                .get_first(schema.contents_ngram())
+                .and_then(|x| x.as_text())
+                .unwrap_or_default();
+            let first_line = doc.get_first(schema.body_line()).and_then(|x| x.as_u64()).unwrap_or(1) as usize;
+            let matches = find_matches(contents, &regex, first_line, options.context_lines);
+            if matches.is_empty() {
+                continue;
+            }
+            if documents.len() == options.limit {
+                truncated = true;
+                break;
+            }
+            documents.push(GrepResultDocument {
+                path: doc.get_first(schema.path()).unwrap().as_text().unwrap().to_string(),
+                title: doc
+                    .get_first(schema.title_ngram())
+                    .and_then(|x| x.as_text())
+                    .map(|x| x.to_string()),
+                watch_id: doc.get_first(schema.watch_id()).unwrap().as_i64().unwrap(),
+                matches,
+            });
+        }
+        Ok(GrepResults { documents, truncated })
+    }
+
+    fn build_grep_candidate_query(&self, literals: &[String], options: &GrepOptions) -> Result<Box<dyn Query>> {
+        let schema = AppSchema::new(self.index.schema());
+        let field = schema.contents_ngram();
+        let analyzer = self.index.tokenizer_for_field(field)?;
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![];
+        for literal in literals {
+            analyzer.token_stream(literal).process(&mut |token| {
+                clauses.push((
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(field, &token.text),
+                        IndexRecordOption::Basic,
+                    )),
+                ));
+            });
+        }
+        if let Some(path) = &options.path {
+            let pattern = format!(r"{}/.*", regex::escape(&path.to_normalized_path_string()));
+            clauses.push((
+                Occur::Must,
+                Box::new(RegexQuery::from_pattern(&pattern, schema.path())?),
+            ));
+        }
+        if clauses.is_empty() {
+            // 手がかりになる文字列がなければ全文書を調べる
+            return Ok(Box::new(AllQuery));
+        }
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+}
+
+/// Returns the strings which every match of the regular expression contains.
+/// It is conservative: groups, classes and optional characters are skipped, and nothing is returned for alternations.
+fn required_literals(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut literals = vec![];
+    let mut current = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let literal = match chars[i] {
+            '\\' => {
+                i += 1;
+                match chars.get(i) {
+                    // \w や \d などの文字クラス
+                    Some(c) if c.is_ascii_alphanumeric() => None,
+                    c => c.copied(),
+                }
+            }
+            '(' => {
+                let end = skip_group(&chars, i);
+                let flags: String = chars[i..end].iter().collect();
+                if flags.starts_with("(?") && flags.chars().take_while(|x| *x != ':' && *x != ')').any(|x| x == 'x') {
+                    // 空白を無視するモードでは文字列をそのまま取り出せない
+                    return vec![];
+                }
+                i = end - 1;
+                None
+            }
+            '[' => {
+                i = skip_class(&chars, i) - 1;
+                None
+            }
+            '|' => return vec![],
+            '.' | '^' | '$' | '*' | '+' | '?' | '{' | '}' | ')' | ']' => None,
+            c => Some(c),
+        };
+        i += 1;
+
+        match (literal, chars.get(i)) {
+            (Some(c), Some('+')) => {
+                current.push(c);
+                literals.extend(take_nonempty(&mut current));
+            }
+            (Some(_), Some('*' | '?' | '{')) | (None, _) => literals.extend(take_nonempty(&mut current)),
+            (Some(c), _) => current.push(c),
+        }
+        // 量指定子は読み飛ばす
+        while let Some(c) = chars.get(i) {
+            match c {
+                '*' | '+' | '?' => i += 1,
+                '{' => {
+                    i = chars[i..]
+                        .iter()
+                        .position(|x| *x == '}')
+                        .map(|x| i + x + 1)
+                        .unwrap_or(chars.len())
+                }
+                _ => break,
+            }
+        }
+    }
+    literals.extend(take_nonempty(&mut current));
+    literals
+}
+
+fn take_nonempty(text: &mut String) -> Option<String> {
+    if text.is_empty() {
+        None
+    } else {
+        Some(std::mem::take(text))
+    }
+}
+
+/// Returns the index next to the `)` closing the group starting at `start`.
+fn skip_group(chars: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => {
+                i = skip_class(chars, i);
+                continue;
+            }
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// Returns the index next to the `]` closing the class starting at `start`.
+fn skip_class(chars: &[char], start: usize) -> usize {
+    let mut i = start + 1;
+    if chars.get(i) == Some(&'^') {
+        i += 1;
+    }
+    // 先頭の ] は文字として扱われる
+    if chars.get(i) == Some(&']') {
+        i += 1;
+    }
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' if chars.get(i + 1) == Some(&':') => {
+                i = chars[i..]
+                    .windows(2)
+                    .position(|x| x == [':', ']'])
+                    .map(|x| i + x + 1)
+                    .unwrap_or(chars.len());
+            }
+            ']' => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// Lines with matches, by their indices in the text
+struct LineGroup {
+    first: usize,
+    last: usize,
+    /// Byte ranges of the matches in the text
+    ranges: Vec<(usize, usize)>,
+}
+
+/// Finds the matches in the text, grouping the ones on the same lines.
+/// `first_line` is the line number of the first line of the text.
+fn find_matches(text: &str, regex: &Regex, first_line: usize, context_lines: usize) -> Vec<GrepMatch> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_of = |position: usize| line_starts.partition_point(|x| *x <= position) - 1;
+    let line_end = |line: usize| line_starts.get(line + 1).map(|x| x - 1).unwrap_or(text.len());
+    let line_text = |line: usize| text[line_starts[line]..line_end(line)].trim_end_matches('\r');
+
+    let mut groups: Vec<LineGroup> = vec![];
+    for m in regex.find_iter(text).filter(|x| !x.as_str().is_empty()) {
+        let first = line_of(m.start());
+        let last = line_of(m.end() - 1).max(first);
+        match groups.last_mut() {
+            Some(group) if first <= group.last => {
+                group.last = last.max(group.last);
+                group.ranges.push((m.start(), m.end()));
+            }
+            _ => {
+                if groups.len() == MAX_MATCHES_PER_DOCUMENT {
+                    break;
+                }
+                groups.push(LineGroup {
+                    first,
+                    last,
+                    ranges: vec![(m.start(), m.end())],
+                });
+            }
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|LineGroup { first, last, ranges }| {
+            let start = line_starts[first];
+            let span = text[start..line_end(last)].trim_end_matches('\r');
+            let ranges = ranges
+                .into_iter()
+                .map(|(s, e)| (s - start, (e - start).min(span.len())))
+                .filter(|(s, e)| s < e)
+                .collect();
+            GrepMatch {
+                line: first_line + first,
+                highlights: to_highlight_ranges(span, ranges),
+                before: (first.saturating_sub(context_lines)..first)
+                    .map(|x| line_text(x).to_string())
+                    .collect(),
+                after: (last + 1..(last + 1 + context_lines).min(line_starts.len()))
+                    .map(|x| line_text(x).to_string())
+                    .collect(),
+                text: span.to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_literals() {
+        assert_eq!(required_literals(r"TODO\(\w+\)"), vec!["TODO(", ")"]);
+        assert_eq!(required_literals(r"colou?r"), vec!["colo", "r"]);
+        assert_eq!(required_literals(r"ab+c"), vec!["ab", "c"]);
+        assert_eq!(required_literals(r"foo(bar|baz)[0-9]{2}qux"), vec!["foo", "qux"]);
+        assert_eq!(required_literals(r"(?i)hello\.world"), vec!["hello.world"]);
+        assert_eq!(required_literals(r"[]a]x"), vec!["x"]);
+        assert_eq!(required_literals(r"foo|bar"), Vec::<String>::new());
+        assert_eq!(required_literals(r"(?x) foo bar"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_find_matches() {
+        let text = "a\nb\nTODO(me) and TODO(you)\nc\nd\ne";
+        let regex = Regex::new(r"TODO\(\w+\)").unwrap();
+        let matches = find_matches(text, &regex, 5, 2);
+        assert_eq!(matches.len(), 1);
+        let m = &matches[0];
+        assert_eq!(m.line, 7);
+        assert_eq!(m.text, "TODO(me) and TODO(you)");
+        assert_eq!(
+            m.highlights.iter().map(|x| (x.start, x.end)).collect::<Vec<_>>(),
+            vec![(0, 8), (13, 22)]
+        );
+        assert_eq!(m.before, vec!["a", "b"]);
+        assert_eq!(m.after, vec!["c", "d"]);
+    }
+
+    #[test]
+    fn test_find_matches_across_lines() {
+        let text = "foo\r\nbar\nbaz";
+        let regex = Regex::new(r"o\r?\nb").unwrap();
+        let matches = find_matches(text, &regex, 1, 1);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].line, 1);
+        assert_eq!(matches[0].text, "foo\r\nbar");
+        assert_eq!(matches[0].before, Vec::<String>::new());
+        assert_eq!(matches[0].after, vec!["baz"]);
+        assert!(find_matches(text, &Regex::new("x*").unwrap(), 1, 1).is_empty());
+    }
+}
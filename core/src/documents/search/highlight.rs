@@ -0,0 +1,218 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+use tantivy::{schema::Field, Document};
+
+use crate::documents::search::{
+    schema::AppSchema,
+    snippet::{to_highlight_ranges, HighlightRange, QueryTerms, SnippetFragment, SnippetOptions},
+    Language, Search, SearchOptions,
+};
+
+#[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchedField {
+    Title,
+    Tag,
+    Path,
+    Contents,
+}
+
+/// Parts of a document matching the query.
+#[derive(Debug, Default)]
+pub(super) struct Highlights {
+    pub snippets: Vec<SnippetFragment>,
+    pub title: Vec<HighlightRange>,
+    pub path: Vec<HighlightRange>,
+    pub matched_fields: Vec<MatchedField>,
+}
+
+impl Search {
+    /// Finds where the query terms appear in the title, tags, path and contents of the document.
+    /// Terms which are not in the query as they are, e.g. prefixes and words with typos, are not highlighted.
+    pub(super) fn make_highlights(
+        &self,
+        schema: &AppSchema,
+        query_terms: &QueryTerms,
+        doc: &Document,
+        language: Language,
+        snippet_options: SnippetOptions,
+    ) -> Result<Highlights> {
+        let mut matched_fields = vec![];
+
+        let title = get_text(doc, schema.title_ngram());
+        let title = self.highlight_text(
+            query_terms,
+            &[schema.title_for_language(language), schema.title_ngram()],
+            title,
+        )?;
+        if !title.is_empty() {
+            matched_fields.push(MatchedField::Title);
+        }
+
+        for tag in doc.get_all(schema.tag_ngram()).filter_map(|x| x.as_text()) {
+            let tag_fields = [schema.tag_for_language(language), schema.tag_ngram()];
+            if !self.highlight_text(query_terms, &tag_fields, tag)?.is_empty() {
+                matched_fields.push(MatchedField::Tag);
+                break;
+            }
+        }
+
+        let path = get_text(doc, schema.path());
+        let mut path_ranges = find_path_component_ranges(path, |component| {
+            query_terms.contains(schema.path_components(), component)
+        });
+        if query_terms.contains(schema.path(), path) {
+            path_ranges.push((0, path.len()));
+        }
+        // n-gram は他の語の一部にも一致するので、パスの要素が一致しないときだけ使う
+        let path = if path_ranges.is_empty() {
+            self.highlight_text(query_terms, &[schema.path_ngram()], path)?
+        } else {
+            to_highlight_ranges(path, path_ranges)
+        };
+        if !path.is_empty() {
+            matched_fields.push(MatchedField::Path);
+        }
+
+        let snippets = self.make_snippet(schema, query_terms, doc, language, snippet_options)?;
+        if !snippets.is_empty() {
+            matched_fields.push(MatchedField::Contents);
+        }
+
+        Ok(Highlights {
+            snippets,
+            title,
+            path,
+            matched_fields,
+        })
+    }
+
+    /// Returns a function finding the query terms in parts of the contents of the document, for the preview.
+    /// As with the snippets, partial words are highlighted only if no whole word matches.
+    pub(crate) fn contents_highlighter<P: AsRef<Path>>(
+        &self,
+        query: &str,
+        path: P,
+    ) -> Result<impl Fn(&str) -> Result<Vec<(usize, usize)>> + '_> {
+        let schema = AppSchema::new(self.index.schema());
+        let searcher = self.index_reader.searcher();
+        let (query, _) = self.build_user_query(query, &SearchOptions::default())?;
+        let query_terms = self.query_terms(&searcher, query.as_ref())?;
+        let language = match self.find_doc_address_by_path(&searcher, path)? {
+            Some(doc_address) => searcher
+                .doc(doc_address)?
+                .get_first(schema.language())
+                .map(|x| x.as_u64().unwrap().into())
+                .unwrap_or(Language::English),
+            None => Language::English,
+        };
+
+        Ok(move |text: &str| {
+            let fields = [schema.contents_for_language(language), schema.contents_ngram()];
+            let highlights = self.highlight_text(&query_terms, &fields, text)?;
+            Ok(highlights.into_iter().map(|x| (x.start, x.end)).collect())
+        })
+    }
+
+    /// Highlights the query terms of the first field which has any in the text.
+    /// Give the language field before the ngram field, so that partial words are highlighted only if no whole word
+    /// matches.
+    fn highlight_text(&self, query_terms: &QueryTerms, fields: &[Field], text: &str) -> Result<Vec<HighlightRange>> {
+        for field in fields {
+            let ranges: Vec<(usize, usize)> = self
+                .find_token_matches(query_terms, *field, text)?
+                .into_iter()
+                .map(|x| (x.start, x.end))
+                .collect();
+            if !ranges.is_empty() {
+                return Ok(to_highlight_ranges(text, ranges));
+            }
+        }
+        Ok(vec![])
+    }
+}
+
+fn get_text(doc: &Document, field: Field) -> &str {
+    doc.get_first(field).and_then(|x| x.as_text()).unwrap_or_default()
+}
+
+/// Returns the byte ranges of the components of the normalized path which satisfy the predicate.
+fn find_path_component_ranges<F: Fn(&str) -> bool>(path: &str, predicate: F) -> Vec<(usize, usize)> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    for component in path.split('/') {
+        if !component.is_empty() && predicate(component) {
+            ranges.push((start, start + component.len()));
+        }
+        start += component.len() + 1;
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::documents::search::{tests::index_documents, Sort};
+
+    #[test]
+    fn test_find_path_component_ranges() {
+        let path = "/Users/me/notes/work/roadmap.md";
+        assert_eq!(
+            find_path_component_ranges(path, |x| x == "work" || x == "roadmap.md"),
+            vec![(16, 20), (21, 31)]
+        );
+        assert_eq!(find_path_component_ranges(path, |x| x == "wor"), vec![]);
+    }
+
+    #[test]
+    fn test_make_highlights() -> Result<()> {
+        let index = index_documents(&[
+            ("Users.md", "# Rust max\n\nrust axum"),
+            ("axum-notes.md", "# Notes\n\naxum"),
+        ])?;
+        let search = |query: &str| {
+            index
+                .search
+                .search_document(query, Sort::Relevance, &SearchOptions::default(), 0, 10)
+        };
+
+        // 言語のフィールドで一致した語があれば、n-gram で一致した語の一部 (max の ax) はハイライトしない
+        let results = search("rust axum")?;
+        let document = results
+            .documents
+            .iter()
+            .find(|x| x.path == index.path("Users.md"))
+            .unwrap();
+        assert_eq!(
+            document.matched_fields,
+            vec![MatchedField::Title, MatchedField::Contents]
+        );
+        assert_eq!(
+            document
+                .title_highlights
+                .iter()
+                .map(|x| (x.start, x.end))
+                .collect::<Vec<_>>(),
+            vec![(0, 4)]
+        );
+        assert!(document.path_highlights.is_empty());
+
+        let results = search("rust")?;
+        assert_eq!(results.documents.len(), 1);
+        assert!(results.documents[0].path_highlights.is_empty());
+        assert!(!results.documents[0].matched_fields.contains(&MatchedField::Path));
+
+        // パスの要素に一致しなければ、語の一部をハイライトする
+        let results = search("axum")?;
+        let document = results
+            .documents
+            .iter()
+            .find(|x| x.path == index.path("axum-notes.md"))
+            .unwrap();
+        assert!(document.matched_fields.contains(&MatchedField::Path));
+        assert!(!document.path_highlights.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+
+/// Version of the index format.
+/// Bump this when a change of the schema or the analyzers requires all documents to be indexed again.
+pub const INDEX_VERSION: u32 = 10;
+
+const INDEX_VERSION_FILE_NAME: &str = "index_version";
+
+pub fn read<P: AsRef<Path>>(index_dir: P) -> Result<Option<u32>> {
+    let path = index_dir.as_ref().join(INDEX_VERSION_FILE_NAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(fs::read_to_string(path)?.trim().parse()?))
+}
+
+pub fn write<P: AsRef<Path>>(index_dir: P) -> Result<()> {
+    fs::write(
+        index_dir.as_ref().join(INDEX_VERSION_FILE_NAME),
+        INDEX_VERSION.to_string(),
+    )?;
+    Ok(())
+}
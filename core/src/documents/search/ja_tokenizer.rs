@@ -13,20 +13,38 @@ use tantivy_vibrato::VibratoTokenizer;
 use crate::{
     assets::Assets,
     documents::search::{
-        japanese_character_normalizer::JapaneseCharacterTypeNormalizer, stopwords::load_stopwords_for_lang,
+        japanese_character_normalizer::JapaneseCharacterTypeNormalizer, nfkc_normalizer::NfkcNormalizer,
+        stopwords::load_stopwords_for_lang, user_dictionary::UserDictionary,
     },
 };
 
-pub fn setup_tokenizer(index: &Index) -> Result<()> {
-    let dict_data = Assets::get_vibrato_dict_data();
-    let tokenizer = VibratoTokenizer::from_reader(&*dict_data)?;
+pub fn setup_tokenizer(index: &Index, user_dictionary: &UserDictionary) -> Result<()> {
+    let tokenizer = VibratoTokenizer::new(vibrato::Tokenizer::new(load_dictionary(user_dictionary)?));
     let stopwords = load_stopwords_for_lang("ja")?;
     let analyzer = TextAnalyzer::from(tokenizer)
         .filter(RemoveLongFilter::limit(40))
-        .filter(LowerCaser)
+        .filter(NfkcNormalizer)
         .filter(JapaneseCharacterTypeNormalizer)
         .filter(StopWordFilter::remove(stopwords));
 
     index.tokenizers().register("lang_ja", analyzer);
     Ok(())
 }
+
+fn load_dictionary(user_dictionary: &UserDictionary) -> Result<vibrato::Dictionary> {
+    let dict_data = Assets::get_vibrato_dict_data();
+    let dictionary = vibrato::Dictionary::read(&*dict_data)?;
+    let user_dictionary_data = match user_dictionary.data() {
+        None => return Ok(dictionary),
+        Some(x) => x,
+    };
+
+    match dictionary.reset_user_lexicon_from_reader(Some(user_dictionary_data)) {
+        Ok(dictionary) => Ok(dictionary),
+        Err(e) => {
+            // 壊れたユーザー辞書で起動できなくなるのを避けるため、システム辞書のみで続行する
+            tracing::error!("Failed to load the user dictionary, ignoring it: {}", e);
+            Ok(vibrato::Dictionary::read(&*dict_data)?)
+        }
+    }
+}
@@ -1,5 +1,15 @@
 use tantivy::tokenizer::{BoxTokenStream, Token, TokenFilter, TokenStream};
 
+/// Converts full-width ASCII to half-width, half-width katakana to full-width and then katakana to hiragana.
+pub(super) fn normalize_character_type(text: &str) -> String {
+    // カタカナをひらがなに変換する
+    // ただしカタカナ語のひらがなでの検索はうまくいかないことが多い。
+    // ひらがなでのクエリはトークナイズがカタカナ語と異なる結果となるため。「めも」-> 「め」「も」など
+    let text = kana::wide2ascii(text);
+    let text = kana::half2kana(&text);
+    kana::kata2hira(&text)
+}
+
 #[derive(Clone)]
 pub struct JapaneseCharacterTypeNormalizer;
 
@@ -19,14 +29,7 @@ impl<'a> TokenStream for JapaneseCharacterTypeNormalizerTokenStream<'a> {
             return false;
         }
 
-        // カタカナをひらがなに変換する
-        // ただしカタカナ語のひらがなでの検索はうまくいかないことが多い。
-        // ひらがなでのクエリはトークナイズがカタカナ語と異なる結果となるため。「めも」-> 「め」「も」など
-        let mut text = kana::wide2ascii(&self.token().text);
-        text = kana::half2kana(&text);
-        text = kana::kata2hira(&text);
-
-        self.tail.token_mut().text = text;
+        self.tail.token_mut().text = normalize_character_type(&self.token().text);
         true
     }
 
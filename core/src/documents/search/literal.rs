@@ -0,0 +1,69 @@
+use anyhow::Result;
+use tantivy::{
+    query::{BooleanQuery, BoostQuery, Occur, PhraseQuery, Query, TermQuery},
+    schema::{Field, IndexRecordOption, Term},
+};
+
+use crate::documents::search::Search;
+
+impl Search {
+    /// Builds a query matching documents containing every word of the text in any of the fields.
+    /// The text is not parsed with the query syntax, so every character is searched for as it is typed.
+    /// Stopwords and synonyms of the query expansion are applied to the words.
+    pub(super) fn build_literal_query(&self, text: &str, fields: &[(Field, f32)]) -> Result<Box<dyn Query>> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        self.build_all_words_query(self.query_expansion.expand_words(&words), fields)
+    }
+
+    /// Builds a query matching the text of a field filter as it is typed, as a phrase if `phrase` is true.
+    pub(super) fn build_literal_filter_query(
+        &self,
+        text: &str,
+        phrase: bool,
+        fields: &[Field],
+    ) -> Result<Box<dyn Query>> {
+        let fields: Vec<(Field, f32)> = fields.iter().map(|x| (*x, 1.0)).collect();
+        let words = if phrase {
+            vec![vec![text.to_string()]]
+        } else {
+            text.split_whitespace().map(|x| vec![x.to_string()]).collect()
+        };
+        self.build_all_words_query(words, &fields)
+    }
+
+    /// Each word is given with its alternatives, any of which matches the word.
+    fn build_all_words_query(&self, words: Vec<Vec<String>>, fields: &[(Field, f32)]) -> Result<Box<dyn Query>> {
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![];
+        for alternatives in words {
+            let mut alternative_clauses = vec![];
+            for alternative in &alternatives {
+                alternative_clauses.extend(self.build_phrase_clauses(alternative, fields)?);
+            }
+            // 記号だけの語などトークンが残らない語は条件にしない
+            if !alternative_clauses.is_empty() {
+                clauses.push((Occur::Must, Box::new(BooleanQuery::new(alternative_clauses))));
+            }
+        }
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    /// Returns a clause for each field matching the tokens of the text as a phrase.
+    fn build_phrase_clauses(&self, text: &str, fields: &[(Field, f32)]) -> Result<Vec<(Occur, Box<dyn Query>)>> {
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![];
+        for (field, boost) in fields {
+            let analyzer = self.index.tokenizer_for_field(*field)?;
+            let mut terms = vec![];
+            analyzer
+                .token_stream(text)
+                .process(&mut |token| terms.push((token.position, Term::from_field_text(*field, &token.text))));
+            // QueryParser と同じく、複数のトークンに分かれる語はフレーズとして検索する
+            let query: Box<dyn Query> = match terms.len() {
+                0 => continue,
+                1 => Box::new(TermQuery::new(terms.remove(0).1, IndexRecordOption::WithFreqs)),
+                _ => Box::new(PhraseQuery::new_with_offset(terms)),
+            };
+            clauses.push((Occur::Should, Box::new(BoostQuery::new(query, *boost))));
+        }
+        Ok(clauses)
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use tantivy::{
+    collector::{Count, DocSetCollector},
+    Index,
+};
+
+use crate::{
+    documents::search::{schema::AppSchema, Search, SearchOptions},
+    WatchId,
+};
+
+/// Tokenizers registered in `Search::open_index`
+const TOKENIZER_NAMES: [&str; 4] = ["lang_en", "lang_ja", "ngram", "spelling"];
+
+/// Minimum memory of the index writer allowed by tantivy
+const RAM_INDEX_WRITER_MEMORY: usize = 15_000_000;
+
+impl Search {
+    /// Returns the paths and the watches of all the documents matching the query and the tags.
+    pub(crate) fn find_matching_paths(&self, query: &str, tags: &[&str]) -> Result<Vec<(PathBuf, WatchId)>> {
+        let schema = AppSchema::new(self.index.schema());
+        let (query, _) = self.build_query_with_tags(query, tags, &SearchOptions::default())?;
+        let searcher = self.index_reader.searcher();
+        let doc_addresses = searcher.search(&query, &DocSetCollector)?;
+        let mut paths = Vec::with_capacity(doc_addresses.len());
+        for doc_address in doc_addresses {
+            let doc = searcher.doc(doc_address)?;
+            let path = doc.get_first(schema.path()).unwrap().as_text().unwrap();
+            let watch_id = doc.get_first(schema.watch_id()).unwrap().as_i64().unwrap();
+            paths.push((PathBuf::from(path), WatchId(watch_id)));
+        }
+        Ok(paths)
+    }
+
+    /// Tests each pair of a query and tags against the file, returning whether the document matches it.
+    /// The file is indexed in memory, since the index may not have been committed yet when a document is added.
+    pub(crate) fn match_document<P: AsRef<Path>>(
+        &self,
+        path: P,
+        watch_id: WatchId,
+        queries: &[(&str, Vec<&str>)],
+    ) -> Result<Vec<bool>> {
+        let document = self.make_document(path, watch_id)?;
+        let index = Index::create_in_ram(self.index.schema());
+        for name in TOKENIZER_NAMES {
+            if let Some(tokenizer) = self.index.tokenizers().get(name) {
+                index.tokenizers().register(name, tokenizer);
+            }
+        }
+        let mut index_writer = index.writer_with_num_threads(1, RAM_INDEX_WRITER_MEMORY)?;
+        index_writer.add_document(document)?;
+        index_writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        queries
+            .iter()
+            .map(|(query, tags)| {
+                // クエリは本体のインデックスのトークナイザで組み立て、メモリ上の文書に対して実行する
+                let (query, _) = self.build_query_with_tags(query, tags, &SearchOptions::default())?;
+                Ok(searcher.search(&query, &Count)? > 0)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs, sync::Arc};
+
+    use super::*;
+    use crate::documents::{annotations::DocumentAnnotation, search::tests::index_documents};
+
+    #[test]
+    fn test_match_document() -> Result<()> {
+        let index = index_documents(&[])?;
+        // 索引にまだ追加されていない文書も、ファイルから判定する
+        let path = index.dir.path().join("a.md");
+        fs::write(&path, "---\ntags: [backend]\n---\n# Rust\n\nrust tokio\n")?;
+        let annotation = DocumentAnnotation {
+            path: index.path("a.md"),
+            pinned: true,
+            labels: vec![],
+            note: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        let annotated = index
+            .search
+            .with_annotations(Arc::new(HashMap::from([(annotation.path.clone(), annotation)])));
+
+        let queries = [
+            ("rust is:pinned", vec!["backend"]),
+            ("tokio", vec![]),
+            ("rust", vec!["cooking"]),
+            ("axum", vec![]),
+        ];
+        assert_eq!(
+            annotated.match_document(&path, WatchId(1), &queries)?,
+            vec![true, true, false, false]
+        );
+        assert_eq!(
+            index.search.match_document(&path, WatchId(1), &queries[..1])?,
+            vec![false]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use tantivy::tokenizer::{BoxTokenStream, Token, TokenFilter, TokenStream};
+use unicode_normalization::UnicodeNormalization;
+
+/// Applies Unicode NFKC normalization and case folding to tokens
+/// so that full-width characters such as `ＡＰＩ` or `１２３` match their ASCII counterparts.
+#[derive(Clone)]
+pub struct NfkcNormalizer;
+
+impl TokenFilter for NfkcNormalizer {
+    fn transform<'a>(&self, token_stream: BoxTokenStream<'a>) -> BoxTokenStream<'a> {
+        BoxTokenStream::from(NfkcNormalizerTokenStream { tail: token_stream })
+    }
+}
+
+struct NfkcNormalizerTokenStream<'a> {
+    tail: BoxTokenStream<'a>,
+}
+
+impl<'a> TokenStream for NfkcNormalizerTokenStream<'a> {
+    fn advance(&mut self) -> bool {
+        if !self.tail.advance() {
+            return false;
+        }
+
+        let text = normalize(&self.token().text);
+        self.tail.token_mut().text = text;
+        true
+    }
+
+    fn token(&self) -> &Token {
+        self.tail.token()
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        self.tail.token_mut()
+    }
+}
+
+pub fn normalize(text: &str) -> String {
+    text.nfkc().flat_map(|c| c.to_lowercase()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("ＡＰＩ"), "api");
+        assert_eq!(normalize("１２３"), "123");
+        assert_eq!(normalize("ｶﾀｶﾅ"), "カタカナ");
+        assert_eq!(normalize("Rust"), "rust");
+    }
+}
@@ -4,7 +4,7 @@ use tantivy::{
     Index,
 };
 
-use crate::documents::search::stopwords::load_stopwords_for_lang;
+use crate::documents::search::{nfkc_normalizer::NfkcNormalizer, stopwords::load_stopwords_for_lang};
 
 pub fn setup_tokenizer(index: &Index) -> Result<()> {
     let tokenizer = tantivy::tokenizer::NgramTokenizer::all_ngrams(2, 2);
@@ -12,7 +12,7 @@ pub fn setup_tokenizer(index: &Index) -> Result<()> {
     stopwords.extend(load_stopwords_for_lang("en")?);
     stopwords.extend(load_stopwords_for_lang("ja")?);
     let analyzer = TextAnalyzer::from(tokenizer)
-        .filter(LowerCaser)
+        .filter(NfkcNormalizer)
         .filter(StopWordFilter::remove(stopwords));
 
     index.tokenizers().register("ngram", analyzer);
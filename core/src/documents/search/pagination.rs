@@ -0,0 +1,354 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tantivy::{query::Query, DocAddress, DocId, Score, Searcher, SegmentId, SegmentOrdinal};
+use thiserror::Error;
+
+use crate::documents::search::{Search, Sort};
+
+/// How long a searcher is kept for the cursors after the last page was returned
+const SNAPSHOT_TTL: Duration = Duration::from_secs(5 * 60);
+/// Maximum number of searchers kept at the same time, since each of them keeps the segment files open
+const MAX_SNAPSHOTS: usize = 8;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CursorError {
+    #[error("invalid cursor")]
+    Invalid,
+    /// The snapshot of the index the cursor refers to has been released. Search again from the first page.
+    #[error("the cursor has expired")]
+    Expired,
+    /// The cursor was returned for another query, sort or filters. Search again from the first page.
+    #[error("the cursor belongs to another search")]
+    Mismatch,
+}
+
+/// Sort key of the last document of a page
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(super) enum CursorKey {
+    Score(Score),
+    Date(i64),
+    /// Value of a numeric fast field like the word count
+    Number(i64),
+    /// Text sorts read all the matching documents anyway, so only the position is used.
+    Position,
+}
+
+/// Where the next page starts. Serialized into an opaque string for clients.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(super) struct Cursor {
+    /// Generation of the searcher, to see the same snapshot of the index on every page
+    generation: u64,
+    /// `search_hash` of the search, since the key and the position only make sense for the same search
+    search_hash: u64,
+    /// Number of the documents in the previous pages
+    position: usize,
+    key: CursorKey,
+    doc_address: DocAddress,
+}
+
+impl Cursor {
+    pub(super) fn encode(&self) -> String {
+        let key = match self.key {
+            CursorKey::Score(x) => format!("s{:x}", x.to_bits()),
+            CursorKey::Date(x) => format!("d{:x}", x as u64),
+            CursorKey::Number(x) => format!("n{:x}", x as u64),
+            CursorKey::Position => "p".to_string(),
+        };
+        format!(
+            "{:x}.{:x}.{:x}.{}.{:x}.{:x}",
+            self.generation,
+            self.search_hash,
+            self.position,
+            key,
+            self.doc_address.segment_ord,
+            self.doc_address.doc_id
+        )
+    }
+
+    pub(super) fn decode(text: &str) -> Result<Self, CursorError> {
+        let parts: Vec<&str> = text.split('.').collect();
+        if parts.len() != 6 {
+            return Err(CursorError::Invalid);
+        }
+        let hex = |x: &str| u64::from_str_radix(x, 16).map_err(|_| CursorError::Invalid);
+        let key = if let Some(x) = parts[3].strip_prefix('s') {
+            CursorKey::Score(Score::from_bits(hex(x)? as u32))
+        } else if let Some(x) = parts[3].strip_prefix('d') {
+            CursorKey::Date(hex(x)? as i64)
+        } else if let Some(x) = parts[3].strip_prefix('n') {
+            CursorKey::Number(hex(x)? as i64)
+        } else if parts[3] == "p" {
+            CursorKey::Position
+        } else {
+            return Err(CursorError::Invalid);
+        };
+        Ok(Cursor {
+            generation: hex(parts[0])?,
+            search_hash: hex(parts[1])?,
+            position: hex(parts[2])? as usize,
+            key,
+            doc_address: DocAddress::new(hex(parts[4])? as SegmentOrdinal, hex(parts[5])? as DocId),
+        })
+    }
+}
+
+/// Page of the search results
+pub(super) struct Page {
+    /// Same searcher as the previous pages if the page continues from a cursor
+    pub(super) searcher: Searcher,
+    /// Unix time to compute the recency boost, fixed for the snapshot so that scores don't change between pages
+    pub(super) ranking_time: i64,
+    /// Number of the documents before the page
+    pub(super) offset: usize,
+    pub(super) limit: usize,
+    search_hash: u64,
+    /// Key and address of the last document of the previous page
+    after: Option<(CursorKey, DocAddress)>,
+}
+
+impl Page {
+    /// Returns the score and the address of the last document of the previous page.
+    pub(super) fn after_score(&self) -> Result<Option<After<Score>>, CursorError> {
+        match self.after {
+            None => Ok(None),
+            Some((CursorKey::Score(key), doc_address)) => Ok(Some(After { key, doc_address })),
+            Some(_) => Err(CursorError::Invalid),
+        }
+    }
+
+    /// Returns the date and the address of the last document of the previous page.
+    pub(super) fn after_date(&self) -> Result<Option<After<i64>>, CursorError> {
+        match self.after {
+            None => Ok(None),
+            Some((CursorKey::Date(key), doc_address)) => Ok(Some(After { key, doc_address })),
+            Some(_) => Err(CursorError::Invalid),
+        }
+    }
+
+    /// Returns the number and the address of the last document of the previous page.
+    pub(super) fn after_number(&self) -> Result<Option<After<i64>>, CursorError> {
+        match self.after {
+            None => Ok(None),
+            Some((CursorKey::Number(key), doc_address)) => Ok(Some(After { key, doc_address })),
+            Some(_) => Err(CursorError::Invalid),
+        }
+    }
+
+    /// Text sorts continue from the position, which is stable on the same searcher.
+    pub(super) fn check_position_cursor(&self) -> Result<(), CursorError> {
+        match self.after {
+            None | Some((CursorKey::Position, _)) => Ok(()),
+            Some(_) => Err(CursorError::Invalid),
+        }
+    }
+
+    /// Returns true if the documents before the page are skipped by the key instead of the offset.
+    pub(super) fn has_cursor(&self) -> bool {
+        self.after.is_some()
+    }
+
+    /// Returns the cursor of the next page, if any.
+    pub(super) fn next_cursor(
+        &self,
+        count: usize,
+        len: usize,
+        last: Option<(CursorKey, DocAddress)>,
+    ) -> Option<Cursor> {
+        let position = self.offset + len;
+        if position >= count {
+            return None;
+        }
+        let (key, doc_address) = last?;
+        Some(Cursor {
+            generation: self.searcher.generation().generation_id(),
+            search_hash: self.search_hash,
+            position,
+            key,
+            doc_address,
+        })
+    }
+}
+
+/// Position of a document in the order of `TopDocs`, which is the key descending and then the address ascending
+#[derive(Debug, Copy, Clone)]
+pub(super) struct After<T> {
+    key: T,
+    doc_address: DocAddress,
+}
+
+impl<T: PartialOrd> After<T> {
+    /// Returns true if the document is in this or the previous pages.
+    pub(super) fn covers(&self, key: T, doc_address: DocAddress) -> bool {
+        key > self.key || (key == self.key && doc_address <= self.doc_address)
+    }
+}
+
+/// Identifies the query, the sort and the filters of a search, which are all in the built query.
+/// Cursors are kept in memory only, so the hash doesn't have to be stable across versions.
+pub(super) fn search_hash(query: &dyn Query, sort: Sort) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", query).hash(&mut hasher);
+    sort.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Ordinals of the segments of the searcher, since scorers only get the segment readers
+pub(super) fn segment_ordinals(searcher: &Searcher) -> HashMap<SegmentId, SegmentOrdinal> {
+    searcher
+        .segment_readers()
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (x.segment_id(), i as SegmentOrdinal))
+        .collect()
+}
+
+struct Snapshot {
+    searcher: Searcher,
+    ranking_time: i64,
+    last_used: Instant,
+}
+
+/// Searchers kept for the cursors, so that the pages of a search don't shift when the index is committed
+#[derive(Default)]
+pub(super) struct Snapshots {
+    snapshots: Mutex<Vec<Snapshot>>,
+}
+
+impl Snapshots {
+    /// Returns the searcher and the ranking time of the generation.
+    fn get(&self, generation: u64) -> Option<(Searcher, i64)> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let now = Instant::now();
+        snapshots.retain(|x| now.duration_since(x.last_used) < SNAPSHOT_TTL);
+        let snapshot = snapshots
+            .iter_mut()
+            .find(|x| x.searcher.generation().generation_id() == generation)?;
+        snapshot.last_used = now;
+        Some((snapshot.searcher.clone(), snapshot.ranking_time))
+    }
+
+    fn keep(&self, searcher: &Searcher, ranking_time: i64) {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let now = Instant::now();
+        let generation = searcher.generation().generation_id();
+        match snapshots
+            .iter_mut()
+            .find(|x| x.searcher.generation().generation_id() == generation)
+        {
+            // 同じ世代の検索は page() で既存の時刻を使っているので、使った時刻だけ更新する
+            Some(x) => x.last_used = now,
+            None => snapshots.push(Snapshot {
+                searcher: searcher.clone(),
+                ranking_time,
+                last_used: now,
+            }),
+        }
+        // 最近使われたものから残す
+        snapshots.sort_by_key(|x| std::cmp::Reverse(x.last_used));
+        snapshots.truncate(MAX_SNAPSHOTS);
+    }
+}
+
+impl Search {
+    /// Returns the page starting at the offset, or continuing from the cursor on the same snapshot of the index.
+    /// The cursor has to be returned for the search with the same `search_hash`.
+    pub(super) fn page(
+        &self,
+        offset: usize,
+        limit: usize,
+        cursor: Option<&str>,
+        search_hash: u64,
+    ) -> Result<Page, CursorError> {
+        let cursor = match cursor {
+            None => {
+                let searcher = self.index_reader.searcher();
+                // 同じ世代のスナップショットがあれば、スコアが揃うように同じ時刻を使う
+                let ranking_time = self
+                    .snapshots
+                    .get(searcher.generation().generation_id())
+                    .map(|(_, x)| x)
+                    .unwrap_or_else(|| chrono::Utc::now().timestamp());
+                return Ok(Page {
+                    searcher,
+                    ranking_time,
+                    offset,
+                    limit,
+                    search_hash,
+                    after: None,
+                });
+            }
+            Some(x) => Cursor::decode(x)?,
+        };
+        if cursor.search_hash != search_hash {
+            return Err(CursorError::Mismatch);
+        }
+        let (searcher, ranking_time) = self.snapshots.get(cursor.generation).ok_or(CursorError::Expired)?;
+        Ok(Page {
+            searcher,
+            ranking_time,
+            offset: cursor.position,
+            limit,
+            search_hash,
+            after: Some((cursor.key, cursor.doc_address)),
+        })
+    }
+
+    /// Encodes the cursor, keeping the searcher so that the next page sees the same documents.
+    pub(super) fn encode_cursor(&self, page: &Page, cursor: Cursor) -> String {
+        self.snapshots.keep(&page.searcher, page.ranking_time);
+        cursor.encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        for key in [
+            CursorKey::Score(1.25),
+            CursorKey::Date(-1_656_000_000),
+            CursorKey::Number(-1200),
+            CursorKey::Position,
+        ] {
+            let cursor = Cursor {
+                generation: 42,
+                search_hash: 0x1234_5678_9abc_def0,
+                position: 20,
+                key,
+                doc_address: DocAddress::new(1, 300),
+            };
+            assert_eq!(Cursor::decode(&cursor.encode()), Ok(cursor));
+        }
+    }
+
+    #[test]
+    fn test_invalid_cursor() {
+        assert_eq!(Cursor::decode(""), Err(CursorError::Invalid));
+        assert_eq!(Cursor::decode("2a.ff.14.x1.1.12c"), Err(CursorError::Invalid));
+        assert_eq!(Cursor::decode("2a.ff.14.p.1"), Err(CursorError::Invalid));
+        assert_eq!(Cursor::decode("2a.ff.zz.p.1.12c"), Err(CursorError::Invalid));
+        // 検索を識別するハッシュのない古い形式
+        assert_eq!(Cursor::decode("2a.14.p.1.12c"), Err(CursorError::Invalid));
+    }
+
+    #[test]
+    fn test_after_covers() {
+        let after = After {
+            key: 2.0,
+            doc_address: DocAddress::new(0, 10),
+        };
+        assert!(after.covers(3.0, DocAddress::new(1, 0)));
+        assert!(after.covers(2.0, DocAddress::new(0, 10)));
+        assert!(after.covers(2.0, DocAddress::new(0, 9)));
+        assert!(!after.covers(2.0, DocAddress::new(0, 11)));
+        assert!(!after.covers(2.0, DocAddress::new(1, 0)));
+        assert!(!after.covers(1.0, DocAddress::new(0, 0)));
+    }
+}
@@ -0,0 +1,261 @@
+use std::{collections::HashMap, ops::Bound};
+
+use anyhow::Result;
+use serde::Serialize;
+use tantivy::{
+    collector::TopDocs,
+    query::{BooleanQuery, BoostQuery, Occur, Query, RangeQuery, TermQuery},
+    schema::{Field, IndexRecordOption, Term},
+};
+
+use crate::documents::search::{
+    nfkc_normalizer::normalize,
+    query_expansion::{is_plain_term, split_terms, OPERATORS},
+    schema::AppSchema,
+    Search,
+};
+
+/// Prefix matches are scored lower than whole word matches
+const PREFIX_BOOST: f32 = 0.5;
+const MAX_COMPLETIONS_PER_KIND: usize = 5;
+/// Number of documents looked up for title and tag completions
+const COMPLETION_DOCUMENT_LIMIT: usize = 20;
+
+#[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletionKind {
+    Title,
+    Tag,
+    Term,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Completion {
+    pub text: String,
+    pub kind: CompletionKind,
+}
+
+impl Search {
+    /// Builds a query matching words starting with the term in the title, contents and tag fields.
+    pub(super) fn build_prefix_query(&self, term: &str) -> Result<Option<Box<dyn Query>>> {
+        self.build_prefix_query_for_fields(term, &self.language_fields_with_boosts())
+    }
+
+    fn build_prefix_query_for_fields(&self, term: &str, fields: &[(Field, f32)]) -> Result<Option<Box<dyn Query>>> {
+        let mut field_queries: Vec<(Occur, Box<dyn Query>)> = vec![];
+        for (field, boost) in fields {
+            let analyzer = self.index.tokenizer_for_field(*field)?;
+            let mut tokens = vec![];
+            analyzer
+                .token_stream(term)
+                .process(&mut |token| tokens.push(token.text.clone()));
+            let last_token = match tokens.pop() {
+                Some(x) => x,
+                None => continue,
+            };
+
+            // 日本語のように一語が複数のトークンに分かれる場合は、最後のトークン以外は完全一致させる
+            let mut clauses: Vec<(Occur, Box<dyn Query>)> = tokens
+                .iter()
+                .map(|token| {
+                    let query: Box<dyn Query> = Box::new(TermQuery::new(
+                        Term::from_field_text(*field, token),
+                        IndexRecordOption::Basic,
+                    ));
+                    (Occur::Must, query)
+                })
+                .collect();
+            let upper_bound = prefix_upper_bound(&last_token);
+            let range_query = RangeQuery::new_str_bounds(
+                *field,
+                Bound::Included(last_token.as_str()),
+                upper_bound.as_deref().map_or(Bound::Unbounded, Bound::Excluded),
+            );
+            clauses.push((Occur::Must, Box::new(range_query)));
+            field_queries.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    Box::new(BooleanQuery::new(clauses)),
+                    boost * PREFIX_BOOST,
+                )),
+            ));
+        }
+        if field_queries.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Box::new(BooleanQuery::new(field_queries))))
+    }
+
+    /// Returns titles, tags and words in the index which complete the last term of the text.
+    pub(crate) fn autocomplete(&self, text: &str) -> Result<Vec<Completion>> {
+        let (head, last_term) = match split_last_term(text) {
+            Some(x) => x,
+            None => return Ok(vec![]),
+        };
+        let word = normalize(last_term);
+
+        let mut completions = vec![];
+        for title in self.complete_titles(last_term, &word)? {
+            completions.push(Completion {
+                text: title,
+                kind: CompletionKind::Title,
+            });
+        }
+        for tag in self.complete_tags(last_term, &word)? {
+            completions.push(Completion {
+                text: tag,
+                kind: CompletionKind::Tag,
+            });
+        }
+        for term in self.complete_terms(&word)? {
+            completions.push(Completion {
+                text: format!("{}{}", head, term),
+                kind: CompletionKind::Term,
+            });
+        }
+        Ok(completions)
+    }
+
+    fn complete_titles(&self, term: &str, word: &str) -> Result<Vec<String>> {
+        let schema = AppSchema::new(self.index.schema());
+        let fields: Vec<(Field, f32)> = schema.title_all_languages().into_iter().map(|x| (x, 1.0)).collect();
+        let query = match self.build_prefix_query_for_fields(term, &fields)? {
+            Some(x) => x,
+            None => return Ok(vec![]),
+        };
+
+        let searcher = self.index_reader.searcher();
+        let mut titles: Vec<String> = vec![];
+        for (_, doc_address) in searcher.search(&query, &TopDocs::with_limit(COMPLETION_DOCUMENT_LIMIT))? {
+            let doc = searcher.doc(doc_address)?;
+            if let Some(title) = doc.get_first(schema.title_ngram()).and_then(|x| x.as_text()) {
+                if normalize(title).contains(word) && !titles.iter().any(|x| x == title) {
+                    titles.push(title.to_string());
+                }
+            }
+            if titles.len() >= MAX_COMPLETIONS_PER_KIND {
+                break;
+            }
+        }
+        Ok(titles)
+    }
+
+    fn complete_tags(&self, term: &str, word: &str) -> Result<Vec<String>> {
+        let schema = AppSchema::new(self.index.schema());
+        let fields: Vec<(Field, f32)> = schema.tag_all_languages().into_iter().map(|x| (x, 1.0)).collect();
+        let query = match self.build_prefix_query_for_fields(term, &fields)? {
+            Some(x) => x,
+            None => return Ok(vec![]),
+        };
+
+        let searcher = self.index_reader.searcher();
+        // tag -> number of documents
+        let mut tags: HashMap<String, usize> = HashMap::new();
+        for (_, doc_address) in searcher.search(&query, &TopDocs::with_limit(COMPLETION_DOCUMENT_LIMIT))? {
+            let doc = searcher.doc(doc_address)?;
+            for tag in doc.get_all(schema.tag_ngram()).filter_map(|x| x.as_text()) {
+                if normalize(tag).starts_with(word) {
+                    *tags.entry(tag.to_string()).or_default() += 1;
+                }
+            }
+        }
+        Ok(take_most_frequent(tags))
+    }
+
+    fn complete_terms(&self, word: &str) -> Result<Vec<String>> {
+        let schema = AppSchema::new(self.index.schema());
+        let searcher = self.index_reader.searcher();
+        let upper_bound = prefix_upper_bound(word);
+        // term -> doc_freq
+        let mut terms: HashMap<String, usize> = HashMap::new();
+        for segment_reader in searcher.segment_readers() {
+            for field in schema.spelling_all() {
+                let inverted_index = segment_reader.inverted_index(field)?;
+                let term_dict = inverted_index.terms();
+                let mut range = term_dict.range().ge(word);
+                if let Some(upper_bound) = &upper_bound {
+                    range = range.lt(upper_bound);
+                }
+                let mut stream = range.into_stream()?;
+                while stream.advance() {
+                    let term = match std::str::from_utf8(stream.key()) {
+                        Ok(x) => x,
+                        Err(_) => continue,
+                    };
+                    if term == word {
+                        continue;
+                    }
+                    *terms.entry(term.to_string()).or_default() += stream.value().doc_freq as usize;
+                }
+            }
+        }
+        Ok(take_most_frequent(terms))
+    }
+}
+
+fn take_most_frequent(counts: HashMap<String, usize>) -> Vec<String> {
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|(a_text, a_count), (b_text, b_count)| b_count.cmp(a_count).then(a_text.cmp(b_text)));
+    counts
+        .into_iter()
+        .take(MAX_COMPLETIONS_PER_KIND)
+        .map(|(text, _)| text)
+        .collect()
+}
+
+/// Splits the query into the leading part and the last term being typed.
+/// Returns `None` if the query ends with a whitespace or the last term uses the query syntax.
+pub(super) fn split_last_term(query: &str) -> Option<(&str, &str)> {
+    if query.ends_with(char::is_whitespace) {
+        return None;
+    }
+    let terms = split_terms(query);
+    let last_term = *terms.last()?;
+    if !is_plain_term(last_term) {
+        return None;
+    }
+    if terms.len() >= 2 && OPERATORS.contains(&terms[terms.len() - 2]) {
+        return None;
+    }
+    Some((&query[..query.len() - last_term.len()], last_term))
+}
+
+/// Returns the smallest string greater than all strings starting with the prefix.
+pub(super) fn prefix_upper_bound(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(c) = chars.pop() {
+        // サロゲートの範囲は文字として表せないので飛ばす
+        let next = match c {
+            '\u{D7FF}' => Some('\u{E000}'),
+            _ => char::from_u32(c as u32 + 1),
+        };
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_last_term() {
+        assert_eq!(split_last_term("kube"), Some(("", "kube")));
+        assert_eq!(split_last_term("deploy kube"), Some(("deploy ", "kube")));
+        assert_eq!(split_last_term("deploy "), None);
+        assert_eq!(split_last_term("deploy AND kube"), None);
+        assert_eq!(split_last_term("title:kube"), None);
+        assert_eq!(split_last_term(""), None);
+    }
+
+    #[test]
+    fn test_prefix_upper_bound() {
+        assert_eq!(prefix_upper_bound("kube"), Some("kubf".to_string()));
+        assert_eq!(prefix_upper_bound("データ"), Some("デーダ".to_string()));
+        assert_eq!(prefix_upper_bound("a\u{10FFFF}"), Some("b".to_string()));
+        assert_eq!(prefix_upper_bound(""), None);
+    }
+}
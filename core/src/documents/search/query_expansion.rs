@@ -0,0 +1,274 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use anyhow::Result;
+
+use crate::documents::search::{
+    details::is_cjk, nfkc_normalizer::normalize, stopwords::read_lines, Language, SPECIAL_CHARACTERS,
+};
+
+/// Languages of the files and the suffixes of their names
+const LANGUAGES: [(Language, &str); 2] = [(Language::English, "en"), (Language::Japanese, "ja")];
+pub(super) const OPERATORS: [&str; 3] = ["AND", "OR", "NOT"];
+
+/// User-defined stopwords and synonyms applied to queries.
+///
+/// They are read from `stopwords.txt`, `stopwords-{lang}.txt`, `synonyms.txt` and `synonyms-{lang}.txt`
+/// in the app directory. Since they are applied at query time, existing indexes don't have to be rebuilt.
+/// The files of a language are only applied to the terms in the language.
+///
+/// A line of a synonym file is a comma-separated group of words, e.g. `k8s, kubernetes`.
+#[derive(Debug, Default)]
+pub struct QueryExpansion {
+    /// Keyed by the language of the files, or `None` for the files applied to every term
+    word_sets: HashMap<Option<Language>, WordSets>,
+}
+
+#[derive(Debug, Default)]
+struct WordSets {
+    stopwords: HashSet<String>,
+    synonyms: HashMap<String, Vec<String>>,
+}
+
+impl WordSets {
+    fn load(app_dir: &Path, suffix: &str) -> Result<Self> {
+        let mut word_sets = Self::default();
+        for line in read_user_file(&app_dir.join(format!("stopwords{}.txt", suffix)))? {
+            word_sets.stopwords.insert(normalize(&line));
+        }
+        for line in read_user_file(&app_dir.join(format!("synonyms{}.txt", suffix)))? {
+            word_sets.add_synonym_group(line.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()));
+        }
+        Ok(word_sets)
+    }
+
+    fn add_synonym_group<'a>(&mut self, words: impl Iterator<Item = &'a str>) {
+        let words: Vec<&str> = words.collect();
+        for word in &words {
+            let entry = self.synonyms.entry(normalize(word)).or_default();
+            for other in &words {
+                if normalize(other) != normalize(word) && !entry.iter().any(|x| x == other) {
+                    entry.push(other.to_string());
+                }
+            }
+        }
+    }
+}
+
+impl QueryExpansion {
+    pub fn load<P: AsRef<Path>>(app_dir: P) -> Result<Self> {
+        let app_dir = app_dir.as_ref();
+        let mut word_sets = HashMap::new();
+        word_sets.insert(None, WordSets::load(app_dir, "")?);
+        for (language, suffix) in LANGUAGES {
+            word_sets.insert(Some(language), WordSets::load(app_dir, &format!("-{}", suffix))?);
+        }
+        Ok(Self { word_sets })
+    }
+
+    /// Word sets for every language and for the language of the term
+    fn word_sets_for<'a>(&'a self, term: &str) -> impl Iterator<Item = &'a WordSets> {
+        [None, Some(term_language(term))]
+            .into_iter()
+            .filter_map(|x| self.word_sets.get(&x))
+    }
+
+    fn is_stopword(&self, term: &str) -> bool {
+        let key = normalize(term);
+        self.word_sets_for(term).any(|x| x.stopwords.contains(&key))
+    }
+
+    fn synonyms(&self, term: &str) -> Vec<&str> {
+        let key = normalize(term);
+        let mut synonyms: Vec<&str> = vec![];
+        for synonym in self.word_sets_for(term).filter_map(|x| x.synonyms.get(&key)).flatten() {
+            if !synonyms.contains(&synonym.as_str()) {
+                synonyms.push(synonym);
+            }
+        }
+        synonyms
+    }
+
+    fn is_empty(&self) -> bool {
+        self.word_sets
+            .values()
+            .all(|x| x.stopwords.is_empty() && x.synonyms.is_empty())
+    }
+
+    /// Removes stopwords from the query and adds synonyms to each term.
+    /// Only plain terms are rewritten, terms using the query syntax are left as they are.
+    pub fn expand(&self, query: &str) -> String {
+        if self.is_empty() {
+            return query.to_string();
+        }
+
+        let terms = split_terms(query);
+        let is_operator = |i: usize| terms.get(i).map(|x| OPERATORS.contains(x)).unwrap_or(false);
+        let mut results = vec![];
+        for (i, term) in terms.iter().enumerate() {
+            if !is_plain_term(term) {
+                results.push(term.to_string());
+                continue;
+            }
+            // 演算子の前後のストップワードを取り除くとクエリが壊れるので残す
+            if self.is_stopword(term) && !(i > 0 && is_operator(i - 1)) && !is_operator(i + 1) {
+                continue;
+            }
+            let synonyms = self.synonyms(term);
+            if synonyms.is_empty() {
+                results.push(term.to_string());
+            } else {
+                let mut alternatives = vec![term.to_string()];
+                alternatives.extend(synonyms.iter().map(|x| format!(r#""{}""#, x.replace('"', ""))));
+                results.push(format!("({})", alternatives.join(" OR ")));
+            }
+        }
+
+        if results.is_empty() {
+            // すべてストップワードだった場合は元のクエリで検索する
+            return query.to_string();
+        }
+        results.join(" ")
+    }
+
+    /// Removes stopwords from the words searched for as they are typed.
+    /// Returns the alternatives of each remaining word, the word itself followed by its synonyms.
+    pub(super) fn expand_words(&self, words: &[&str]) -> Vec<Vec<String>> {
+        let alternatives = |word: &str| {
+            let mut alternatives = vec![word.to_string()];
+            alternatives.extend(self.synonyms(word).into_iter().map(|x| x.to_string()));
+            alternatives
+        };
+        let results: Vec<Vec<String>> = words
+            .iter()
+            .filter(|x| !self.is_stopword(x))
+            .map(|x| alternatives(x))
+            .collect();
+        if results.is_empty() {
+            // すべてストップワードだった場合は元の語で検索する
+            return words.iter().map(|x| alternatives(x)).collect();
+        }
+        results
+    }
+}
+
+/// Language of a term of the query, which is too short to be detected like documents.
+/// Terms with Japanese characters are Japanese and the others are English.
+fn term_language(term: &str) -> Language {
+    if term.chars().any(is_cjk) {
+        Language::Japanese
+    } else {
+        Language::English
+    }
+}
+
+fn read_user_file(path: &Path) -> Result<Vec<String>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let lines = read_lines(fs::File::open(path)?)?;
+    Ok(lines.into_iter().filter(|x| !x.starts_with('#')).collect())
+}
+
+/// Splits a query by whitespaces, keeping quoted phrases together.
+pub(super) fn split_terms(query: &str) -> Vec<&str> {
+    let mut terms = vec![];
+    let mut start = None;
+    let mut in_quote = false;
+    for (i, c) in query.char_indices() {
+        if c == '"' {
+            in_quote = !in_quote;
+        }
+        if c.is_whitespace() && !in_quote {
+            if let Some(s) = start.take() {
+                terms.push(&query[s..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        terms.push(&query[s..]);
+    }
+    terms
+}
+
+pub(super) fn is_plain_term(term: &str) -> bool {
+    !OPERATORS.contains(&term) && !term.starts_with('-') && !term.chars().any(|c| SPECIAL_CHARACTERS.contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_query_expansion() -> Result<QueryExpansion> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("stopwords.txt"), "memo\n")?;
+        fs::write(dir.path().join("stopwords-en.txt"), "# English only\nnote\n")?;
+        fs::write(dir.path().join("stopwords-ja.txt"), "メモ\n")?;
+        fs::write(dir.path().join("synonyms.txt"), "k8s, kubernetes\n")?;
+        fs::write(dir.path().join("synonyms-en.txt"), "db, database\n")?;
+        fs::write(dir.path().join("synonyms-ja.txt"), "データベース, DB\n")?;
+        QueryExpansion::load(dir.path())
+    }
+
+    #[test]
+    fn test_synonyms() -> Result<()> {
+        let query_expansion = make_query_expansion()?;
+        assert_eq!(query_expansion.expand("k8s deploy"), r#"(k8s OR "kubernetes") deploy"#);
+        assert_eq!(
+            query_expansion.expand("クラスタ k8s"),
+            r#"クラスタ (k8s OR "kubernetes")"#
+        );
+        // 言語ごとの同義語は、その言語の語にだけ加える
+        assert_eq!(query_expansion.expand("db"), r#"(db OR "database")"#);
+        assert_eq!(query_expansion.expand("データベース"), r#"(データベース OR "DB")"#);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stopwords() -> Result<()> {
+        let query_expansion = make_query_expansion()?;
+        assert_eq!(query_expansion.expand("memo rust"), "rust");
+        assert_eq!(query_expansion.expand("memo"), "memo");
+        assert_eq!(query_expansion.expand("rust AND memo"), "rust AND memo");
+        assert_eq!(query_expansion.expand("note rust"), "rust");
+        assert_eq!(query_expansion.expand("メモ 会議"), "会議");
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_syntax_is_kept() -> Result<()> {
+        let query_expansion = make_query_expansion()?;
+        assert_eq!(
+            query_expansion.expand(r#""k8s memo" title:k8s"#),
+            r#""k8s memo" title:k8s"#
+        );
+        assert_eq!(query_expansion.expand("-k8s"), "-k8s");
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_words() -> Result<()> {
+        let query_expansion = make_query_expansion()?;
+        assert_eq!(
+            query_expansion.expand_words(&["memo", "K8s", "C++"]),
+            vec![
+                vec!["K8s".to_string(), "kubernetes".to_string()],
+                vec!["C++".to_string()]
+            ]
+        );
+        assert_eq!(query_expansion.expand_words(&["memo"]), vec![vec!["memo".to_string()]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_term_language() {
+        assert_eq!(term_language("rust"), Language::English);
+        assert_eq!(term_language("会議"), Language::Japanese);
+        assert_eq!(term_language("Rustのメモ"), Language::Japanese);
+    }
+}
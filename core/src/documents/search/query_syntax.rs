@@ -0,0 +1,700 @@
+use std::ops::Bound;
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+use tantivy::{
+    query::{Query, RangeQuery, RegexQuery, TermSetQuery},
+    Term,
+};
+use thiserror::Error;
+
+use crate::documents::{
+    annotations::DocumentAnnotation,
+    search::{schema::AppSchema, Search},
+};
+
+/// Query typed by the user, split into the free text and the field filters.
+///
+/// e.g. `title:roadmap tag:q3 modified:>2022-06-01 path:work/ -tag:archive is:pinned label:todo words:<50 notes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedQuery {
+    /// Rest of the query, passed to the query parser as it is
+    pub text: String,
+    pub filters: Vec<Filter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    /// Excludes the matching documents when prefixed with `-`
+    pub negated: bool,
+    pub condition: Condition,
+    /// The filter as written in the query
+    pub source: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Title(TextValue),
+    Tag(TextValue),
+    Contents(TextValue),
+    /// Part of the normalized path
+    Path(String),
+    ModifiedAt(DateRange),
+    CreatedAt(DateRange),
+    /// `is:pinned`
+    Pinned,
+    /// Label of the annotation, case-insensitive
+    Label(String),
+    /// Number of words, counting each CJK character as a word
+    WordCount(NumberRange),
+    /// Estimated reading time in minutes
+    ReadingTime(NumberRange),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextValue {
+    pub text: String,
+    /// Quoted with `"`
+    pub phrase: bool,
+}
+
+/// Half-open range of time, `start <= t < end`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DateRange {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// Half-open range of numbers, `start <= n < end`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NumberRange {
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+}
+
+/// Error in the query syntax. `start` and `end` are the character offsets in the query.
+#[derive(Error, Serialize, Debug, Clone, PartialEq, Eq)]
+#[error("{kind} at {start}..{end}")]
+pub struct QuerySyntaxError {
+    pub kind: QuerySyntaxErrorKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Error, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuerySyntaxErrorKind {
+    #[error("unknown field '{field}'")]
+    UnknownField { field: String },
+    #[error("missing value of '{field}'")]
+    MissingValue { field: String },
+    #[error("invalid date '{value}'")]
+    InvalidDate { value: String },
+    #[error("invalid value '{value}'")]
+    InvalidValue { value: String },
+    #[error("unclosed quote")]
+    UnclosedQuote,
+    /// Rejected by the query parser, which doesn't tell the position
+    #[error("invalid syntax: {message}")]
+    InvalidSyntax { message: String },
+}
+
+impl QuerySyntaxError {
+    pub(super) fn invalid_syntax(query: &str, error: tantivy::query::QueryParserError) -> Self {
+        Self {
+            kind: QuerySyntaxErrorKind::InvalidSyntax {
+                message: error.to_string(),
+            },
+            start: 0,
+            end: query.chars().count(),
+        }
+    }
+}
+
+impl ParsedQuery {
+    /// Query without filters
+    pub fn from_text(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            filters: vec![],
+        }
+    }
+
+    /// Returns the query with the text replaced, keeping the filters.
+    pub fn with_text(&self, text: &str) -> String {
+        let mut parts = vec![text];
+        parts.extend(self.filters.iter().map(|x| x.source.as_str()));
+        parts.retain(|x| !x.is_empty());
+        parts.join(" ")
+    }
+}
+
+/// Parses the field filters in the query. Dates are interpreted in the time zone of `now`.
+pub fn parse<Tz: TimeZone>(query: &str, now: &DateTime<Tz>) -> Result<ParsedQuery, QuerySyntaxError> {
+    let mut texts = vec![];
+    let mut filters = vec![];
+    for token in split_tokens(query)? {
+        match parse_filter(&token, now)? {
+            Some(filter) => filters.push(filter),
+            None => texts.push(token.text),
+        }
+    }
+    Ok(ParsedQuery {
+        text: texts.join(" "),
+        filters,
+    })
+}
+
+struct Token {
+    text: String,
+    /// Character offset in the query
+    start: usize,
+}
+
+/// Splits the query by whitespaces outside of quotes.
+fn split_tokens(query: &str) -> Result<Vec<Token>, QuerySyntaxError> {
+    let mut tokens = vec![];
+    let mut current: Option<Token> = None;
+    let mut quote_start = None;
+    for (i, c) in query.chars().enumerate() {
+        if c.is_whitespace() && quote_start.is_none() {
+            tokens.extend(current.take());
+            continue;
+        }
+        if c == '"' {
+            quote_start = match quote_start {
+                Some(_) => None,
+                None => Some(i),
+            };
+        }
+        current
+            .get_or_insert_with(|| Token {
+                text: String::new(),
+                start: i,
+            })
+            .text
+            .push(c);
+    }
+    if let Some(start) = quote_start {
+        return Err(QuerySyntaxError {
+            kind: QuerySyntaxErrorKind::UnclosedQuote,
+            start,
+            end: query.chars().count(),
+        });
+    }
+    tokens.extend(current);
+    Ok(tokens)
+}
+
+/// Returns `None` if the token is not a filter.
+fn parse_filter<Tz: TimeZone>(token: &Token, now: &DateTime<Tz>) -> Result<Option<Filter>, QuerySyntaxError> {
+    let (negated, body, body_start) = match token.text.strip_prefix('-') {
+        Some(x) => (true, x, token.start + 1),
+        None => (false, token.text.as_str(), token.start),
+    };
+    let (field, value) = match body.split_once(':') {
+        // 時刻などコロンを含む語は、英字だけのフィールド名でなければそのまま検索する
+        Some((field, value)) if !field.is_empty() && field.chars().all(|x| x.is_ascii_alphabetic()) => (field, value),
+        _ => return Ok(None),
+    };
+    let field_end = body_start + field.chars().count();
+    let value_start = field_end + 1;
+    let value_end = value_start + value.chars().count();
+    let error = |kind, start, end| QuerySyntaxError { kind, start, end };
+
+    let (text, phrase) = match value.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+        Some(x) => (x, true),
+        None => (value, false),
+    };
+    if text.trim().is_empty() {
+        return Err(error(
+            QuerySyntaxErrorKind::MissingValue {
+                field: field.to_string(),
+            },
+            body_start,
+            value_end,
+        ));
+    }
+    let text_value = || TextValue {
+        text: text.to_string(),
+        phrase,
+    };
+    let number_range = || {
+        parse_number_range(text).ok_or_else(|| {
+            error(
+                QuerySyntaxErrorKind::InvalidValue {
+                    value: value.to_string(),
+                },
+                value_start,
+                value_end,
+            )
+        })
+    };
+    let date_range = || {
+        parse_date_range(text, now).ok_or_else(|| {
+            error(
+                QuerySyntaxErrorKind::InvalidDate {
+                    value: value.to_string(),
+                },
+                value_start,
+                value_end,
+            )
+        })
+    };
+
+    let condition = match field.to_lowercase().as_str() {
+        "title" => Condition::Title(text_value()),
+        "tag" | "tags" => Condition::Tag(text_value()),
+        "body" | "contents" => Condition::Contents(text_value()),
+        "path" => Condition::Path(text.to_string()),
+        "modified" | "updated" => Condition::ModifiedAt(date_range()?),
+        "created" => Condition::CreatedAt(date_range()?),
+        "is" if text.eq_ignore_ascii_case("pinned") => Condition::Pinned,
+        "is" => {
+            return Err(error(
+                QuerySyntaxErrorKind::InvalidValue {
+                    value: value.to_string(),
+                },
+                value_start,
+                value_end,
+            ))
+        }
+        "label" | "labels" => Condition::Label(text.to_string()),
+        "words" => Condition::WordCount(number_range()?),
+        "reading" => Condition::ReadingTime(number_range()?),
+        _ => {
+            return Err(error(
+                QuerySyntaxErrorKind::UnknownField {
+                    field: field.to_string(),
+                },
+                body_start,
+                field_end,
+            ))
+        }
+    };
+    Ok(Some(Filter {
+        negated,
+        condition,
+        source: token.text.clone(),
+    }))
+}
+
+/// Parses `2022-06-01`, `>2022-06`, `<=2022`, `2022-01..2022-03`, `last-week` etc.
+/// A date stands for the whole day, month or year.
+fn parse_date_range<Tz: TimeZone>(value: &str, now: &DateTime<Tz>) -> Option<DateRange> {
+    let period = |x: &str| parse_period(x, now);
+    if let Some((start, end)) = value.split_once("..") {
+        let start = if start.is_empty() { None } else { Some(period(start)?.0) };
+        let end = if end.is_empty() { None } else { Some(period(end)?.1) };
+        return Some(DateRange { start, end });
+    }
+    let range = if let Some(x) = value.strip_prefix(">=") {
+        DateRange {
+            start: Some(period(x)?.0),
+            end: None,
+        }
+    } else if let Some(x) = value.strip_prefix("<=") {
+        DateRange {
+            start: None,
+            end: Some(period(x)?.1),
+        }
+    } else if let Some(x) = value.strip_prefix('>') {
+        DateRange {
+            start: Some(period(x)?.1),
+            end: None,
+        }
+    } else if let Some(x) = value.strip_prefix('<') {
+        DateRange {
+            start: None,
+            end: Some(period(x)?.0),
+        }
+    } else {
+        let (start, end) = period(value)?;
+        DateRange {
+            start: Some(start),
+            end: Some(end),
+        }
+    };
+    Some(range)
+}
+
+/// Parses `50`, `>50`, `<=10`, `100..500` etc. in the same way as dates, so `100..500` includes 500.
+fn parse_number_range(value: &str) -> Option<NumberRange> {
+    let number = |x: &str| -> Option<u64> {
+        if x.is_empty() || !x.chars().all(|x| x.is_ascii_digit()) {
+            return None;
+        }
+        x.parse().ok()
+    };
+    if let Some((start, end)) = value.split_once("..") {
+        let start = if start.is_empty() { None } else { Some(number(start)?) };
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(number(end)?.checked_add(1)?)
+        };
+        return Some(NumberRange { start, end });
+    }
+    let range = if let Some(x) = value.strip_prefix(">=") {
+        NumberRange {
+            start: Some(number(x)?),
+            end: None,
+        }
+    } else if let Some(x) = value.strip_prefix("<=") {
+        NumberRange {
+            start: None,
+            end: Some(number(x)?.checked_add(1)?),
+        }
+    } else if let Some(x) = value.strip_prefix('>') {
+        NumberRange {
+            start: Some(number(x)?.checked_add(1)?),
+            end: None,
+        }
+    } else if let Some(x) = value.strip_prefix('<') {
+        NumberRange {
+            start: None,
+            end: Some(number(x)?),
+        }
+    } else {
+        let x = number(value)?;
+        NumberRange {
+            start: Some(x),
+            end: Some(x.checked_add(1)?),
+        }
+    };
+    Some(range)
+}
+
+/// Returns the start and the end of the period.
+/// Relative periods like `last-week` end at the end of today.
+fn parse_period<Tz: TimeZone>(value: &str, now: &DateTime<Tz>) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let today = now.naive_local().date();
+    let tomorrow = today + Duration::days(1);
+    let (start, end) = match value.to_lowercase().as_str() {
+        "today" => (today, tomorrow),
+        "yesterday" => (today - Duration::days(1), today),
+        "this-week" => (
+            today - Duration::days(today.weekday().num_days_from_monday() as i64),
+            tomorrow,
+        ),
+        "this-month" => (today.with_day(1)?, tomorrow),
+        "this-year" => (NaiveDate::from_ymd_opt(today.year(), 1, 1)?, tomorrow),
+        "last-week" => (today - Duration::days(7), tomorrow),
+        "last-month" => (add_months(today, -1)?, tomorrow),
+        "last-year" => (add_months(today, -12)?, tomorrow),
+        _ => parse_absolute_period(value)?,
+    };
+    let timezone = now.timezone();
+    Some((start_of_day(&timezone, start), start_of_day(&timezone, end)))
+}
+
+/// Parses `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+fn parse_absolute_period(value: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let parts: Vec<&str> = value.split('-').collect();
+    if parts
+        .iter()
+        .any(|x| x.is_empty() || !x.chars().all(|x| x.is_ascii_digit()))
+    {
+        return None;
+    }
+    match parts[..] {
+        [year] => {
+            let start = NaiveDate::from_ymd_opt(year.parse().ok()?, 1, 1)?;
+            Some((start, add_months(start, 12)?))
+        }
+        [year, month] => {
+            let start = NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)?;
+            Some((start, add_months(start, 1)?))
+        }
+        [year, month, day] => {
+            let start = NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)?;
+            Some((start, start + Duration::days(1)))
+        }
+        _ => None,
+    }
+}
+
+/// Adds the months, clamping the day to the end of the month.
+fn add_months(date: NaiveDate, months: i32) -> Option<NaiveDate> {
+    let total = date.year() * 12 + date.month0() as i32 + months;
+    let (year, month) = (total.div_euclid(12), total.rem_euclid(12) as u32 + 1);
+    (1..=date.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+fn start_of_day<Tz: TimeZone>(timezone: &Tz, date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms(0, 0, 0);
+    match timezone.from_local_datetime(&midnight).earliest() {
+        Some(x) => x.with_timezone(&Utc),
+        // 夏時間の切り替えで 0 時が存在しない日
+        None => DateTime::from_utc(midnight, Utc),
+    }
+}
+
+/// Escapes the characters having special meanings in regular expressions.
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if r"\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl Search {
+    pub(super) fn build_filter_query(&self, condition: &Condition) -> Result<Box<dyn Query>> {
+        let schema = AppSchema::new(self.index.schema());
+        match condition {
+            Condition::Title(value) => self.build_literal_filter_query(&value.text, value.phrase, &schema.title_all()),
+            Condition::Tag(value) => self.build_literal_filter_query(&value.text, value.phrase, &schema.tag_all()),
+            Condition::Contents(value) => {
+                self.build_literal_filter_query(&value.text, value.phrase, &schema.contents_all())
+            }
+            Condition::Path(text) => {
+                let pattern = format!("(?i).*{}.*", escape_regex(text));
+                Ok(Box::new(RegexQuery::from_pattern(&pattern, schema.path())?))
+            }
+            Condition::ModifiedAt(range) => Ok(Box::new(date_range_query(schema.modified_at(), range))),
+            Condition::CreatedAt(range) => Ok(Box::new(date_range_query(schema.created_at(), range))),
+            Condition::Pinned => Ok(self.build_annotation_filter_query(|x| x.pinned)),
+            Condition::Label(label) => Ok(self.build_annotation_filter_query(|x| x.has_label(label))),
+            Condition::WordCount(range) => Ok(Box::new(number_range_query(schema.word_count(), range))),
+            Condition::ReadingTime(range) => Ok(Box::new(number_range_query(schema.reading_time(), range))),
+        }
+    }
+
+    /// Annotations are not in the index, so the filter matches the paths of the annotated documents.
+    fn build_annotation_filter_query<F: Fn(&DocumentAnnotation) -> bool>(&self, predicate: F) -> Box<dyn Query> {
+        let schema = AppSchema::new(self.index.schema());
+        let terms = self
+            .annotations
+            .values()
+            .filter(|x| predicate(x))
+            .map(|x| Term::from_field_text(schema.path(), &x.path))
+            .collect::<Vec<_>>();
+        Box::new(TermSetQuery::new(terms))
+    }
+}
+
+fn date_range_query(field: tantivy::schema::Field, range: &DateRange) -> RangeQuery {
+    let to_bound = |x: Option<DateTime<Utc>>, bound: fn(tantivy::DateTime) -> Bound<tantivy::DateTime>| match x {
+        Some(x) => bound(tantivy::DateTime::from_timestamp_secs(x.timestamp())),
+        None => Bound::Unbounded,
+    };
+    RangeQuery::new_date_bounds(
+        field,
+        to_bound(range.start, Bound::Included),
+        to_bound(range.end, Bound::Excluded),
+    )
+}
+
+fn number_range_query(field: tantivy::schema::Field, range: &NumberRange) -> RangeQuery {
+    RangeQuery::new_u64_bounds(
+        field,
+        range.start.map_or(Bound::Unbounded, Bound::Included),
+        range.end.map_or(Bound::Unbounded, Bound::Excluded),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+
+    use super::*;
+
+    fn now() -> DateTime<FixedOffset> {
+        // 2022-07-15 (金) 10:00 JST
+        FixedOffset::east(9 * 3600).ymd(2022, 7, 15).and_hms(10, 0, 0)
+    }
+
+    fn jst(y: i32, m: u32, d: u32) -> Option<DateTime<Utc>> {
+        Some(
+            FixedOffset::east(9 * 3600)
+                .ymd(y, m, d)
+                .and_hms(0, 0, 0)
+                .with_timezone(&Utc),
+        )
+    }
+
+    fn text(text: &str, phrase: bool) -> TextValue {
+        TextValue {
+            text: text.to_string(),
+            phrase,
+        }
+    }
+
+    #[test]
+    fn test_parse() -> Result<(), QuerySyntaxError> {
+        let parsed = parse(
+            r#"title:roadmap tag:q3 modified:>2022-06-01 path:work/ -tag:archive meeting "10:30 sync""#,
+            &now(),
+        )?;
+        assert_eq!(parsed.text, r#"meeting "10:30 sync""#);
+        let conditions: Vec<(bool, Condition)> = parsed
+            .filters
+            .iter()
+            .map(|x| (x.negated, x.condition.clone()))
+            .collect();
+        assert_eq!(
+            conditions,
+            vec![
+                (false, Condition::Title(text("roadmap", false))),
+                (false, Condition::Tag(text("q3", false))),
+                (
+                    false,
+                    Condition::ModifiedAt(DateRange {
+                        start: jst(2022, 6, 2),
+                        end: None
+                    })
+                ),
+                (false, Condition::Path("work/".to_string())),
+                (true, Condition::Tag(text("archive", false))),
+            ]
+        );
+        assert_eq!(
+            parsed.with_text("meetings"),
+            "meetings title:roadmap tag:q3 modified:>2022-06-01 path:work/ -tag:archive"
+        );
+
+        let parsed = parse(r#"Title:"road map" -foo 12:00"#, &now())?;
+        assert_eq!(parsed.text, "-foo 12:00");
+        assert_eq!(parsed.filters[0].condition, Condition::Title(text("road map", true)));
+
+        let parsed = parse("is:Pinned -label:done report", &now())?;
+        assert_eq!(parsed.text, "report");
+        assert_eq!(parsed.filters[0].condition, Condition::Pinned);
+        assert_eq!(parsed.filters[1].condition, Condition::Label("done".to_string()));
+        assert!(parsed.filters[1].negated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_dates() -> Result<(), QuerySyntaxError> {
+        let range = |query: &str| match parse(query, &now()).unwrap().filters[0].condition {
+            Condition::ModifiedAt(x) | Condition::CreatedAt(x) => (x.start, x.end),
+            _ => panic!(),
+        };
+        assert_eq!(range("modified:2022-06-01"), (jst(2022, 6, 1), jst(2022, 6, 2)));
+        assert_eq!(range("modified:2022-02"), (jst(2022, 2, 1), jst(2022, 3, 1)));
+        assert_eq!(range("created:2021"), (jst(2021, 1, 1), jst(2022, 1, 1)));
+        assert_eq!(range("modified:>=2022-06"), (jst(2022, 6, 1), None));
+        assert_eq!(range("modified:<2022-06"), (None, jst(2022, 6, 1)));
+        assert_eq!(range("modified:<=2022-06"), (None, jst(2022, 7, 1)));
+        assert_eq!(range("modified:2022-01..2022-03"), (jst(2022, 1, 1), jst(2022, 4, 1)));
+        assert_eq!(range("modified:..2022-03"), (None, jst(2022, 4, 1)));
+        assert_eq!(range("modified:today"), (jst(2022, 7, 15), jst(2022, 7, 16)));
+        assert_eq!(range("modified:yesterday"), (jst(2022, 7, 14), jst(2022, 7, 15)));
+        assert_eq!(range("modified:this-week"), (jst(2022, 7, 11), jst(2022, 7, 16)));
+        assert_eq!(range("modified:last-week"), (jst(2022, 7, 8), jst(2022, 7, 16)));
+        assert_eq!(range("modified:last-month"), (jst(2022, 6, 15), jst(2022, 7, 16)));
+        assert_eq!(range("modified:<last-year"), (None, jst(2021, 7, 15)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_numbers() -> Result<(), QuerySyntaxError> {
+        let range = |query: &str| match parse(query, &now()).unwrap().filters[0].condition {
+            Condition::WordCount(x) | Condition::ReadingTime(x) => (x.start, x.end),
+            _ => panic!(),
+        };
+        assert_eq!(range("words:50"), (Some(50), Some(51)));
+        assert_eq!(range("words:<50"), (None, Some(50)));
+        assert_eq!(range("words:<=50"), (None, Some(51)));
+        assert_eq!(range("words:>1000"), (Some(1001), None));
+        assert_eq!(range("reading:>=10"), (Some(10), None));
+        assert_eq!(range("reading:5..10"), (Some(5), Some(11)));
+        assert_eq!(range("reading:..10"), (None, Some(11)));
+
+        let parsed = parse("-words:<50 reading:>5 notes", &now())?;
+        assert_eq!(parsed.text, "notes");
+        assert!(parsed.filters[0].negated);
+        assert_eq!(
+            parsed.filters[1].condition,
+            Condition::ReadingTime(NumberRange {
+                start: Some(6),
+                end: None
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |query: &str| parse(query, &now()).unwrap_err();
+        assert_eq!(
+            error("foo -author:me"),
+            QuerySyntaxError {
+                kind: QuerySyntaxErrorKind::UnknownField {
+                    field: "author".to_string()
+                },
+                start: 5,
+                end: 11,
+            }
+        );
+        assert_eq!(
+            error("日本 modified:2022-13"),
+            QuerySyntaxError {
+                kind: QuerySyntaxErrorKind::InvalidDate {
+                    value: "2022-13".to_string()
+                },
+                start: 12,
+                end: 19,
+            }
+        );
+        assert_eq!(
+            error("tag: foo"),
+            QuerySyntaxError {
+                kind: QuerySyntaxErrorKind::MissingValue {
+                    field: "tag".to_string()
+                },
+                start: 0,
+                end: 4,
+            }
+        );
+        assert_eq!(
+            error("is:starred"),
+            QuerySyntaxError {
+                kind: QuerySyntaxErrorKind::InvalidValue {
+                    value: "starred".to_string()
+                },
+                start: 3,
+                end: 10,
+            }
+        );
+        assert_eq!(
+            error("words:<fifty"),
+            QuerySyntaxError {
+                kind: QuerySyntaxErrorKind::InvalidValue {
+                    value: "<fifty".to_string()
+                },
+                start: 6,
+                end: 12,
+            }
+        );
+        assert_eq!(
+            error(r#"foo title:"bar"#),
+            QuerySyntaxError {
+                kind: QuerySyntaxErrorKind::UnclosedQuote,
+                start: 10,
+                end: 14,
+            }
+        );
+    }
+
+    #[test]
+    fn test_add_months() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(add_months(date(2022, 3, 31), -1), Some(date(2022, 2, 28)));
+        assert_eq!(add_months(date(2022, 1, 15), -1), Some(date(2021, 12, 15)));
+        assert_eq!(add_months(date(2022, 12, 1), 1), Some(date(2023, 1, 1)));
+    }
+
+    #[test]
+    fn test_escape_regex() {
+        assert_eq!(escape_regex("a.b/c-d"), r"a\.b/c\-d");
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tantivy::{
+    collector::TopDocs,
+    query::{MoreLikeThisQuery, Query},
+    schema::Value,
+};
+
+use crate::{
+    documents::search::{highlight::Highlights, populate_document, schema::AppSchema, Search, SearchResultDocument},
+    path_string_normalization::PathStringNormalizationExt,
+};
+
+// 個人のノートは文書数が少ないので、tantivy の既定値より緩くする
+const MIN_DOC_FREQUENCY: u64 = 2;
+const MIN_TERM_FREQUENCY: usize = 1;
+const MAX_QUERY_TERMS: usize = 25;
+
+#[derive(Serialize, Debug)]
+pub struct RelatedDocument {
+    #[serde(flatten)]
+    pub document: SearchResultDocument,
+    /// Score relative to the score of the source document itself, usually between 0 and 1
+    pub similarity: f32,
+}
+
+impl Search {
+    /// Returns documents similar to the document in terms of the titles, contents and tags.
+    /// Documents whose similarity is below `min_similarity` are skipped.
+    /// The results have the annotations given by `with_annotations`.
+    pub(crate) fn related_documents<P: AsRef<Path>>(
+        &self,
+        path: P,
+        limit: usize,
+        min_similarity: f32,
+    ) -> Result<Vec<RelatedDocument>> {
+        let path = path.as_ref();
+        let schema = AppSchema::new(self.index.schema());
+        let searcher = self.index_reader.searcher();
+        let doc_address = self
+            .find_doc_address_by_path(&searcher, path)?
+            .ok_or(anyhow!("document not found: {}", path.to_normalized_path_string()))?;
+        let doc = searcher.doc(doc_address)?;
+
+        let mut fields = schema.title_all_languages();
+        fields.extend(schema.contents_all_languages());
+        fields.extend(schema.tag_all_languages());
+        let doc_fields: Vec<(_, Vec<Value>)> = fields
+            .into_iter()
+            .map(|field| (field, doc.get_all(field).cloned().collect::<Vec<_>>()))
+            .filter(|(_, values)| !values.is_empty())
+            .collect();
+        if doc_fields.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let query = MoreLikeThisQuery::builder()
+            .with_min_doc_frequency(MIN_DOC_FREQUENCY)
+            .with_min_term_frequency(MIN_TERM_FREQUENCY)
+            .with_max_query_terms(MAX_QUERY_TERMS)
+            .with_document_fields(doc_fields);
+        // 元の文書自身のスコアを基準に類似度を求める。語が残らずクエリが何にも一致しない場合は関連文書なしとする
+        let source_score = match query.explain(&searcher, doc_address) {
+            Ok(explanation) if explanation.value() > 0.0 => explanation.value(),
+            _ => return Ok(vec![]),
+        };
+
+        let mut documents = vec![];
+        for (score, hit_address) in searcher.search(&query, &TopDocs::with_limit(limit + 1))? {
+            if hit_address == doc_address {
+                continue;
+            }
+            let similarity = score / source_score;
+            if similarity < min_similarity || documents.len() >= limit {
+                break;
+            }
+            let hit = searcher.doc(hit_address)?;
+            let mut document = populate_document(&schema, Highlights::default(), &hit)?;
+            self.annotate(&mut document);
+            documents.push(RelatedDocument { document, similarity });
+        }
+        Ok(documents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::*;
+    use crate::documents::{annotations::DocumentAnnotation, search::tests::index_documents};
+
+    #[test]
+    fn test_related_documents() -> Result<()> {
+        let index = index_documents(&[
+            ("a.md", "# Rust async\n\nrust tokio axum runtime"),
+            ("b.md", "# Rust web\n\nrust tokio axum server"),
+            ("c.md", "# Rust cli\n\nrust clap"),
+            ("d.md", "# Cooking\n\npasta recipe"),
+        ])?;
+        let related = |limit: usize, min_similarity: f32| -> Result<Vec<(String, f32)>> {
+            Ok(index
+                .search
+                .related_documents(index.dir.path().join("a.md"), limit, min_similarity)?
+                .into_iter()
+                .map(|x| (x.document.path, x.similarity))
+                .collect())
+        };
+
+        // 元の文書と、共通の語がない文書は含めない
+        let all = related(10, 0.0)?;
+        let paths: Vec<&str> = all.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, vec![index.path("b.md"), index.path("c.md")]);
+        assert!(all[0].1 > all[1].1);
+
+        let paths = |results: Vec<(String, f32)>| results.into_iter().map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(paths(related(1, 0.0)?), vec![index.path("b.md")]);
+        let min_similarity = (all[0].1 + all[1].1) / 2.0;
+        assert_eq!(paths(related(10, min_similarity)?), vec![index.path("b.md")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_related_documents_with_annotations() -> Result<()> {
+        let index = index_documents(&[("a.md", "# Rust\n\nrust tokio"), ("b.md", "# Rust\n\nrust tokio")])?;
+        let annotation = DocumentAnnotation {
+            path: index.path("b.md"),
+            pinned: true,
+            labels: vec!["work".to_string()],
+            note: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        let search = index
+            .search
+            .with_annotations(Arc::new(HashMap::from([(annotation.path.clone(), annotation)])));
+        let related = search.related_documents(index.dir.path().join("a.md"), 10, 0.0)?;
+        assert_eq!(related.len(), 1);
+        assert!(related[0].document.pinned);
+        assert_eq!(related[0].document.labels, vec!["work".to_string()]);
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use tantivy::schema::{
-    Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, FAST, INDEXED, STORED, STRING, TEXT,
+    FacetOptions, Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, FAST, INDEXED, STORED, STRING, TEXT,
 };
 
 use crate::documents::search::Language;
@@ -27,22 +27,51 @@ pub fn define_schema() -> Schema {
     schema_builder.add_text_field("path", STRING | STORED);
     schema_builder.add_text_field("path_components", STRING | STORED);
     schema_builder.add_text_field("path_ngram", ngram_text.clone() | STORED);
+    // 監視フォルダ・フォルダごとの件数を集計するため、親ディレクトリを facet として持つ
+    schema_builder.add_facet_field("directory", FacetOptions::default());
+    // 並べ替え用。テキストの fast field は使えないので、正規化したテキストの先頭 8 バイトを数値として持つ
+    schema_builder.add_u64_field("path_sort", FAST);
 
     schema_builder.add_text_field("contents_en", en_text.clone() | STORED);
     schema_builder.add_text_field("contents_ja", ja_text.clone() | STORED);
     schema_builder.add_text_field("contents_ngram", ngram_text.clone() | STORED);
+    // 本文が始まる行番号。フロントマターの分だけずれる
+    schema_builder.add_u64_field("body_line", STORED);
 
     schema_builder.add_text_field("title_en", en_text.clone() | STORED);
     schema_builder.add_text_field("title_ja", ja_text.clone() | STORED);
     schema_builder.add_text_field("title_ngram", ngram_text.clone() | STORED);
+    schema_builder.add_u64_field("title_sort", FAST);
 
     schema_builder.add_text_field("tag_en", en_text.clone() | STORED);
     schema_builder.add_text_field("tag_ja", ja_text.clone() | STORED);
     schema_builder.add_text_field("tag_ngram", ngram_text.clone() | STORED);
 
-    schema_builder.add_date_field("created_at", STORED | FAST);
-    schema_builder.add_date_field("modified_at", STORED | FAST);
-    schema_builder.add_i64_field("watch_id", STORED | INDEXED);
+    let spelling_text: TextOptions = TextOptions::default().set_indexing_options(
+        TextFieldIndexing::default()
+            .set_tokenizer("spelling")
+            .set_index_option(IndexRecordOption::Basic),
+    );
+    schema_builder.add_text_field("spelling", spelling_text);
+
+    // 日付の範囲で絞り込めるように INDEXED にする
+    schema_builder.add_date_field("created_at", STORED | INDEXED | FAST);
+    schema_builder.add_date_field("modified_at", STORED | INDEXED | FAST);
+    schema_builder.add_i64_field("watch_id", STORED | INDEXED | FAST);
+
+    // 詳細表示用。見出しとフロントマターは同じ順に並べた複数の値で持つ
+    schema_builder.add_u64_field("heading_level", STORED);
+    schema_builder.add_u64_field("heading_line", STORED);
+    schema_builder.add_text_field("heading_text", STORED);
+    schema_builder.add_text_field("frontmatter_key", STORED);
+    schema_builder.add_text_field("frontmatter_value", STORED);
+    schema_builder.add_u64_field("char_count", STORED);
+    schema_builder.add_u64_field("internal_link_count", STORED);
+    schema_builder.add_u64_field("external_link_count", STORED);
+    schema_builder.add_u64_field("file_size", STORED);
+    // 長さでの絞り込みと並べ替えに使う
+    schema_builder.add_u64_field("word_count", STORED | INDEXED | FAST);
+    schema_builder.add_u64_field("reading_time", STORED | INDEXED | FAST);
 
     schema_builder.build()
 }
@@ -64,6 +93,16 @@ impl AppSchema {
         self.get_field("path_ngram")
     }
 
+    /// Prefix of the normalized path for sorting documents
+    pub fn path_sort(&self) -> Field {
+        self.get_field("path_sort")
+    }
+
+    /// Parent directory of the document as a facet
+    pub fn directory(&self) -> Field {
+        self.get_field("directory")
+    }
+
     pub fn language(&self) -> Field {
         self.get_field("language")
     }
@@ -91,6 +130,11 @@ impl AppSchema {
         }
     }
 
+    /// 1-based line number in the file where the contents start
+    pub fn body_line(&self) -> Field {
+        self.get_field("body_line")
+    }
+
     pub fn title_all_languages(&self) -> Vec<Field> {
         vec![self.get_field("title_en"), self.get_field("title_ja")]
     }
@@ -107,6 +151,11 @@ impl AppSchema {
         self.get_field("title_ngram")
     }
 
+    /// Prefix of the normalized title for sorting documents
+    pub fn title_sort(&self) -> Field {
+        self.get_field("title_sort")
+    }
+
     pub fn title_for_language(&self, language: Language) -> Field {
         match language {
             Language::English => self.get_field("title_en"),
@@ -141,6 +190,21 @@ impl AppSchema {
         vec!["tag_en", "tag_ja", "tag_ngram"]
     }
 
+    /// Words of English documents as they are written, used for spelling suggestions
+    pub fn spelling(&self) -> Field {
+        self.get_field("spelling")
+    }
+
+    /// Fields whose terms are not stemmed and can be suggested to users
+    pub fn spelling_all(&self) -> Vec<Field> {
+        vec![
+            self.spelling(),
+            self.get_field("title_ja"),
+            self.get_field("contents_ja"),
+            self.get_field("tag_ja"),
+        ]
+    }
+
     pub fn created_at(&self) -> Field {
         self.get_field("created_at")
     }
@@ -153,6 +217,55 @@ impl AppSchema {
         self.get_field("watch_id")
     }
 
+    pub fn heading_level(&self) -> Field {
+        self.get_field("heading_level")
+    }
+
+    /// 1-based line number of the heading in the file
+    pub fn heading_line(&self) -> Field {
+        self.get_field("heading_line")
+    }
+
+    pub fn heading_text(&self) -> Field {
+        self.get_field("heading_text")
+    }
+
+    pub fn frontmatter_key(&self) -> Field {
+        self.get_field("frontmatter_key")
+    }
+
+    pub fn frontmatter_value(&self) -> Field {
+        self.get_field("frontmatter_value")
+    }
+
+    /// Number of words, counting each CJK character as a word
+    pub fn word_count(&self) -> Field {
+        self.get_field("word_count")
+    }
+
+    /// Number of non-whitespace characters of the text, without the Markdown syntax
+    pub fn char_count(&self) -> Field {
+        self.get_field("char_count")
+    }
+
+    /// Estimated reading time in minutes
+    pub fn reading_time(&self) -> Field {
+        self.get_field("reading_time")
+    }
+
+    pub fn internal_link_count(&self) -> Field {
+        self.get_field("internal_link_count")
+    }
+
+    pub fn external_link_count(&self) -> Field {
+        self.get_field("external_link_count")
+    }
+
+    /// In bytes
+    pub fn file_size(&self) -> Field {
+        self.get_field("file_size")
+    }
+
     fn get_field(&self, field_name: &str) -> Field {
         self.0
             .get_field(field_name)
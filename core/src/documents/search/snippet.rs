@@ -0,0 +1,412 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use serde::Serialize;
+use tantivy::{query::Query, schema::Field, Document, Searcher};
+
+use crate::documents::search::{schema::AppSchema, Language, Search};
+
+pub(super) const DEFAULT_FRAGMENT_LENGTH: usize = 150;
+pub(super) const DEFAULT_MAX_FRAGMENTS: usize = 3;
+const FRAGMENT_SEPARATOR: &str = " … ";
+
+/// A part of the contents around the matched words.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SnippetFragment {
+    pub text: String,
+    /// 1-based line number in the file where the fragment starts
+    pub line: usize,
+    pub highlights: Vec<HighlightRange>,
+}
+
+/// Range of a highlighted part in `SnippetFragment::text`, in bytes and in characters.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct HighlightRange {
+    pub start: usize,
+    pub end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(super) struct SnippetOptions {
+    /// Approximate number of characters of a fragment
+    pub fragment_length: usize,
+    pub max_fragments: usize,
+}
+
+/// A token of the text matching a query term.
+#[derive(Debug, Clone)]
+pub(super) struct TokenMatch {
+    pub start: usize,
+    pub end: usize,
+    pub term: String,
+    pub score: f32,
+}
+
+/// Terms of the query for each field with their scores, rarer terms scoring higher.
+#[derive(Default)]
+pub(super) struct QueryTerms(HashMap<Field, HashMap<String, f32>>);
+
+impl QueryTerms {
+    pub fn contains(&self, field: Field, text: &str) -> bool {
+        self.0.get(&field).map_or(false, |x| x.contains_key(text))
+    }
+}
+
+impl Search {
+    pub(super) fn query_terms(&self, searcher: &Searcher, query: &dyn Query) -> Result<QueryTerms> {
+        let mut terms = vec![];
+        query.query_terms(&mut |term, _| terms.push(term.clone()));
+        let mut query_terms = QueryTerms::default();
+        for term in terms {
+            if let Some(text) = term.as_str() {
+                let doc_freq = searcher.doc_freq(&term)?;
+                if doc_freq > 0 {
+                    query_terms
+                        .0
+                        .entry(term.field())
+                        .or_default()
+                        .insert(text.to_string(), 1.0 / (1.0 + doc_freq as f32));
+                }
+            }
+        }
+        Ok(query_terms)
+    }
+
+    /// Makes snippet fragments of the contents of the document.
+    /// The language field is tried first, then the ngram field so that partial words are highlighted.
+    pub(super) fn make_snippet(
+        &self,
+        schema: &AppSchema,
+        query_terms: &QueryTerms,
+        doc: &Document,
+        language: Language,
+        options: SnippetOptions,
+    ) -> Result<Vec<SnippetFragment>> {
+        let first_line = doc.get_first(schema.body_line()).and_then(|x| x.as_u64()).unwrap_or(1) as usize;
+        let text = match doc
+            .get_first(schema.contents_for_language(language))
+            .and_then(|x| x.as_text())
+        {
+            Some(x) => x,
+            None => return Ok(vec![]),
+        };
+        for field in [schema.contents_for_language(language), schema.contents_ngram()] {
+            let matches = self.find_token_matches(query_terms, field, text)?;
+            if !matches.is_empty() {
+                return Ok(make_fragments(
+                    text,
+                    &matches,
+                    options.fragment_length,
+                    options.max_fragments,
+                    first_line,
+                ));
+            }
+        }
+        Ok(vec![])
+    }
+
+    /// Finds the tokens of the text analyzed as the field which match the query terms of the field.
+    pub(super) fn find_token_matches(
+        &self,
+        query_terms: &QueryTerms,
+        field: Field,
+        text: &str,
+    ) -> Result<Vec<TokenMatch>> {
+        let scores = match query_terms.0.get(&field) {
+            Some(x) => x,
+            None => return Ok(vec![]),
+        };
+        let analyzer = self.index.tokenizer_for_field(field)?;
+        let mut matches = vec![];
+        analyzer.token_stream(text).process(&mut |token| {
+            if let Some(score) = scores.get(&token.text) {
+                matches.push(TokenMatch {
+                    start: token.offset_from,
+                    end: token.offset_to,
+                    term: token.text.clone(),
+                    score: *score,
+                });
+            }
+        });
+        matches.sort_by_key(|x| (x.start, x.end));
+        Ok(matches)
+    }
+}
+
+struct Candidate {
+    start: usize,
+    end: usize,
+    score: f32,
+}
+
+/// Picks up to `max_fragments` non-overlapping fragments containing the most valuable matches.
+/// A fragment does not span multiple lines and has about `fragment_length` characters.
+pub(super) fn make_fragments(
+    text: &str,
+    matches: &[TokenMatch],
+    fragment_length: usize,
+    max_fragments: usize,
+    first_line: usize,
+) -> Vec<SnippetFragment> {
+    let mut candidates = vec![];
+    for (i, first) in matches.iter().enumerate() {
+        let line_end = line_end(text, first.start);
+        let mut terms = HashSet::from([first.term.as_str()]);
+        let mut candidate = Candidate {
+            start: first.start,
+            end: first.end,
+            score: first.score,
+        };
+        for m in &matches[i + 1..] {
+            if m.end > line_end || text[first.start..m.end].chars().count() > fragment_length {
+                break;
+            }
+            // 同じ語が何度も出てくるより、異なる語が含まれる方を優先する
+            if terms.insert(m.term.as_str()) {
+                candidate.score += m.score;
+            }
+            candidate.end = candidate.end.max(m.end);
+        }
+        candidates.push(candidate);
+    }
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.start.cmp(&b.start)));
+
+    let mut selected: Vec<Candidate> = vec![];
+    for candidate in candidates {
+        if selected.len() >= max_fragments {
+            break;
+        }
+        if selected
+            .iter()
+            .any(|x| candidate.start < x.end && x.start < candidate.end)
+        {
+            continue;
+        }
+        selected.push(candidate);
+    }
+    selected.sort_by_key(|x| x.start);
+
+    let mut fragments = vec![];
+    for (i, candidate) in selected.iter().enumerate() {
+        // 前後の文脈を同じ行の中で足す。隣の断片とは重ならないようにする
+        let lower = line_start(text, candidate.start).max(if i > 0 { selected[i - 1].end } else { 0 });
+        let upper = line_end(text, candidate.end).min(selected.get(i + 1).map_or(text.len(), |x| x.start));
+        let slack = fragment_length.saturating_sub(text[candidate.start..candidate.end].chars().count());
+        let left_available = text[lower..candidate.start].chars().count();
+        let right_available = text[candidate.end..upper].chars().count();
+        let right = right_available.min(slack - (slack / 2).min(left_available));
+        let left = left_available.min(slack - right);
+        let start = if left == 0 {
+            candidate.start
+        } else {
+            text[lower..candidate.start]
+                .char_indices()
+                .rev()
+                .nth(left - 1)
+                .map_or(lower, |(x, _)| lower + x)
+        };
+        let end = text[candidate.end..upper]
+            .char_indices()
+            .nth(right)
+            .map_or(upper, |(x, _)| candidate.end + x);
+
+        let fragment_text = &text[start..end];
+        let start = start + (fragment_text.len() - fragment_text.trim_start().len());
+        let end = end - (fragment_text.len() - fragment_text.trim_end().len());
+        let fragment_text = &text[start..end];
+
+        let highlights = to_highlight_ranges(
+            fragment_text,
+            matches
+                .iter()
+                .filter(|m| start <= m.start && m.end <= end)
+                .map(|m| (m.start - start, m.end - start))
+                .collect(),
+        );
+        fragments.push(SnippetFragment {
+            text: fragment_text.to_string(),
+            line: first_line + text[..start].matches('\n').count(),
+            highlights,
+        });
+    }
+    fragments
+}
+
+/// Converts byte ranges of matches in the text into highlight ranges, merging overlapping ones.
+pub(super) fn to_highlight_ranges(text: &str, mut ranges: Vec<(usize, usize)>) -> Vec<HighlightRange> {
+    ranges.sort();
+    let mut highlights: Vec<HighlightRange> = vec![];
+    for (start, end) in ranges {
+        // n-gram のトークンは重なるのでまとめる
+        if let Some(last) = highlights.last_mut() {
+            if start <= last.end {
+                if end > last.end {
+                    last.char_end += text[last.end..end].chars().count();
+                    last.end = end;
+                }
+                continue;
+            }
+        }
+        let char_start = text[..start].chars().count();
+        highlights.push(HighlightRange {
+            start,
+            end,
+            char_start,
+            char_end: char_start + text[start..end].chars().count(),
+        });
+    }
+    highlights
+}
+
+/// Renders the fragments as HTML with `<b>` tags, for the webview.
+pub(super) fn to_html(fragments: &[SnippetFragment]) -> String {
+    fragments
+        .iter()
+        .map(|fragment| highlight_to_html(&fragment.text, &fragment.highlights))
+        .collect::<Vec<_>>()
+        .join(FRAGMENT_SEPARATOR)
+}
+
+pub(super) fn highlight_to_html(text: &str, highlights: &[HighlightRange]) -> String {
+    let mut html = String::new();
+    let mut position = 0;
+    for highlight in highlights {
+        html.push_str(&escape_html(&text[position..highlight.start]));
+        html.push_str("<b>");
+        html.push_str(&escape_html(&text[highlight.start..highlight.end]));
+        html.push_str("</b>");
+        position = highlight.end;
+    }
+    html.push_str(&escape_html(&text[position..]));
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn line_start(text: &str, position: usize) -> usize {
+    text[..position].rfind('\n').map_or(0, |x| x + 1)
+}
+
+fn line_end(text: &str, position: usize) -> usize {
+    text[position..].find('\n').map_or(text.len(), |x| position + x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_matches(text: &str, terms: &[(&str, f32)]) -> Vec<TokenMatch> {
+        let mut matches = vec![];
+        for (term, score) in terms {
+            for (start, _) in text.match_indices(term) {
+                matches.push(TokenMatch {
+                    start,
+                    end: start + term.len(),
+                    term: term.to_string(),
+                    score: *score,
+                });
+            }
+        }
+        matches.sort_by_key(|x| (x.start, x.end));
+        matches
+    }
+
+    #[test]
+    fn test_make_fragments() {
+        let text = "# Title\nfoo is here.\nsome filler text\nfoo and bar are here.\n";
+        let matches = find_matches(text, &[("foo", 0.5), ("bar", 0.5)]);
+        let fragments = make_fragments(text, &matches, 30, 1, 3);
+        assert_eq!(
+            fragments,
+            vec![SnippetFragment {
+                text: "foo and bar are here.".to_string(),
+                line: 6,
+                highlights: vec![
+                    HighlightRange {
+                        start: 0,
+                        end: 3,
+                        char_start: 0,
+                        char_end: 3,
+                    },
+                    HighlightRange {
+                        start: 8,
+                        end: 11,
+                        char_start: 8,
+                        char_end: 11,
+                    },
+                ],
+            }]
+        );
+
+        let fragments = make_fragments(text, &matches, 30, 3, 1);
+        assert_eq!(
+            fragments.iter().map(|x| (x.text.as_str(), x.line)).collect::<Vec<_>>(),
+            vec![("foo is here.", 2), ("foo and bar are here.", 4)]
+        );
+    }
+
+    #[test]
+    fn test_make_fragments_with_context() {
+        let text = "aaaa bbbb cccc dddd eeee ffff gggg";
+        let matches = find_matches(text, &[("dddd", 1.0)]);
+        let fragments = make_fragments(text, &matches, 14, 3, 1);
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(fragments[0].text, "cccc dddd eeee");
+        assert_eq!(fragments[0].highlights[0].start, 5);
+    }
+
+    #[test]
+    fn test_make_fragments_multibyte() {
+        let text = "今日は検索エンジンの話";
+        let matches = find_matches(text, &[("検索", 1.0), ("索エ", 1.0)]);
+        let fragments = make_fragments(text, &matches, 100, 3, 1);
+        assert_eq!(fragments[0].text, text);
+        assert_eq!(
+            fragments[0].highlights,
+            vec![HighlightRange {
+                start: 9,
+                end: 18,
+                char_start: 3,
+                char_end: 6,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_to_html() {
+        let fragments = vec![
+            SnippetFragment {
+                text: "a <foo> b".to_string(),
+                line: 1,
+                highlights: vec![HighlightRange {
+                    start: 3,
+                    end: 6,
+                    char_start: 3,
+                    char_end: 6,
+                }],
+            },
+            SnippetFragment {
+                text: "foo".to_string(),
+                line: 2,
+                highlights: vec![],
+            },
+        ];
+        assert_eq!(to_html(&fragments), "a &lt;<b>foo</b>&gt; b … foo");
+    }
+}
@@ -0,0 +1,440 @@
+use std::{cmp::Ordering, collections::HashMap, fmt, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Serializer};
+use tantivy::{collector::DocSetCollector, query::Query, schema::Field, DocAddress, DocId, Searcher, SegmentReader};
+
+use crate::{
+    documents::search::{
+        nfkc_normalizer,
+        pagination::{self, After, CursorKey, Page},
+        schema::AppSchema,
+        snippet::SnippetOptions,
+        Search, SearchResults,
+    },
+    path_string_normalization::PathStringNormalizationExt,
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Sort {
+    Relevance,
+    ModifiedAt(SortOrder),
+    CreatedAt(SortOrder),
+    Title(SortOrder),
+    Path(SortOrder),
+    WordCount(SortOrder),
+    /// Estimated reading time, which is rounded to minutes and ties are frequent
+    ReadingTime(SortOrder),
+}
+
+/// Formats the sort like `relevance` or `modified_at:desc`, which is stored with saved searches.
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, order) = match self {
+            Sort::Relevance => return write!(f, "relevance"),
+            Sort::ModifiedAt(order) => ("modified_at", order),
+            Sort::CreatedAt(order) => ("created_at", order),
+            Sort::Title(order) => ("title", order),
+            Sort::Path(order) => ("path", order),
+            Sort::WordCount(order) => ("word_count", order),
+            Sort::ReadingTime(order) => ("reading_time", order),
+        };
+        let order = match order {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        };
+        write!(f, "{}:{}", name, order)
+    }
+}
+
+impl FromStr for Sort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "relevance" {
+            return Ok(Sort::Relevance);
+        }
+        let (name, order) = s.split_once(':').ok_or_else(|| anyhow!("invalid sort: {}", s))?;
+        let order = match order {
+            "asc" => SortOrder::Asc,
+            "desc" => SortOrder::Desc,
+            _ => return Err(anyhow!("invalid sort order: {}", s)),
+        };
+        match name {
+            "modified_at" => Ok(Sort::ModifiedAt(order)),
+            "created_at" => Ok(Sort::CreatedAt(order)),
+            "title" => Ok(Sort::Title(order)),
+            "path" => Ok(Sort::Path(order)),
+            "word_count" => Ok(Sort::WordCount(order)),
+            "reading_time" => Ok(Sort::ReadingTime(order)),
+            _ => Err(anyhow!("invalid sort: {}", s)),
+        }
+    }
+}
+
+impl Serialize for Sort {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Text field to sort documents by
+#[derive(Debug, Copy, Clone)]
+pub(super) enum TextSortField {
+    Title,
+    Path,
+}
+
+/// Key to compare texts, ignoring the width and the case of characters.
+pub(super) fn sort_key(text: &str) -> String {
+    nfkc_normalizer::normalize(text)
+}
+
+/// First 8 bytes of the sort key as a number, stored in a fast field.
+/// Texts with different prefixes are ordered in the same way as their sort keys.
+pub(super) fn sort_key_prefix(text: &str) -> u64 {
+    let mut bytes = [0u8; 8];
+    for (x, b) in bytes.iter_mut().zip(sort_key(text).bytes()) {
+        *x = b;
+    }
+    u64::from_be_bytes(bytes)
+}
+
+/// Reads the date fast field as seconds, for `Search::fast_field_sort_scorer`.
+pub(super) fn date_values(
+    field: Field,
+) -> impl Fn(&SegmentReader) -> Box<dyn Fn(DocId) -> i64> + Send + Sync + 'static {
+    move |segment_reader: &SegmentReader| -> Box<dyn Fn(DocId) -> i64> {
+        let reader = segment_reader.fast_fields().date(field).unwrap();
+        Box::new(move |doc: DocId| reader.get_val(doc).into_timestamp_secs())
+    }
+}
+
+/// Reads the u64 fast field, for `Search::fast_field_sort_scorer`.
+pub(super) fn number_values(
+    field: Field,
+) -> impl Fn(&SegmentReader) -> Box<dyn Fn(DocId) -> i64> + Send + Sync + 'static {
+    move |segment_reader: &SegmentReader| -> Box<dyn Fn(DocId) -> i64> {
+        let reader = segment_reader.fast_fields().u64(field).unwrap();
+        Box::new(move |doc: DocId| reader.get_val(doc) as i64)
+    }
+}
+
+impl Search {
+    /// Returns a scorer for `TopDocs::custom_score` sorting documents by the value of a fast field,
+    /// which `values` reads for each segment. Documents in the previous pages get `i64::MIN`.
+    pub(super) fn fast_field_sort_scorer<V>(
+        searcher: &Searcher,
+        order: SortOrder,
+        after: Option<After<i64>>,
+        values: V,
+    ) -> impl Fn(&SegmentReader) -> Box<dyn FnMut(DocId) -> i64> + Send + Sync + 'static
+    where
+        V: Fn(&SegmentReader) -> Box<dyn Fn(DocId) -> i64> + Send + Sync + 'static,
+    {
+        // TopDocs は値が大きい順に返すので、昇順のときは符号を反転する
+        let sign = match order {
+            SortOrder::Asc => -1,
+            SortOrder::Desc => 1,
+        };
+        let segment_ords = pagination::segment_ordinals(searcher);
+        move |segment_reader: &SegmentReader| -> Box<dyn FnMut(DocId) -> i64> {
+            let value = values(segment_reader);
+            let segment_ord = segment_ords[&segment_reader.segment_id()];
+            Box::new(move |doc: DocId| {
+                let key = sign * value(doc);
+                match after {
+                    Some(after) if after.covers(key, DocAddress::new(segment_ord, doc)) => i64::MIN,
+                    _ => key,
+                }
+            })
+        }
+    }
+
+    /// Sorts the documents matching the query by the title or the path.
+    pub(super) fn search_sorted_by_text(
+        &self,
+        query: Box<dyn Query>,
+        sort_field: TextSortField,
+        order: SortOrder,
+        snippet_options: SnippetOptions,
+        page: Page,
+    ) -> Result<SearchResults> {
+        page.check_position_cursor()?;
+        let schema = AppSchema::new(self.index.schema());
+        // key_field は text_field に保存されたテキストの sort_key_prefix を持つ (パスは監視フォルダからの相対パス)
+        let (key_field, text_field) = match sort_field {
+            TextSortField::Title => (schema.title_sort(), schema.title_ngram()),
+            TextSortField::Path => (schema.path_sort(), schema.path()),
+        };
+        let searcher = &page.searcher;
+        let collector = self.cancellable((DocSetCollector, self.facets_collector()));
+        let (doc_addresses, facets) = searcher.search(&query, &collector)?;
+        self.cancellation.check()?;
+        let count = doc_addresses.len();
+
+        let readers = searcher
+            .segment_readers()
+            .iter()
+            .map(|x| x.fast_fields().u64(key_field))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        let watch_id_readers = searcher
+            .segment_readers()
+            .iter()
+            .map(|x| x.fast_fields().i64(schema.watch_id()))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        // path_sort は監視フォルダからの相対パスのキーなので、パスでは監視フォルダの順に分けてから比べる
+        let watch_ranks = match sort_field {
+            TextSortField::Title => HashMap::new(),
+            TextSortField::Path => self.watch_ranks(),
+        };
+        let keyed: Vec<((u64, u64), DocAddress)> = doc_addresses
+            .into_iter()
+            .map(|x| {
+                let segment_ord = x.segment_ord as usize;
+                let rank = match sort_field {
+                    TextSortField::Title => 0,
+                    TextSortField::Path => {
+                        let watch_id = watch_id_readers[segment_ord].get_val(x.doc_id);
+                        watch_ranks.get(&watch_id).copied().unwrap_or(u64::MAX)
+                    }
+                };
+                ((rank, readers[segment_ord].get_val(x.doc_id)), x)
+            })
+            .collect();
+
+        let doc_addresses = sort_page(keyed, order, page.offset, page.limit, |doc_addresses| {
+            // 先頭 8 バイトが同じ文書だけ、保存済みのテキストを読んで比較する
+            doc_addresses
+                .iter()
+                .map(|x| {
+                    let doc = searcher.doc(*x)?;
+                    let text = doc.get_first(text_field).and_then(|x| x.as_text()).unwrap_or_default();
+                    let path = doc
+                        .get_first(schema.path())
+                        .and_then(|x| x.as_text())
+                        .unwrap_or_default();
+                    Ok((sort_key(text), path.to_string()))
+                })
+                .collect()
+        })?;
+        let last = doc_addresses.last().map(|x| (CursorKey::Position, *x));
+        let next_cursor = page.next_cursor(count, doc_addresses.len(), last);
+        let mut results = self.build_results(searcher, &*query, count, doc_addresses, facets, snippet_options)?;
+        results.cursor = next_cursor.map(|x| self.encode_cursor(&page, x));
+        Ok(results)
+    }
+
+    /// Positions of the watches ordered by their paths, keyed by the watch IDs.
+    /// Watches are never nested, so the paths of their documents are ordered in the same way.
+    fn watch_ranks(&self) -> HashMap<i64, u64> {
+        let watch_paths = self.watch_paths.read().unwrap();
+        let mut roots: Vec<(String, i64)> = watch_paths
+            .iter()
+            .map(|(watch_id, path)| {
+                (
+                    sort_key(&format!("{}/", path.as_path().to_normalized_path_string())),
+                    watch_id.0,
+                )
+            })
+            .collect();
+        roots.sort();
+        roots
+            .into_iter()
+            .enumerate()
+            .map(|(rank, (_, watch_id))| (watch_id, rank as u64))
+            .collect()
+    }
+}
+
+/// Key prefix of the path in `path_sort`, relative to the watch if the path is under it.
+/// Documents in a watch share the path of the watch, which would make the prefixes of all of them the same.
+pub(super) fn path_sort_key_prefix(path: &Path, watch_path: Option<&Path>) -> u64 {
+    match watch_path.and_then(|x| path.strip_prefix(x).ok()) {
+        Some(relative_path) => sort_key_prefix(&relative_path.to_normalized_path_string()),
+        None => sort_key_prefix(&path.to_normalized_path_string()),
+    }
+}
+
+/// Sorts the items by the key prefixes and returns the items in `offset..offset + limit`.
+/// Only the items whose prefixes are the same as the ones at the edges of the page are compared by `full_keys`,
+/// which returns the keys of the items in the same order.
+fn sort_page<P, T, K, F>(
+    mut items: Vec<(P, T)>,
+    order: SortOrder,
+    offset: usize,
+    limit: usize,
+    full_keys: F,
+) -> Result<Vec<T>>
+where
+    P: Copy + Ord,
+    T: Copy + Ord,
+    K: Ord,
+    F: FnOnce(&[T]) -> Result<Vec<K>>,
+{
+    let apply_order = |x: Ordering| match order {
+        SortOrder::Asc => x,
+        SortOrder::Desc => x.reverse(),
+    };
+    items.sort_by(|a, b| apply_order(a.0.cmp(&b.0)).then(a.1.cmp(&b.1)));
+    if offset >= items.len() || limit == 0 {
+        return Ok(vec![]);
+    }
+    let end = (offset + limit).min(items.len());
+
+    let mut start_group = offset;
+    while start_group > 0 && items[start_group - 1].0 == items[offset].0 {
+        start_group -= 1;
+    }
+    let mut end_group = end;
+    while end_group < items.len() && items[end_group].0 == items[end - 1].0 {
+        end_group += 1;
+    }
+
+    let candidates: Vec<T> = items[start_group..end_group].iter().map(|x| x.1).collect();
+    let keys = full_keys(&candidates)?;
+    let mut candidates: Vec<(P, K, T)> = items[start_group..end_group]
+        .iter()
+        .zip(keys)
+        .map(|((prefix, item), key)| (*prefix, key, *item))
+        .collect();
+    candidates.sort_by(|a, b| apply_order(a.0.cmp(&b.0).then(a.1.cmp(&b.1))).then(a.2.cmp(&b.2)));
+    Ok(candidates
+        .into_iter()
+        .skip(offset - start_group)
+        .take(end - offset)
+        .map(|x| x.2)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_key_prefix() {
+        assert_eq!(sort_key_prefix("Ａbc"), sort_key_prefix("abc"));
+        assert!(sort_key_prefix("apple") < sort_key_prefix("Banana"));
+        assert!(sort_key_prefix("ab") < sort_key_prefix("abc"));
+        assert_eq!(sort_key_prefix("/notes/work/a.md"), sort_key_prefix("/notes/work/b.md"));
+        assert_eq!(sort_key_prefix(""), 0);
+    }
+
+    #[test]
+    fn test_path_sort_key_prefix() {
+        let watch_path = Path::new("/users/alice/notes");
+        assert_eq!(
+            path_sort_key_prefix(Path::new("/users/alice/notes/work/a.md"), Some(watch_path)),
+            sort_key_prefix("work/a.md")
+        );
+        assert_ne!(
+            path_sort_key_prefix(Path::new("/users/alice/notes/work/a.md"), Some(watch_path)),
+            path_sort_key_prefix(Path::new("/users/alice/notes/home/a.md"), Some(watch_path))
+        );
+        assert_eq!(
+            path_sort_key_prefix(Path::new("/tmp/a.md"), Some(watch_path)),
+            sort_key_prefix("/tmp/a.md")
+        );
+        assert_eq!(
+            path_sort_key_prefix(Path::new("/tmp/a.md"), None),
+            sort_key_prefix("/tmp/a.md")
+        );
+    }
+
+    #[test]
+    fn test_sort_to_string_and_back() -> Result<()> {
+        for sort in [
+            Sort::Relevance,
+            Sort::ModifiedAt(SortOrder::Desc),
+            Sort::CreatedAt(SortOrder::Asc),
+            Sort::Title(SortOrder::Asc),
+            Sort::Path(SortOrder::Desc),
+            Sort::WordCount(SortOrder::Asc),
+            Sort::ReadingTime(SortOrder::Desc),
+        ] {
+            assert_eq!(sort.to_string().parse::<Sort>()?, sort);
+        }
+        assert_eq!(Sort::ModifiedAt(SortOrder::Desc).to_string(), "modified_at:desc");
+        assert!("title".parse::<Sort>().is_err());
+        assert!("size:asc".parse::<Sort>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_page() -> Result<()> {
+        let texts = [
+            "/notes/work/c.md",
+            "banana",
+            "/notes/work/a.md",
+            "Apple",
+            "/notes/work/b.md",
+        ];
+        let items: Vec<(u64, usize)> = texts.iter().enumerate().map(|(i, x)| (sort_key_prefix(x), i)).collect();
+        let full_keys =
+            |indices: &[usize]| -> Result<Vec<String>> { Ok(indices.iter().map(|i| sort_key(texts[*i])).collect()) };
+
+        assert_eq!(
+            sort_page(items.clone(), SortOrder::Asc, 0, 10, full_keys)?,
+            vec![2, 4, 0, 3, 1]
+        );
+        assert_eq!(
+            sort_page(items.clone(), SortOrder::Desc, 0, 10, full_keys)?,
+            vec![1, 3, 0, 4, 2]
+        );
+        assert_eq!(sort_page(items.clone(), SortOrder::Asc, 1, 2, full_keys)?, vec![4, 0]);
+        assert_eq!(sort_page(items.clone(), SortOrder::Desc, 3, 1, full_keys)?, vec![4]);
+        assert_eq!(sort_page(items, SortOrder::Asc, 5, 1, full_keys)?, Vec::<usize>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_page_reads_only_ties_at_the_edges() -> Result<()> {
+        let items = vec![(1, 0), (2, 1), (2, 2), (3, 3), (4, 4)];
+        let page = sort_page(items, SortOrder::Asc, 2, 2, |indices: &[usize]| {
+            assert_eq!(indices, &[1, 2, 3]);
+            Ok(vec![1, 0, 0])
+        })?;
+        assert_eq!(page, vec![1, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_page_by_watch_and_relative_path() -> Result<()> {
+        let watch_paths = [Path::new("/notes/work"), Path::new("/notes/home")];
+        let documents = [
+            ("/notes/work/b.md", 0),
+            ("/notes/home/a.md", 1),
+            ("/notes/work/a.md", 0),
+        ];
+        // /notes/home/ は /notes/work/ より前に並ぶ
+        let ranks = [1, 0];
+        let items: Vec<((u64, u64), usize)> = documents
+            .iter()
+            .enumerate()
+            .map(|(i, (path, watch))| {
+                let prefix = path_sort_key_prefix(Path::new(path), Some(watch_paths[*watch]));
+                ((ranks[*watch], prefix), i)
+            })
+            .collect();
+        let full_keys = |indices: &[usize]| -> Result<Vec<String>> {
+            Ok(indices.iter().map(|i| sort_key(documents[*i].0)).collect())
+        };
+        assert_eq!(
+            sort_page(items.clone(), SortOrder::Asc, 0, 10, full_keys)?,
+            vec![1, 2, 0]
+        );
+        // 同じ監視フォルダの文書でもキーが異なるので、ページの端で全体のキーを読むのはその文書だけになる
+        let page = sort_page(items, SortOrder::Asc, 1, 1, |indices: &[usize]| {
+            assert_eq!(indices, &[2]);
+            full_keys(indices)
+        })?;
+        assert_eq!(page, vec![2]);
+        Ok(())
+    }
+}
@@ -0,0 +1,17 @@
+use anyhow::Result;
+use tantivy::{
+    tokenizer::{RemoveLongFilter, SimpleTokenizer, TextAnalyzer},
+    Index,
+};
+
+use crate::documents::search::nfkc_normalizer::NfkcNormalizer;
+
+/// Keeps words as they are written (no stemming, no stopwords) so that they can be suggested for misspelled queries.
+pub fn setup_tokenizer(index: &Index) -> Result<()> {
+    let analyzer = TextAnalyzer::from(SimpleTokenizer)
+        .filter(RemoveLongFilter::limit(40))
+        .filter(NfkcNormalizer);
+
+    index.tokenizers().register("spelling", analyzer);
+    Ok(())
+}
@@ -13,7 +13,7 @@ pub fn load_stopwords_for_lang(lang: &str) -> Result<Vec<String>> {
     read_lines(&*data)
 }
 
-fn read_lines<R: Read>(reader: R) -> Result<Vec<String>> {
+pub(super) fn read_lines<R: Read>(reader: R) -> Result<Vec<String>> {
     let mut lines = Vec::new();
     let reader = std::io::BufReader::new(reader);
     for line in reader.lines() {
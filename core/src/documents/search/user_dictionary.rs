@@ -0,0 +1,49 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+
+const USER_DICTIONARY_FILE_NAME: &str = "user_dictionary.csv";
+
+/// A user dictionary for the Japanese tokenizer in the Vibrato/MeCab lexicon CSV format.
+///
+/// A copy of the dictionary used for indexing is kept in the index directory
+/// so that a change of the dictionary can be detected at the next startup.
+pub struct UserDictionary {
+    data: Option<Vec<u8>>,
+}
+
+impl UserDictionary {
+    pub fn load<P: AsRef<Path>>(app_dir: P) -> Result<Self> {
+        let data = read_if_exists(&app_dir.as_ref().join(USER_DICTIONARY_FILE_NAME))?;
+        Ok(Self { data })
+    }
+
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+
+    pub fn has_changed<P: AsRef<Path>>(&self, index_dir: P) -> Result<bool> {
+        let snapshot = read_if_exists(&index_dir.as_ref().join(USER_DICTIONARY_FILE_NAME))?;
+        Ok(snapshot != self.data)
+    }
+
+    pub fn save_snapshot<P: AsRef<Path>>(&self, index_dir: P) -> Result<()> {
+        let snapshot_path = index_dir.as_ref().join(USER_DICTIONARY_FILE_NAME);
+        match &self.data {
+            Some(data) => fs::write(snapshot_path, data)?,
+            None => {
+                if snapshot_path.exists() {
+                    fs::remove_file(snapshot_path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(fs::read(path)?))
+}
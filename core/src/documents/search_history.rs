@@ -0,0 +1,103 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::SqliteConnection;
+
+pub use search_history_repository::{
+    clear, find_frequent_queries, find_opened_counts, find_recent_queries, is_enabled, set_enabled,
+};
+
+mod search_history_repository;
+
+/// Queries searched within this time after a shorter or longer one are taken as the same query being typed
+const TYPING_WINDOW_SECONDS: i64 = 30;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryHistoryEntry {
+    pub query: String,
+    pub count: i64,
+    pub last_searched_at: DateTime<Utc>,
+}
+
+/// Lowercases the query and collapses whitespaces, so that the same query typed differently is counted together.
+pub fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|x| x.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Records the query if the history is enabled.
+///
+/// The search box searches while typing, so a query extending or shortening the last one shortly after it
+/// replaces the last one instead of being recorded separately.
+pub async fn record_query(query: &str, searched_at: DateTime<Utc>, conn: &mut SqliteConnection) -> Result<()> {
+    let query = normalize_query(query);
+    if query.is_empty() || !is_enabled(&mut *conn).await? {
+        return Ok(());
+    }
+    if let Some(last) = find_recent_queries(1, &mut *conn).await?.into_iter().next() {
+        if is_typing(&last, &query, searched_at) {
+            search_history_repository::delete_query(&last.query, &mut *conn).await?;
+        }
+    }
+    search_history_repository::upsert_query(&query, searched_at, &mut *conn).await
+}
+
+/// Records that the document was opened from the results of the query if the history is enabled.
+pub async fn record_opened_result(
+    query: &str,
+    path: &str,
+    opened_at: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<()> {
+    let query = normalize_query(query);
+    if query.is_empty() || !is_enabled(&mut *conn).await? {
+        return Ok(());
+    }
+    search_history_repository::upsert_opened_result(&query, path, opened_at, &mut *conn).await
+}
+
+/// Returns true if the query looks like the last query being typed further, e.g. `rus` followed by `rust`.
+fn is_typing(last: &QueryHistoryEntry, query: &str, searched_at: DateTime<Utc>) -> bool {
+    // 何度も検索されたクエリは入力途中のものではない
+    last.count == 1
+        && last.query != query
+        && searched_at - last.last_searched_at < Duration::seconds(TYPING_WINDOW_SECONDS)
+        && (query.starts_with(&last.query) || last.query.starts_with(query))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn entry(query: &str, count: i64, last_searched_at: DateTime<Utc>) -> QueryHistoryEntry {
+        QueryHistoryEntry {
+            query: query.to_string(),
+            count,
+            last_searched_at,
+        }
+    }
+
+    #[test]
+    fn test_normalize_query() {
+        assert_eq!(normalize_query("  Rust   Tokio\t"), "rust tokio");
+        assert_eq!(normalize_query("議事録　2022"), "議事録 2022");
+        assert_eq!(normalize_query(" "), "");
+    }
+
+    #[test]
+    fn test_is_typing() {
+        let now = Utc.ymd(2022, 11, 10).and_hms(12, 0, 0);
+        assert!(is_typing(&entry("rus", 1, now - Duration::seconds(1)), "rust", now));
+        assert!(is_typing(&entry("rust", 1, now - Duration::seconds(1)), "rus", now));
+        assert!(!is_typing(&entry("rust", 1, now - Duration::seconds(1)), "rust", now));
+        assert!(!is_typing(&entry("rus", 3, now - Duration::seconds(1)), "rust", now));
+        assert!(!is_typing(&entry("rus", 1, now - Duration::minutes(5)), "rust", now));
+        assert!(!is_typing(&entry("tokio", 1, now - Duration::seconds(1)), "rust", now));
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::SqliteConnection;
+
+use crate::documents::search_history::QueryHistoryEntry;
+
+const ENABLED_KEY: &str = "search_history.enabled";
+
+pub(super) async fn upsert_query(query: &str, searched_at: DateTime<Utc>, conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query!(
+        r"
+insert into search_history (query, count, last_searched_at)
+values ($1, 1, $2)
+on conflict (query) do update
+set count = count + 1,
+last_searched_at = excluded.last_searched_at
+",
+        query,
+        searched_at,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+pub(super) async fn delete_query(query: &str, conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query!(
+        r"
+delete from search_history where query = $1
+",
+        query,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the queries searched most recently first.
+pub async fn find_recent_queries(limit: u32, conn: &mut SqliteConnection) -> Result<Vec<QueryHistoryEntry>> {
+    let rows = sqlx::query_as!(
+        QueryHistoryRow,
+        r"
+select query, count, last_searched_at
+from search_history
+order by last_searched_at desc
+limit $1
+",
+        limit,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|x| x.into()).collect())
+}
+
+/// Returns the queries searched most often first.
+pub async fn find_frequent_queries(limit: u32, conn: &mut SqliteConnection) -> Result<Vec<QueryHistoryEntry>> {
+    let rows = sqlx::query_as!(
+        QueryHistoryRow,
+        r"
+select query, count, last_searched_at
+from search_history
+order by count desc, last_searched_at desc
+limit $1
+",
+        limit,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|x| x.into()).collect())
+}
+
+pub(super) async fn upsert_opened_result(
+    query: &str,
+    path: &str,
+    opened_at: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<()> {
+    sqlx::query!(
+        r"
+insert into opened_results (query, path, count, last_opened_at)
+values ($1, $2, 1, $3)
+on conflict (query, path) do update
+set count = count + 1,
+last_opened_at = excluded.last_opened_at
+",
+        query,
+        path,
+        opened_at,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns how many times each document was opened from the results of the normalized query.
+pub async fn find_opened_counts(query: &str, conn: &mut SqliteConnection) -> Result<HashMap<String, u32>> {
+    let rows = sqlx::query!(
+        r"
+select path, count
+from opened_results
+where query = $1
+",
+        query,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|x| (x.path, x.count as u32)).collect())
+}
+
+/// Deletes all the queries and the opened results.
+pub async fn clear(conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query!(
+        r"
+delete from search_history
+"
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        r"
+delete from opened_results
+"
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// The history is enabled unless it's turned off.
+pub async fn is_enabled(conn: &mut SqliteConnection) -> Result<bool> {
+    let row = sqlx::query!(
+        r"
+select value
+from preferences
+where key = $1
+",
+        ENABLED_KEY,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(row.map_or(true, |x| x.value != "false"))
+}
+
+pub async fn set_enabled(enabled: bool, conn: &mut SqliteConnection) -> Result<()> {
+    let value = enabled.to_string();
+    sqlx::query!(
+        r"
+insert into preferences (key, value)
+values ($1, $2)
+on conflict (key) do update
+set value = excluded.value
+",
+        ENABLED_KEY,
+        value,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+struct QueryHistoryRow {
+    query: String,
+    count: i64,
+    last_searched_at: chrono::NaiveDateTime,
+}
+
+impl From<QueryHistoryRow> for QueryHistoryEntry {
+    fn from(row: QueryHistoryRow) -> Self {
+        Self {
+            query: row.query,
+            count: row.count,
+            last_searched_at: DateTime::from_utc(row.last_searched_at, Utc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use chrono::Duration;
+    use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+
+    use super::*;
+    use crate::documents::search_history::{record_opened_result, record_query};
+
+    async fn init() -> Result<(tempfile::TempDir, SqlitePool)> {
+        let db_dir = tempfile::tempdir()?;
+        let connection = SqlitePoolOptions::new()
+            .connect(format!("sqlite://{}/jomai.db?mode=rwc", db_dir.path().display()).as_str())
+            .await?;
+        sqlx::migrate!().run(&connection).await?;
+        Ok((db_dir, connection))
+    }
+
+    #[tokio::test]
+    async fn test_record_query() -> Result<()> {
+        let (_db_dir, pool) = init().await?;
+        let mut conn = pool.acquire().await?;
+        let now = Utc::now();
+
+        record_query("ru", now - Duration::minutes(10), &mut *conn).await?;
+        record_query("Rust", now - Duration::minutes(5), &mut *conn).await?;
+        record_query("rust tok", now - Duration::seconds(2), &mut *conn).await?;
+        record_query("rust tokio", now - Duration::seconds(1), &mut *conn).await?;
+        record_query("rust", now + Duration::minutes(1), &mut *conn).await?;
+
+        let recent: Vec<String> = find_recent_queries(10, &mut *conn)
+            .await?
+            .into_iter()
+            .map(|x| x.query)
+            .collect();
+        assert_eq!(recent, vec!["rust", "rust tokio", "ru"]);
+        let frequent = find_frequent_queries(1, &mut *conn).await?;
+        assert_eq!(frequent[0].query, "rust");
+        assert_eq!(frequent[0].count, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_opened_results_and_toggle() -> Result<()> {
+        let (_db_dir, pool) = init().await?;
+        let mut conn = pool.acquire().await?;
+        let now = Utc::now();
+
+        assert!(is_enabled(&mut *conn).await?);
+        record_opened_result("Weekly  Report", "/foo/a.md", now, &mut *conn).await?;
+        record_opened_result("weekly report", "/foo/a.md", now, &mut *conn).await?;
+        record_opened_result("weekly report", "/foo/b.md", now, &mut *conn).await?;
+        let counts = find_opened_counts("weekly report", &mut *conn).await?;
+        assert_eq!(counts.get("/foo/a.md"), Some(&2));
+        assert_eq!(counts.get("/foo/b.md"), Some(&1));
+
+        set_enabled(false, &mut *conn).await?;
+        assert!(!is_enabled(&mut *conn).await?);
+        record_query("weekly report", now, &mut *conn).await?;
+        record_opened_result("weekly report", "/foo/b.md", now, &mut *conn).await?;
+        assert!(find_recent_queries(10, &mut *conn).await?.is_empty());
+        assert_eq!(
+            find_opened_counts("weekly report", &mut *conn).await?.get("/foo/b.md"),
+            Some(&1)
+        );
+
+        clear(&mut *conn).await?;
+        assert!(find_opened_counts("weekly report", &mut *conn).await?.is_empty());
+        Ok(())
+    }
+}
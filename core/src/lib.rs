@@ -1,6 +1,13 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
 
-pub use documents::{SearchResults, Sort};
+pub use documents::{
+    preview::LOCAL_IMAGE_SCHEME, quick_open::QuickOpenResult, AnalyzerTokens, Completion, CursorError,
+    DocumentAnnotation, DocumentDetails, DuplicateGroup, Facets, FolderFacet, FrontmatterField, GrepMatch, GrepMode,
+    GrepOptions, GrepResultDocument, GrepResults, HeadingNode, HighlightRange, MatchedField, MonthFacet,
+    NewSavedSearch, OutlineHeading, QueryHistoryEntry, QueryMode, QuerySyntaxError, QuerySyntaxErrorKind,
+    RelatedDocument, RenderedDocument, SavedSearch, SavedSearchId, SavedSearchMatch, SearchCancelled, SearchOptions,
+    SearchResults, SnippetFragment, Sort, SortOrder, WatchFacet,
+};
 pub use watches::{jobs::JobReport, Watch, WatchId, WatchState};
 
 pub use crate::core::{AddWatchError, Core, CoreController};
@@ -9,6 +16,7 @@ mod assets;
 mod core;
 mod documents;
 mod path_string_normalization;
+mod settings;
 mod watches;
 
 type DateTime = chrono::DateTime<chrono::Utc>;
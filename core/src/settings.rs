@@ -0,0 +1,326 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::documents::QueryMode;
+
+const SETTINGS_FILE_NAME: &str = "settings.toml";
+
+/// User settings read from `settings.toml` in the app directory.
+/// Every item is optional and falls back to its default.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Settings {
+    pub ranking: RankingSettings,
+    pub related_documents: RelatedDocumentsSettings,
+    pub duplicates: DuplicatesSettings,
+    pub search: SearchSettings,
+}
+
+impl Settings {
+    pub fn load<P: AsRef<Path>>(app_dir: P) -> Result<Self> {
+        let path = app_dir.as_ref().join(SETTINGS_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        match toml::from_str(&fs::read_to_string(&path)?) {
+            Ok(settings) => Ok(Self::validate(settings)),
+            Err(e) => {
+                tracing::error!("Failed to parse {}, using the default settings: {}", path.display(), e);
+                Ok(Self::default())
+            }
+        }
+    }
+
+    /// Replaces the invalid values with the defaults, warning about them.
+    fn validate(mut self) -> Self {
+        self.ranking = self.ranking.validate();
+        self
+    }
+}
+
+/// Returns true if the score can be multiplied by the value without reversing or breaking the ranking.
+fn is_valid_multiplier(value: f32) -> bool {
+    value.is_finite() && value >= 0.0
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RankingSettings {
+    pub field_boosts: FieldBoosts,
+    pub recency: RecencySettings,
+    pub opened: OpenedSettings,
+    /// Score multipliers keyed by watch path
+    pub watch_priorities: HashMap<String, f32>,
+}
+
+impl RankingSettings {
+    fn validate(mut self) -> Self {
+        self.field_boosts = self.field_boosts.validate();
+        self.recency = self.recency.validate();
+        self.opened = self.opened.validate();
+        // 優先度を指定しない監視フォルダと同じ扱いにする
+        self.watch_priorities.retain(|path, priority| {
+            let is_valid = is_valid_multiplier(*priority);
+            if !is_valid {
+                tracing::warn!(
+                    "Invalid [ranking.watch_priorities] for {}, ignoring it: {}",
+                    path,
+                    priority
+                );
+            }
+            is_valid
+        });
+        self
+    }
+
+    pub fn watch_priority<P: AsRef<Path>>(&self, watch_path: P) -> Option<f32> {
+        self.watch_priorities
+            .iter()
+            .find(|(path, _)| Path::new(path) == watch_path.as_ref())
+            .map(|(_, priority)| *priority)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FieldBoosts {
+    pub path: f32,
+    pub path_components: f32,
+    pub path_ngram: f32,
+    pub title: f32,
+    pub title_ngram: f32,
+    pub contents: f32,
+    pub contents_ngram: f32,
+    pub tag: f32,
+    pub tag_ngram: f32,
+}
+
+impl Default for FieldBoosts {
+    fn default() -> Self {
+        const BOOST_LITTLE: f32 = 0.5;
+        const BOOST_NORMAL: f32 = 1.0;
+        const BOOST_MUCH: f32 = 5.0;
+        const BOOST_MORE: f32 = 8.0;
+        const BOOST_MOST: f32 = 10.0;
+
+        Self {
+            path: BOOST_MOST,
+            path_components: BOOST_MOST,
+            path_ngram: BOOST_LITTLE,
+            title: BOOST_MOST,
+            title_ngram: BOOST_MUCH,
+            contents: BOOST_NORMAL,
+            contents_ngram: BOOST_LITTLE,
+            tag: BOOST_MORE,
+            tag_ngram: BOOST_MUCH,
+        }
+    }
+}
+
+impl FieldBoosts {
+    /// Falls back to the default for each boost which is negative, infinite or NaN.
+    fn validate(mut self) -> Self {
+        let default = Self::default();
+        for (name, boost, default) in [
+            ("path", &mut self.path, default.path),
+            ("path_components", &mut self.path_components, default.path_components),
+            ("path_ngram", &mut self.path_ngram, default.path_ngram),
+            ("title", &mut self.title, default.title),
+            ("title_ngram", &mut self.title_ngram, default.title_ngram),
+            ("contents", &mut self.contents, default.contents),
+            ("contents_ngram", &mut self.contents_ngram, default.contents_ngram),
+            ("tag", &mut self.tag, default.tag),
+            ("tag_ngram", &mut self.tag_ngram, default.tag_ngram),
+        ] {
+            if !is_valid_multiplier(*boost) {
+                tracing::warn!("Invalid [ranking.field_boosts] {}, using the default: {}", name, boost);
+                *boost = default;
+            }
+        }
+        self
+    }
+}
+
+/// Blends the relevance score with the last modified time.
+/// The score is multiplied by `1 + weight * 0.5 ^ (age_in_days / half_life_days)`.
+#[derive(Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct RecencySettings {
+    /// 0 disables the recency boost
+    pub weight: f32,
+    pub half_life_days: f32,
+}
+
+impl Default for RecencySettings {
+    fn default() -> Self {
+        Self {
+            weight: 0.0,
+            half_life_days: 30.0,
+        }
+    }
+}
+
+impl RecencySettings {
+    /// Falls back to the default if the boost would be negative, infinite or NaN.
+    fn validate(self) -> Self {
+        let is_valid = is_valid_multiplier(self.weight) && self.half_life_days.is_finite() && self.half_life_days > 0.0;
+        if is_valid {
+            return self;
+        }
+        tracing::warn!("Invalid [ranking.recency] settings, using the default: {:?}", self);
+        Self::default()
+    }
+}
+
+/// Boosts the documents often opened from the results of the same query.
+/// The score is multiplied by `1 + weight * log2(1 + times_opened)`.
+#[derive(Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct OpenedSettings {
+    /// 0 disables the opened boost
+    pub weight: f32,
+}
+
+impl Default for OpenedSettings {
+    fn default() -> Self {
+        Self { weight: 0.3 }
+    }
+}
+
+impl OpenedSettings {
+    /// Falls back to the default if the boost would be negative, infinite or NaN.
+    fn validate(self) -> Self {
+        if is_valid_multiplier(self.weight) {
+            return self;
+        }
+        tracing::warn!("Invalid [ranking.opened] settings, using the default: {:?}", self);
+        Self::default()
+    }
+}
+
+#[derive(Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct RelatedDocumentsSettings {
+    /// Related documents scoring lower than this ratio to the score of the source document itself are skipped
+    pub min_similarity: f32,
+}
+
+impl Default for RelatedDocumentsSettings {
+    fn default() -> Self {
+        Self { min_similarity: 0.1 }
+    }
+}
+
+#[derive(Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct DuplicatesSettings {
+    /// Documents are reported as near-duplicates when the estimated similarity of their contents is at least this value
+    pub threshold: f32,
+}
+
+impl Default for DuplicatesSettings {
+    fn default() -> Self {
+        Self { threshold: 0.8 }
+    }
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, Default)]
+#[serde(default)]
+pub struct SearchSettings {
+    /// Query mode used when the search request doesn't specify one
+    pub query_mode: QueryMode,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let settings: Settings = toml::from_str(
+            r#"
+[ranking.field_boosts]
+title = 20.0
+
+[ranking.recency]
+weight = 0.5
+
+[ranking.opened]
+weight = 0.0
+
+[ranking.watch_priorities]
+"/Users/me/archive" = 0.5
+
+[related_documents]
+min_similarity = 0.2
+
+[duplicates]
+threshold = 0.9
+
+[search]
+query_mode = "literal"
+"#,
+        )
+        .unwrap();
+        assert_eq!(settings.ranking.field_boosts.title, 20.0);
+        assert_eq!(settings.ranking.field_boosts.contents, 1.0);
+        assert_eq!(settings.ranking.recency.weight, 0.5);
+        assert_eq!(settings.ranking.recency.half_life_days, 30.0);
+        assert_eq!(settings.ranking.opened.weight, 0.0);
+        assert_eq!(settings.ranking.watch_priority("/Users/me/archive"), Some(0.5));
+        assert_eq!(settings.ranking.watch_priority("/Users/me/main"), None);
+        assert_eq!(settings.related_documents.min_similarity, 0.2);
+        assert_eq!(settings.duplicates.threshold, 0.9);
+        assert_eq!(settings.search.query_mode, QueryMode::Literal);
+    }
+
+    #[test]
+    fn test_validate_recency() {
+        let recency = |text: &str| {
+            let settings: Settings = toml::from_str(&format!("[ranking.recency]\n{}", text)).unwrap();
+            settings.validate().ranking.recency
+        };
+        let valid = recency("weight = 0.5\nhalf_life_days = 7.0");
+        assert_eq!((valid.weight, valid.half_life_days), (0.5, 7.0));
+        for text in [
+            "weight = 0.5\nhalf_life_days = 0.0",
+            "weight = 0.5\nhalf_life_days = -7.0",
+            "weight = 0.5\nhalf_life_days = inf",
+            "weight = nan",
+            "weight = inf",
+            "weight = -0.5",
+        ] {
+            let recency = recency(text);
+            assert_eq!((recency.weight, recency.half_life_days), (0.0, 30.0), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_validate_ranking() {
+        let settings: Settings = toml::from_str(
+            r#"
+[ranking.field_boosts]
+title = 20.0
+contents = -1.0
+tag = nan
+
+[ranking.opened]
+weight = inf
+
+[ranking.watch_priorities]
+"/Users/me/archive" = 0.5
+"/Users/me/main" = -2.0
+"#,
+        )
+        .unwrap();
+        let ranking = settings.validate().ranking;
+        assert_eq!(ranking.field_boosts.title, 20.0);
+        assert_eq!(ranking.field_boosts.contents, 1.0);
+        assert_eq!(ranking.field_boosts.tag, 8.0);
+        assert_eq!(ranking.opened.weight, 0.3);
+        assert_eq!(ranking.watch_priority("/Users/me/archive"), Some(0.5));
+        assert_eq!(ranking.watch_priority("/Users/me/main"), None);
+    }
+}
@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 use crate::{DateTime, Watch, WatchId};
 
 pub mod delete_watch_job;
+pub mod detect_duplicates_job;
 mod job_manager;
 pub mod job_queue;
 mod parallelism;
@@ -87,6 +88,7 @@ pub enum JobType {
     ScanWatchPath,
     DeleteWatch,
     SyncWatch,
+    DetectDuplicates,
 }
 
 impl FromStr for JobType {
@@ -97,6 +99,7 @@ impl FromStr for JobType {
             "scan_watch_path" => Ok(JobType::ScanWatchPath),
             "delete_watch" => Ok(JobType::DeleteWatch),
             "sync_watch" => Ok(JobType::SyncWatch),
+            "detect_duplicates" => Ok(JobType::DetectDuplicates),
             _ => Err(()),
         }
     }
@@ -0,0 +1,154 @@
+use anyhow::Result;
+use futures::{channel::mpsc, SinkExt, TryStreamExt};
+use sqlx::SqlitePool;
+use tracing::instrument;
+
+use crate::{
+    documents::{
+        duplicates,
+        duplicates::{DuplicateGroup, Signature},
+        Document, DocumentService,
+    },
+    path_string_normalization::PathStringNormalizationExt,
+    watches::{
+        jobs::{JobProgress, JobStatus, JobType},
+        WatchEvent,
+    },
+    JobReport, Watch,
+};
+
+/// 進捗を通知する間隔 (文書数)
+const REPORT_INTERVAL: u32 = 100;
+
+/// watch 内の文書のシグネチャを計算し、ほぼ重複している文書をグループにまとめるジョブ
+///
+/// - シグネチャは文書がインデックスに登録されたときの本文から計算し、再登録されるまでは保存済みのものを使う
+/// - 結果は前回の結果を置き換える
+#[derive(Clone)]
+pub struct DetectDuplicatesJob {
+    connection_pool: SqlitePool,
+    document_service: DocumentService,
+    watch_event_tx: mpsc::UnboundedSender<WatchEvent>,
+    threshold: f32,
+}
+
+impl DetectDuplicatesJob {
+    pub fn new(
+        connection_pool: SqlitePool,
+        document_service: DocumentService,
+        watch_event_tx: mpsc::UnboundedSender<WatchEvent>,
+        threshold: f32,
+    ) -> Self {
+        Self {
+            connection_pool,
+            document_service,
+            watch_event_tx,
+            threshold,
+        }
+    }
+
+    #[instrument(name = "DetectDuplicatesJob::start", level = "info", skip(self))]
+    pub async fn start(mut self, watch: Watch) -> Result<()> {
+        let mut conn = self.connection_pool.acquire().await?;
+        let documents: Vec<Document> = self
+            .document_service
+            .find_documents_by_watch_id(watch.id, &mut *conn)?
+            .try_collect()
+            .await?;
+        let mut stored_signatures = duplicates::find_signatures_by_watch_id(watch.id, &mut *conn).await?;
+        drop(conn);
+
+        let total = documents.len() as u32;
+        let mut done = 0;
+        let mut failed = 0;
+        let mut paths = vec![];
+        let mut signatures = vec![];
+        for document in documents {
+            let indexed_at = match document.indexed_at {
+                Some(x) => x,
+                None => {
+                    // インデックスに未登録の文書は本文がないので対象外
+                    done += 1;
+                    continue;
+                }
+            };
+            let signature = match stored_signatures.remove(&document.id) {
+                Some((signature, computed_for)) if computed_for == indexed_at => Some(signature),
+                _ => match self.compute_signature(&document, indexed_at).await {
+                    Ok(x) => x,
+                    Err(e) => {
+                        tracing::warn!("failed to compute signature of {}: {}", document.path.display(), e);
+                        failed += 1;
+                        continue;
+                    }
+                },
+            };
+            if let Some(signature) = signature {
+                paths.push(document.path.to_normalized_path_string());
+                signatures.push(signature);
+            }
+
+            done += 1;
+            if done % REPORT_INTERVAL == 0 {
+                self.watch_event_tx
+                    .send(WatchEvent::DetectDuplicatesProgressed {
+                        report: make_report(&watch, JobProgress::new(done, failed, total), JobStatus::Running),
+                    })
+                    .await?;
+            }
+        }
+
+        let groups: Vec<DuplicateGroup> = duplicates::group_near_duplicates(&signatures, self.threshold)
+            .into_iter()
+            .map(|(similarity, members)| DuplicateGroup {
+                similarity,
+                paths: members.into_iter().map(|i| paths[i].clone()).collect(),
+            })
+            .collect();
+
+        let mut tx = self.connection_pool.begin().await?;
+        duplicates::replace_duplicate_groups(watch.id, &groups, &mut *tx).await?;
+        tx.commit().await?;
+
+        self.watch_event_tx
+            .send(WatchEvent::DetectDuplicatesFinished {
+                watch: watch.clone(),
+                report: make_report(&watch, JobProgress::new(done, failed, total), JobStatus::Finished),
+            })
+            .await?;
+
+        tracing::info!(
+            "Finished DetectDuplicatesJob for watch {}: {} groups found",
+            watch.id,
+            groups.len()
+        );
+        Ok(())
+    }
+
+    async fn compute_signature(
+        &self,
+        document: &Document,
+        indexed_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<Signature>> {
+        let contents = match self.document_service.get_contents(&document.path)? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let signature = match Signature::from_text(&contents) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let mut conn = self.connection_pool.acquire().await?;
+        duplicates::upsert_signature(document.id, &signature, indexed_at, &mut *conn).await?;
+        Ok(Some(signature))
+    }
+}
+
+fn make_report(watch: &Watch, progress: JobProgress, status: JobStatus) -> JobReport {
+    JobReport {
+        watch: watch.clone(),
+        progress,
+        job_type: JobType::DetectDuplicates,
+        status,
+    }
+}
@@ -11,8 +11,8 @@ use crate::{
     documents::DocumentService,
     watches::{
         jobs::{
-            delete_watch_job::DeleteWatchJob, job_queue, scan_watch_job::ScanWatchJob, sync_watch_job::SyncWatchJob,
-            Job, JobId, JobType,
+            delete_watch_job::DeleteWatchJob, detect_duplicates_job::DetectDuplicatesJob, job_queue,
+            scan_watch_job::ScanWatchJob, sync_watch_job::SyncWatchJob, Job, JobId, JobType,
         },
         watch_repository,
     },
@@ -25,6 +25,7 @@ enum JobManagerCommand {
     EnqueueScanWatchJob(WatchId),
     EnqueueDeleteWatchJob(WatchId),
     EnqueueSyncWatchJob(WatchId),
+    EnqueueDetectDuplicatesJob(WatchId),
     JobCompleted(JobId),
 }
 
@@ -49,6 +50,12 @@ impl JobManagerController {
         self.command_tx.send(JobManagerCommand::EnqueueSyncWatchJob(watch_id))?;
         Ok(())
     }
+
+    pub async fn enqueue_detect_duplicates_job(&self, watch_id: WatchId) -> Result<()> {
+        self.command_tx
+            .send(JobManagerCommand::EnqueueDetectDuplicatesJob(watch_id))?;
+        Ok(())
+    }
 }
 
 /// Manages status of jobs, launches them.
@@ -65,6 +72,7 @@ pub struct JobManager {
     scan_watch_job: ScanWatchJob,
     delete_watch_job: DeleteWatchJob,
     sync_watch_job: SyncWatchJob,
+    detect_duplicates_job: DetectDuplicatesJob,
 }
 
 impl JobManager {
@@ -74,6 +82,7 @@ impl JobManager {
         scan_watch_job: ScanWatchJob,
         delete_watch_job: DeleteWatchJob,
         sync_watch_job: SyncWatchJob,
+        detect_duplicates_job: DetectDuplicatesJob,
     ) -> Self {
         let (command_tx, command_rx) = mpsc::unbounded_channel();
         Self {
@@ -86,6 +95,7 @@ impl JobManager {
             scan_watch_job,
             delete_watch_job,
             sync_watch_job,
+            detect_duplicates_job,
         }
     }
 
@@ -112,6 +122,9 @@ impl JobManager {
                 JobManagerCommand::EnqueueScanWatchJob(watch_id) => self.enqueue_scan_watch_path_job(watch_id).await,
                 JobManagerCommand::EnqueueDeleteWatchJob(watch_id) => self.enqueue_delete_watch_job(watch_id).await,
                 JobManagerCommand::EnqueueSyncWatchJob(watch_id) => self.enqueue_sync_watch_job(watch_id).await,
+                JobManagerCommand::EnqueueDetectDuplicatesJob(watch_id) => {
+                    self.enqueue_detect_duplicates_job(watch_id).await
+                }
                 JobManagerCommand::JobCompleted(job_id) => self.complete(job_id).await,
             };
 
@@ -149,6 +162,13 @@ impl JobManager {
         Ok(())
     }
 
+    pub async fn enqueue_detect_duplicates_job(&mut self, watch_id: WatchId) -> Result<()> {
+        // 同期中の文書は対象にならないので、実行中のジョブは止めずに後ろに並べる
+        self.job_queue.push(JobType::DetectDuplicates, watch_id).await?;
+        self.command_tx.send(JobManagerCommand::RunJob)?;
+        Ok(())
+    }
+
     #[instrument(name = "JobManager::run_job", level = "info", skip(self))]
     async fn run_job(&mut self) -> Result<()> {
         if self.current_job.is_some() {
@@ -182,6 +202,10 @@ impl JobManager {
                 let sync_watch_job = self.sync_watch_job.clone();
                 self.spawn_job(job.clone(), sync_watch_job.start(job.id, watch)).await
             }
+            JobType::DetectDuplicates => {
+                let detect_duplicates_job = self.detect_duplicates_job.clone();
+                self.spawn_job(job.clone(), detect_duplicates_job.start(watch)).await
+            }
         }?;
 
         self.current_job = Some((job.clone(), join_handle));
@@ -67,6 +67,8 @@ impl ScanWatchJob {
         let start = Instant::now();
         let parallelism = available_parallelism();
         let mut join_set = tokio::task::JoinSet::new();
+        // パスの並び順のキーは監視フォルダからの相対パスで作るので、文書を登録する前に監視フォルダを知らせておく
+        self.document_service.set_watch_path(watch.id, &watch.path);
 
         self.watch_event_tx
             .send(WatchEvent::AddWatchStarted { watch: watch.clone() })
@@ -119,6 +121,7 @@ impl ScanWatchJob {
 
         let mut conn = self.connection_pool.acquire().await?;
         watch_repository::update(watch.id, WatchStatus::Active, &mut *conn).await?;
+        self.document_service.notify_watch_documents_added(watch.id)?;
 
         tracing::info!(
             "Finished ScanWatchJob for watch {watch_id} in {elapsed} seconds",
@@ -81,6 +81,17 @@ impl WatchService {
         Ok(watch)
     }
 
+    pub async fn detect_duplicates<P: AsRef<Path>>(&mut self, path: P, conn: &mut SqliteConnection) -> Result<()> {
+        let path = path.as_ref();
+        let watch = watch_repository::find_by_path(path, &mut *conn)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("watch not found: {}", path.display()))?;
+        self.job_manager_controller
+            .enqueue_detect_duplicates_job(watch.id)
+            .await?;
+        Ok(())
+    }
+
     pub async fn delete_watch<P: AsRef<Path>>(&mut self, path: P, conn: &mut SqliteConnection) -> Result<()> {
         let path = path.as_ref();
         let watch = match watch_repository::find_by_path(path, &mut *conn).await? {
@@ -54,6 +54,13 @@ pub enum WatchEvent {
         watch: watches::Watch,
         report: jobs::JobReport,
     },
+    DetectDuplicatesProgressed {
+        report: jobs::JobReport,
+    },
+    DetectDuplicatesFinished {
+        watch: watches::Watch,
+        report: jobs::JobReport,
+    },
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -161,6 +168,12 @@ impl WatchStateSync {
                             document_count_map.insert(watch.id, report.progress.done);
                             watches.insert(watch.id, watch);
                         }
+                        DetectDuplicatesProgressed { report } => {
+                            reports.insert(report.watch.id, report);
+                        }
+                        DetectDuplicatesFinished { watch, .. } => {
+                            reports.remove(&watch.id);
+                        }
                     }
                 }
 
@@ -172,7 +185,7 @@ impl WatchStateSync {
                                 *x += 1;
                             }
                         }
-                        DocumentUpdated(_) => {},
+                        DocumentUpdated(_) | WatchDocumentsAdded(_) => {},
                         DocumentDeleted(document) => {
                             if let Some(x) = document_count_map.get_mut(&document.watch_id) {
                                 if *x > 0 {
@@ -180,6 +193,11 @@ impl WatchStateSync {
                                 }
                             }
                         }
+                        WatchDocumentsDeleted(watch_id) => {
+                            if let Some(x) = document_count_map.get_mut(&watch_id) {
+                                *x = 0;
+                            }
+                        }
                     }
                 }
                 else => {
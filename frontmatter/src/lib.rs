@@ -1,3 +1,3 @@
-pub use parser::{parse, Frontmatter, ParseResult};
+pub use parser::{parse, parse_fields, FieldsParseResult, Frontmatter, ParseResult};
 
 mod parser;
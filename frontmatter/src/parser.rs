@@ -12,6 +12,13 @@ pub struct ParseResult<'a> {
     pub body: &'a str,
 }
 
+/// All the fields of the frontmatter with their values formatted for display, in the written order for YAML
+/// and alphabetical order for TOML.
+pub struct FieldsParseResult<'a> {
+    pub fields: Vec<(String, String)>,
+    pub body: &'a str,
+}
+
 #[derive(Copy, Clone)]
 enum Separator {
     Hyphen,
@@ -28,13 +35,60 @@ impl Separator {
 }
 
 pub fn parse(s: &str) -> Result<ParseResult> {
-    let (start, separator) = match find_start_of_frontmatter(s) {
+    let (frontmatter, body, separator) = match split(s)? {
         None => {
             return Ok(ParseResult {
                 frontmatter: None,
                 body: s,
             });
         }
+        Some(x) => x,
+    };
+
+    let frontmatter = match separator {
+        Separator::Hyphen => serde_yaml::from_str(frontmatter)?,
+        Separator::Plus => toml::from_str(frontmatter)?,
+    };
+
+    Ok(ParseResult {
+        frontmatter: Some(frontmatter),
+        body,
+    })
+}
+
+pub fn parse_fields(s: &str) -> Result<FieldsParseResult> {
+    let (frontmatter, body, separator) = match split(s)? {
+        None => {
+            return Ok(FieldsParseResult {
+                fields: vec![],
+                body: s,
+            });
+        }
+        Some(x) => x,
+    };
+
+    let fields = match separator {
+        Separator::Hyphen => match serde_yaml::from_str(frontmatter)? {
+            serde_yaml::Value::Mapping(mapping) => mapping
+                .iter()
+                .map(|(key, value)| (format_yaml_value(key), format_yaml_value(value)))
+                .collect(),
+            serde_yaml::Value::Null => vec![],
+            _ => return Err(anyhow!("Frontmatter is not a mapping")),
+        },
+        Separator::Plus => toml::from_str::<toml::value::Table>(frontmatter)?
+            .iter()
+            .map(|(key, value)| (key.clone(), format_toml_value(value)))
+            .collect(),
+    };
+
+    Ok(FieldsParseResult { fields, body })
+}
+
+/// Returns the frontmatter, the body and the separator, or `None` if there is no frontmatter.
+fn split(s: &str) -> Result<Option<(&str, &str, Separator)>> {
+    let (start, separator) = match find_start_of_frontmatter(s) {
+        None => return Ok(None),
         Some((start, separator)) => (start, separator),
     };
 
@@ -47,16 +101,41 @@ pub fn parse(s: &str) -> Result<ParseResult> {
 
     let frontmatter = &s[start..end];
     let body = &s[(end + separator.as_str().len() + 1)..];
+    Ok(Some((frontmatter, body, separator)))
+}
 
-    let frontmatter = match separator {
-        Separator::Hyphen => serde_yaml::from_str(frontmatter)?,
-        Separator::Plus => toml::from_str(frontmatter)?,
-    };
+fn format_yaml_value(value: &serde_yaml::Value) -> String {
+    use serde_yaml::Value;
+    match value {
+        Value::Null => "".to_string(),
+        Value::Bool(x) => x.to_string(),
+        Value::Number(x) => x.to_string(),
+        Value::String(x) => x.clone(),
+        Value::Sequence(x) => x.iter().map(format_yaml_value).collect::<Vec<_>>().join(", "),
+        Value::Mapping(x) => x
+            .iter()
+            .map(|(key, value)| format!("{}: {}", format_yaml_value(key), format_yaml_value(value)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        Value::Tagged(x) => format_yaml_value(&x.value),
+    }
+}
 
-    Ok(ParseResult {
-        frontmatter: Some(frontmatter),
-        body,
-    })
+fn format_toml_value(value: &toml::Value) -> String {
+    use toml::Value;
+    match value {
+        Value::String(x) => x.clone(),
+        Value::Integer(x) => x.to_string(),
+        Value::Float(x) => x.to_string(),
+        Value::Boolean(x) => x.to_string(),
+        Value::Datetime(x) => x.to_string(),
+        Value::Array(x) => x.iter().map(format_toml_value).collect::<Vec<_>>().join(", "),
+        Value::Table(x) => x
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, format_toml_value(value)))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
 }
 
 fn find_start_of_frontmatter(s: &str) -> Option<(usize, Separator)> {
@@ -107,6 +186,50 @@ body
         assert_eq!(result.body, "body\n");
     }
 
+    #[test]
+    fn yaml_fields() {
+        let result = parse_fields(
+            r#"---
+title: aaa
+tags: [a, b]
+draft: false
+---
+body
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            result.fields,
+            vec![
+                ("title".to_string(), "aaa".to_string()),
+                ("tags".to_string(), "a, b".to_string()),
+                ("draft".to_string(), "false".to_string()),
+            ]
+        );
+        assert_eq!(result.body, "body\n");
+    }
+
+    #[test]
+    fn toml_fields() {
+        let result = parse_fields(
+            r#"+++
+title = "aaa"
+date = 2022-11-20
++++
+body
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            result.fields,
+            vec![
+                ("date".to_string(), "2022-11-20".to_string()),
+                ("title".to_string(), "aaa".to_string()),
+            ]
+        );
+        assert_eq!(result.body, "body\n");
+    }
+
     #[test]
     fn no_frontmatter() {
         let result = parse(